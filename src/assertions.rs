@@ -1,5 +1,26 @@
-use crate::config::AssertionKind;
-use std::path::Path;
+use crate::config::{AssertionKind, ExpectedMatch};
+use crate::providers::TokenUsage;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How snapshot assertions treat the files on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SnapshotMode {
+    /// Compare against saved snapshots, creating missing ones on first run.
+    #[default]
+    Check,
+    /// Overwrite every snapshot with the current output.
+    Update,
+    /// Compare only; never write. Used by `sentinel snapshots review`.
+    Review,
+    /// Compare against saved snapshots; a missing one fails instead of being
+    /// created, so CI can't accept outputs nobody has looked at.
+    Require,
+}
 
 /// Result of a single assertion check.
 #[derive(Debug)]
@@ -9,23 +30,81 @@ pub struct AssertionResult {
     pub detail: String,
 }
 
-/// Evaluate an assertion against the LLM output and measured latency.
+/// Everything about a case, besides its output, that assertions may look at.
+#[derive(Debug, Clone, Copy)]
+pub struct AssertionContext<'a> {
+    pub latency_ms: u64,
+    pub snapshot_key: &'a str,
+    pub snapshot_dir: &'a Path,
+    /// Baseline snapshots compared in place of `snapshot_dir`'s where present
+    pub snapshot_base: Option<&'a SnapshotBase>,
+    pub snapshot_mode: SnapshotMode,
+    pub input: &'a HashMap<String, String>,
+    pub usage: &'a TokenUsage,
+    /// How long a `command` or `external` assertion may run before it's killed and fails;
+    /// `None` waits for it however long it takes
+    pub timeout: Option<Duration>,
+}
+
+/// Snapshot key `check_text` compares against, in the default snapshot dir.
+pub const CHECK_TEXT_SNAPSHOT_KEY: &str = "assert";
+
+/// Evaluate one assertion against standalone text, outside any run
+/// (`sentinel assert`). There is no case input, latency or token usage, and
+/// snapshots are compared but never written.
+pub fn check_text(
+    kind: &str,
+    value: &serde_yaml::Value,
+    text: &str,
+    snapshot_dir: &Path,
+) -> anyhow::Result<AssertionResult> {
+    let parsed = crate::config::Assertion::from_parts(kind, value.clone())?.parse()?;
+    Ok(check_assertion(
+        &parsed,
+        text,
+        &AssertionContext {
+            latency_ms: 0,
+            snapshot_key: CHECK_TEXT_SNAPSHOT_KEY,
+            snapshot_dir,
+            snapshot_base: None,
+            snapshot_mode: SnapshotMode::Review,
+            input: &HashMap::new(),
+            usage: &TokenUsage::default(),
+            timeout: Some(Duration::from_millis(
+                crate::config::DEFAULT_ASSERTION_TIMEOUT_MS,
+            )),
+        },
+    ))
+}
+
+/// Evaluate an assertion against the LLM output and its case context.
+///
+/// `Command` and `External` assertions spawn a child process and block until it exits, so
+/// async callers should evaluate assertions on a blocking thread.
 pub fn check_assertion(
     kind: &AssertionKind,
     output: &str,
-    latency_ms: u64,
-    snapshot_key: &str,
-    snapshot_dir: &Path,
-    update_snapshots: bool,
+    ctx: &AssertionContext,
 ) -> AssertionResult {
+    let AssertionContext {
+        latency_ms,
+        snapshot_key,
+        snapshot_dir,
+        snapshot_base,
+        snapshot_mode,
+        input,
+        usage,
+        timeout,
+    } = *ctx;
     match kind {
-        AssertionKind::Contains(expected) => {
-            let lower_output = output.to_lowercase();
-            let lower_expected = expected.to_lowercase();
-            let passed = lower_output.contains(&lower_expected);
+        AssertionKind::Contains {
+            value,
+            case_sensitive,
+        } => {
+            let passed = contains(output, value, case_sensitive.unwrap_or(false));
             AssertionResult {
                 passed,
-                label: format!("contains \"{}\"", expected),
+                label: format!("contains \"{}\"", value),
                 detail: if passed {
                     "found in output".to_string()
                 } else {
@@ -33,13 +112,14 @@ pub fn check_assertion(
                 },
             }
         }
-        AssertionKind::NotContains(unexpected) => {
-            let lower_output = output.to_lowercase();
-            let lower_unexpected = unexpected.to_lowercase();
-            let passed = !lower_output.contains(&lower_unexpected);
+        AssertionKind::NotContains {
+            value,
+            case_sensitive,
+        } => {
+            let passed = !contains(output, value, case_sensitive.unwrap_or(false));
             AssertionResult {
                 passed,
-                label: format!("not-contains \"{}\"", unexpected),
+                label: format!("not-contains \"{}\"", value),
                 detail: if passed {
                     "correctly absent from output".to_string()
                 } else {
@@ -47,6 +127,7 @@ pub fn check_assertion(
                 },
             }
         }
+        AssertionKind::Expected { column, mode } => check_expected(output, column, *mode, input),
         AssertionKind::LatencyMax(max_ms) => {
             let passed = latency_ms <= *max_ms;
             AssertionResult {
@@ -55,9 +136,18 @@ pub fn check_assertion(
                 detail: format!("actual: {}ms", latency_ms),
             }
         }
-        AssertionKind::Snapshot => {
-            check_snapshot(output, snapshot_key, snapshot_dir, update_snapshots)
-        }
+        AssertionKind::Snapshot {
+            min_similarity,
+            json,
+        } => check_snapshot(
+            output,
+            snapshot_key,
+            snapshot_dir,
+            snapshot_base,
+            snapshot_mode,
+            *min_similarity,
+            *json,
+        ),
         AssertionKind::Regex(pattern) => {
             let re = regex::Regex::new(pattern).expect("regex already validated at parse time");
             let passed = re.is_match(output);
@@ -71,6 +161,68 @@ pub fn check_assertion(
                 },
             }
         }
+        AssertionKind::RegexAny(patterns) => {
+            let matched = patterns.iter().find(|p| {
+                regex::Regex::new(p)
+                    .expect("regex already validated at parse time")
+                    .is_match(output)
+            });
+            AssertionResult {
+                passed: matched.is_some(),
+                label: format!("regex_any ({})", patterns.len()),
+                detail: match matched {
+                    Some(pattern) => format!("matched /{}/", pattern),
+                    None => format!("none of {} patterns matched", patterns.len()),
+                },
+            }
+        }
+        AssertionKind::RegexAll(patterns) => {
+            let missed: Vec<String> = patterns
+                .iter()
+                .filter(|p| {
+                    !regex::Regex::new(p)
+                        .expect("regex already validated at parse time")
+                        .is_match(output)
+                })
+                .map(|p| format!("/{}/", p))
+                .collect();
+            AssertionResult {
+                passed: missed.is_empty(),
+                label: format!("regex_all ({})", patterns.len()),
+                detail: if missed.is_empty() {
+                    format!("all {} patterns matched", patterns.len())
+                } else {
+                    format!("NOT matched: {}", missed.join(", "))
+                },
+            }
+        }
+        AssertionKind::FuzzyMatch {
+            expected,
+            max_distance,
+        } => {
+            let distance = levenshtein(&nfc(output.trim()), &nfc(expected.trim()));
+            AssertionResult {
+                passed: distance <= *max_distance,
+                label: format!(
+                    "fuzzy_match \"{}\" ≤{}",
+                    truncate(expected, 40),
+                    max_distance
+                ),
+                detail: format!("edit distance {} (max {})", distance, max_distance),
+            }
+        }
+        AssertionKind::RegexCapture {
+            pattern,
+            group,
+            equals,
+            matches,
+        } => check_regex_capture(
+            output,
+            pattern,
+            *group,
+            equals.as_deref(),
+            matches.as_deref(),
+        ),
         AssertionKind::JsonValid => {
             let passed = serde_json::from_str::<serde_json::Value>(output.trim()).is_ok();
             AssertionResult {
@@ -83,6 +235,15 @@ pub fn check_assertion(
                 },
             }
         }
+        AssertionKind::ValidUtf8 => check_valid_utf8(output),
+        AssertionKind::Markdown { requires } => check_markdown(output, requires),
+        AssertionKind::JsonConsistent { rule } => check_json_consistent(output, rule),
+        AssertionKind::JsonArrayLength {
+            path,
+            min,
+            max,
+            equals,
+        } => check_json_array_length(output, path.as_deref(), *min, *max, *equals),
         AssertionKind::MinLength(min) => {
             let len = output.trim().len() as u64;
             let passed = len >= *min;
@@ -101,97 +262,1243 @@ pub fn check_assertion(
                 detail: format!("actual: {} chars", len),
             }
         }
+        AssertionKind::LineCount {
+            min,
+            max,
+            count_blank,
+        } => {
+            let lines = output
+                .trim()
+                .lines()
+                .filter(|line| *count_blank || !line.trim().is_empty())
+                .count() as u64;
+            let passed = min.is_none_or(|min| lines >= min) && max.is_none_or(|max| lines <= max);
+            AssertionResult {
+                passed,
+                label: format!("line_count {}", describe_bounds(*min, *max)),
+                detail: format!("actual: {} lines", lines),
+            }
+        }
+        AssertionKind::MaxTokens(max) => {
+            let actual = usage.completion_tokens;
+            let passed = actual <= *max;
+            AssertionResult {
+                passed,
+                label: format!("max_tokens {}", max),
+                detail: format!("actual: {} completion tokens", actual),
+            }
+        }
+        AssertionKind::NoRepetition { max_repeats } => {
+            let label = format!("no_repetition {}", max_repeats);
+            match longest_repetition(output) {
+                Some((fragment, count)) if count as u64 > *max_repeats => AssertionResult {
+                    passed: false,
+                    label,
+                    detail: format!("\"{}\" repeated {} times in a row", fragment, count),
+                },
+                Some((_, count)) => AssertionResult {
+                    passed: true,
+                    label,
+                    detail: format!("longest run: {} in a row", count),
+                },
+                None => AssertionResult {
+                    passed: true,
+                    label,
+                    detail: "no repetition".to_string(),
+                },
+            }
+        }
+        AssertionKind::Command(cmd) => {
+            check_command(cmd, output, latency_ms, snapshot_key, input, timeout)
+        }
+        AssertionKind::External { command } => check_external(command, output, ctx),
+        AssertionKind::AnyOf(children) => {
+            let results: Vec<AssertionResult> = children
+                .iter()
+                .map(|c| check_assertion(c, output, ctx))
+                .collect();
+            let label = format!("any_of ({})", children.len());
+            match results.iter().find(|r| r.passed) {
+                Some(winner) => AssertionResult {
+                    passed: true,
+                    label,
+                    detail: format!("passed via {}", winner.label),
+                },
+                None => AssertionResult {
+                    passed: false,
+                    label,
+                    detail: format!("none passed: {}", describe_results(&results)),
+                },
+            }
+        }
+        AssertionKind::AllOf(children) => {
+            let results: Vec<AssertionResult> = children
+                .iter()
+                .map(|c| check_assertion(c, output, ctx))
+                .collect();
+            let failures: Vec<AssertionResult> =
+                results.into_iter().filter(|r| !r.passed).collect();
+            AssertionResult {
+                passed: failures.is_empty(),
+                label: format!("all_of ({})", children.len()),
+                detail: if failures.is_empty() {
+                    format!("all {} passed", children.len())
+                } else {
+                    format!("failed: {}", describe_results(&failures))
+                },
+            }
+        }
     }
 }
 
-// ─── Snapshot logic ──────────────────────────────────────────────────────────
+/// `label (detail); label (detail)` for a group's child results.
+fn describe_results(results: &[AssertionResult]) -> String {
+    results
+        .iter()
+        .map(|r| format!("{} ({})", r.label, r.detail))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
 
-fn check_snapshot(
+// ─── Repetition logic ────────────────────────────────────────────────────────
+
+/// Longest phrase (in words) checked for back-to-back repeats; longer loops
+/// are caught by the line check.
+const MAX_PHRASE_WORDS: usize = 32;
+
+/// The most-repeated fragment and how many times it occurs back to back:
+/// a word or phrase (case- and punctuation-insensitive), or a whole line.
+/// `None` if nothing repeats at all.
+fn longest_repetition(output: &str) -> Option<(String, usize)> {
+    let words: Vec<&str> = output.split_whitespace().collect();
+    let normalized: Vec<String> = words
+        .iter()
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .collect();
+
+    let mut best: Option<(String, usize)> = None;
+    let mut consider = |fragment: String, count: usize| {
+        if count > 1 && best.as_ref().is_none_or(|(_, c)| count > *c) {
+            best = Some((fragment, count));
+        }
+    };
+
+    for n in 1..=MAX_PHRASE_WORDS.min(words.len() / 2) {
+        let mut i = 0;
+        while i + 2 * n <= words.len() {
+            let mut count = 1;
+            while i + (count + 1) * n <= words.len()
+                && normalized[i + count * n..i + (count + 1) * n] == normalized[i..i + n]
+            {
+                count += 1;
+            }
+            if count > 1 {
+                consider(words[i..i + n].join(" "), count);
+                i += count * n;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    let lines: Vec<&str> = output
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    let mut i = 0;
+    while i < lines.len() {
+        let count = lines[i..].iter().take_while(|l| **l == lines[i]).count();
+        consider(lines[i].to_string(), count);
+        i += count;
+    }
+
+    best.map(|(fragment, count)| {
+        let short: String = fragment.chars().take(60).collect();
+        if short.len() < fragment.len() {
+            (format!("{}…", short), count)
+        } else {
+            (fragment, count)
+        }
+    })
+}
+
+// ─── Command logic ───────────────────────────────────────────────────────────
+
+/// Run a user command with the output on stdin and the case context in env vars.
+///
+/// Exposed variables: `SENTINEL_CASE`, `SENTINEL_LATENCY_MS`, and one
+/// `SENTINEL_INPUT_<NAME>` per input variable (name upper-cased). A command
+/// still running after `timeout` is killed and the assertion fails.
+fn check_command(
+    cmd: &str,
     output: &str,
-    snapshot_key: &str,
-    snapshot_dir: &Path,
-    update: bool,
+    latency_ms: u64,
+    case_key: &str,
+    input: &HashMap<String, String>,
+    timeout: Option<Duration>,
 ) -> AssertionResult {
-    let snap_file = snapshot_dir.join(format!("{}.snap", snapshot_key));
+    let label = format!("command `{}`", cmd);
 
-    if update {
-        if let Err(e) = std::fs::create_dir_all(snapshot_dir) {
+    let mut command = shell_command(cmd);
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .env("SENTINEL_CASE", case_key)
+        .env("SENTINEL_LATENCY_MS", latency_ms.to_string());
+    for (key, value) in input {
+        command.env(format!("SENTINEL_INPUT_{}", env_var_name(key)), value);
+    }
+
+    let (status, _, stderr) = match run_with_stdin(command, output, timeout) {
+        Ok(finished) => finished,
+        Err(detail) => {
             return AssertionResult {
                 passed: false,
-                label: "snapshot".to_string(),
-                detail: format!("failed to create snapshot dir: {}", e),
+                label,
+                detail,
             };
         }
-        if let Err(e) = std::fs::write(&snap_file, output) {
+    };
+
+    let passed = status.success();
+    let status = describe_exit(status);
+    let stderr = String::from_utf8_lossy(&stderr);
+    let stderr = stderr.trim();
+
+    AssertionResult {
+        passed,
+        label,
+        detail: if stderr.is_empty() {
+            status
+        } else {
+            format!("{}: {}", status, stderr)
+        },
+    }
+}
+
+/// Run an `external` assertion: `cmd` gets the output and case context as a
+/// JSON object on stdin and must print `{"passed": bool, "detail": string}`
+/// on stdout and exit 0. Anything else fails the assertion.
+fn check_external(cmd: &str, output: &str, ctx: &AssertionContext) -> AssertionResult {
+    let label = format!("external `{}`", cmd);
+
+    let request = serde_json::json!({
+        "output": output,
+        "case": ctx.snapshot_key,
+        "input": ctx.input,
+        "latency_ms": ctx.latency_ms,
+        "usage": {
+            "prompt_tokens": ctx.usage.prompt_tokens,
+            "completion_tokens": ctx.usage.completion_tokens,
+            "total_tokens": ctx.usage.total_tokens,
+        },
+    });
+
+    let mut command = shell_command(cmd);
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let (status, stdout, stderr) = match run_with_stdin(command, &request.to_string(), ctx.timeout)
+    {
+        Ok(finished) => finished,
+        Err(detail) => {
             return AssertionResult {
                 passed: false,
-                label: "snapshot".to_string(),
-                detail: format!("failed to write snapshot: {}", e),
+                label,
+                detail,
             };
         }
+    };
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        let stderr = stderr.trim();
         return AssertionResult {
-            passed: true,
-            label: "snapshot".to_string(),
-            detail: "updated".to_string(),
+            passed: false,
+            label,
+            detail: if stderr.is_empty() {
+                describe_exit(status)
+            } else {
+                format!("{}: {}", describe_exit(status), stderr)
+            },
         };
     }
 
-    if !snap_file.exists() {
-        if let Err(e) = std::fs::create_dir_all(snapshot_dir) {
-            return AssertionResult {
-                passed: false,
-                label: "snapshot".to_string(),
-                detail: format!("failed to create snapshot dir: {}", e),
-            };
+    #[derive(serde::Deserialize)]
+    struct Verdict {
+        passed: bool,
+        #[serde(default)]
+        detail: String,
+    }
+
+    let stdout = String::from_utf8_lossy(&stdout);
+    match serde_json::from_str::<Verdict>(stdout.trim()) {
+        Ok(verdict) => AssertionResult {
+            passed: verdict.passed,
+            label,
+            detail: verdict.detail,
+        },
+        Err(e) => AssertionResult {
+            passed: false,
+            label,
+            detail: format!(
+                "expected {{\"passed\": bool, \"detail\": string}} on stdout ({}), got: {}",
+                e,
+                truncate(stdout.trim(), 200)
+            ),
+        },
+    }
+}
+
+/// Spawn `command`, write `input` to its stdin and wait up to `timeout` for
+/// it to exit and close its output. Returns the exit status with whatever it
+/// wrote to stdout and stderr (empty unless piped), or a failure detail.
+///
+/// The command runs in its own process group, so on timeout everything it
+/// started is killed with it and the pipes close. That includes a
+/// background process still holding the pipes after the command exits.
+fn run_with_stdin(
+    mut command: Command,
+    input: &str,
+    timeout: Option<Duration>,
+) -> Result<(ExitStatus, Vec<u8>, Vec<u8>), String> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let timed_out = || {
+        format!(
+            "timed out after {}ms",
+            timeout.unwrap_or_default().as_millis()
+        )
+    };
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("failed to spawn command: {}", e))?;
+
+    // Feed stdin and drain stdout/stderr from separate threads so a chatty
+    // child can't deadlock us. The writer is never joined: a background
+    // process that inherits stdin but never reads it could block it.
+    if let Some(mut stdin) = child.stdin.take() {
+        let data = input.to_string();
+        std::thread::spawn(move || {
+            // The command may exit without reading stdin; that's not an error.
+            let _ = stdin.write_all(data.as_bytes());
+        });
+    }
+    let stdout_reader = child.stdout.take().map(drain_pipe);
+    let stderr_reader = child.stderr.take().map(drain_pipe);
+
+    let pid = child.id();
+    let (done, exited) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = done.send(child.wait());
+    });
+    let status = match recv_by(&exited, deadline) {
+        Some(Ok(status)) => status,
+        Some(Err(e)) => return Err(format!("failed to wait for command: {}", e)),
+        None => {
+            kill_process_group(pid);
+            let _ = exited.recv();
+            return Err(timed_out());
         }
-        if let Err(e) = std::fs::write(&snap_file, output) {
-            return AssertionResult {
-                passed: false,
-                label: "snapshot".to_string(),
-                detail: format!("failed to write snapshot: {}", e),
-            };
+    };
+
+    let drained = |reader: Option<mpsc::Receiver<Vec<u8>>>| match reader {
+        Some(reader) => recv_by(&reader, deadline),
+        None => Some(Vec::new()),
+    };
+    match (drained(stdout_reader), drained(stderr_reader)) {
+        (Some(stdout), Some(stderr)) => Ok((status, stdout, stderr)),
+        _ => {
+            kill_process_group(pid);
+            Err(format!(
+                "{} (a background process kept its output open)",
+                timed_out()
+            ))
+        }
+    }
+}
+
+/// Read `pipe` to the end on its own thread; the contents arrive on the
+/// returned channel.
+fn drain_pipe<R: Read + Send + 'static>(mut pipe: R) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        let _ = tx.send(buf);
+    });
+    rx
+}
+
+/// Wait for `rx` until `deadline`, or indefinitely without one. `None` if
+/// the deadline passed first.
+fn recv_by<T>(rx: &mpsc::Receiver<T>, deadline: Option<Instant>) -> Option<T> {
+    match deadline {
+        Some(deadline) => rx
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .ok(),
+        None => rx.recv().ok(),
+    }
+}
+
+/// Kill the process group `run_with_stdin` started `pid` in.
+#[cfg(unix)]
+fn kill_process_group(pid: u32) {
+    // SAFETY: kill(2) takes no pointers; a negative pid names the group.
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+/// Kill `pid` and every process it started.
+#[cfg(windows)]
+fn kill_process_group(pid: u32) {
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// Whether `needle` occurs in `haystack`, ignoring case unless
+/// `case_sensitive`.
+fn contains(haystack: &str, needle: &str, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        haystack.contains(needle)
+    } else {
+        haystack.to_lowercase().contains(&needle.to_lowercase())
+    }
+}
+
+/// "exited with code N", or how else the process ended.
+pub(crate) fn describe_exit(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exited with code {}", code),
+        None => "terminated by signal".to_string(),
+    }
+}
+
+/// `cmd` run by the platform shell (`sh -c`, or `cmd /C` on Windows).
+#[cfg(windows)]
+pub(crate) fn shell_command(cmd: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.args(["/C", cmd]);
+    command
+}
+
+#[cfg(not(windows))]
+pub(crate) fn shell_command(cmd: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.args(["-c", cmd]);
+    command
+}
+
+fn env_var_name(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// `min..=max`, `>= min` or `<= max`, for labels.
+fn describe_bounds(min: Option<u64>, max: Option<u64>) -> String {
+    match (min, max) {
+        (Some(min), Some(max)) => format!("{}..={}", min, max),
+        (Some(min), None) => format!(">= {}", min),
+        (None, Some(max)) => format!("<= {}", max),
+        (None, None) => "any".to_string(),
+    }
+}
+
+/// Count U+FFFD replacement characters and locate the first one.
+fn check_valid_utf8(output: &str) -> AssertionResult {
+    let mut count = 0;
+    let mut first = None;
+    let (mut line, mut column) = (1, 1);
+    for (index, c) in output.chars().enumerate() {
+        if c == '\u{FFFD}' {
+            count += 1;
+            first.get_or_insert((index, line, column));
+        }
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    let detail = match first {
+        None => "no replacement characters".to_string(),
+        Some((index, line, column)) => format!(
+            "{} replacement character(s) (U+FFFD), first at char {} (line {}, column {})",
+            count, index, line, column
+        ),
+    };
+    AssertionResult {
+        passed: count == 0,
+        label: "valid_utf8".to_string(),
+        detail,
+    }
+}
+
+/// Scan the output's Markdown for the required element kinds and for a
+/// code fence that's never closed (common when an answer is cut off).
+fn check_markdown(output: &str, requires: &[String]) -> AssertionResult {
+    use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+
+    let label = if requires.is_empty() {
+        "markdown".to_string()
+    } else {
+        format!("markdown [{}]", requires.join(", "))
+    };
+    let mut found: Vec<&str> = Vec::new();
+    let mut unclosed_fence = None;
+    for (event, range) in Parser::new_ext(output, Options::ENABLE_TABLES).into_offset_iter() {
+        let Event::Start(tag) = event else {
+            continue;
+        };
+        let element = match tag {
+            Tag::Heading { .. } => "heading",
+            Tag::CodeBlock(kind) => {
+                if let CodeBlockKind::Fenced(_) = kind {
+                    let block = output[range.clone()].trim_end();
+                    let fence: String = block
+                        .trim_start()
+                        .chars()
+                        .take_while(|c| *c == '`' || *c == '~')
+                        .collect();
+                    let closed = block.lines().count() > 1
+                        && block
+                            .lines()
+                            .last()
+                            .is_some_and(|line| line.trim().starts_with(&fence));
+                    if !closed && unclosed_fence.is_none() {
+                        unclosed_fence = Some(output[..range.start].lines().count() + 1);
+                    }
+                }
+                "code"
+            }
+            Tag::Table(_) => "table",
+            Tag::List(_) => "list",
+            Tag::Link { .. } => "link",
+            _ => continue,
+        };
+        if !found.contains(&element) {
+            found.push(element);
+        }
+    }
+
+    let missing: Vec<&str> = requires
+        .iter()
+        .map(String::as_str)
+        .filter(|element| !found.contains(element))
+        .collect();
+    let mut problems = Vec::new();
+    if !missing.is_empty() {
+        problems.push(format!("missing {}", missing.join(", ")));
+    }
+    if let Some(line) = unclosed_fence {
+        problems.push(format!(
+            "code fence opened at line {} is never closed",
+            line
+        ));
+    }
+    let detail = if !problems.is_empty() {
+        problems.join("; ")
+    } else if found.is_empty() {
+        "no structural elements".to_string()
+    } else {
+        format!("found {}", found.join(", "))
+    };
+    AssertionResult {
+        passed: problems.is_empty(),
+        label,
+        detail,
+    }
+}
+
+fn check_json_array_length(
+    output: &str,
+    path: Option<&str>,
+    min: Option<u64>,
+    max: Option<u64>,
+    equals: Option<u64>,
+) -> AssertionResult {
+    let bounds = match equals {
+        Some(n) if min.is_none() && max.is_none() => format!("== {}", n),
+        Some(n) => format!("== {}, {}", n, describe_bounds(min, max)),
+        None => describe_bounds(min, max),
+    };
+    let label = match path {
+        Some(path) => format!("json_array_length {} {}", path, bounds),
+        None => format!("json_array_length {}", bounds),
+    };
+    let result = |passed: bool, detail: String| AssertionResult {
+        passed,
+        label: label.clone(),
+        detail,
+    };
+
+    let root = match serde_json::from_str::<serde_json::Value>(output.trim()) {
+        Ok(root) => root,
+        Err(_) => return result(false, "output is NOT valid JSON".to_string()),
+    };
+    let Some(node) = json_at(&root, path.unwrap_or("$")) else {
+        return result(
+            false,
+            format!("path {} not found in output", path.unwrap_or("$")),
+        );
+    };
+    let Some(items) = node.as_array() else {
+        return result(false, format!("{}, not an array", json_kind(node)));
+    };
+
+    let len = items.len() as u64;
+    let passed = equals.is_none_or(|n| len == n)
+        && min.is_none_or(|min| len >= min)
+        && max.is_none_or(|max| len <= max);
+    result(passed, format!("actual: {} items", len))
+}
+
+/// The node at an already-validated JSON path, if the output has one.
+fn json_at<'a>(root: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    let segments =
+        crate::config::parse_json_path(path).expect("JSON path already validated at parse time");
+    segments
+        .iter()
+        .try_fold(root, |node, segment| match segment {
+            crate::config::JsonPathSegment::Key(key) => node.get(key),
+            crate::config::JsonPathSegment::Index(i) => node.get(i),
+        })
+}
+
+/// What a JSON value is, for details like "a string, not an array".
+fn json_kind(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Object(_) => "an object",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Null => "null",
+    }
+}
+
+/// Check that two parts of the JSON output agree: the numbers on the left
+/// sum to the right (`sum-equals`), or the array on the left has as many
+/// items as the right says (`count-matches`).
+fn check_json_consistent(output: &str, rule: &str) -> AssertionResult {
+    let rule = crate::config::parse_consistency_rule(rule)
+        .expect("json_consistent rule already validated at parse time");
+    let label = format!("json_consistent {} {} {}", rule.name, rule.left, rule.right);
+    let result = |passed: bool, detail: String| AssertionResult {
+        passed,
+        label: label.clone(),
+        detail,
+    };
+
+    let root = match serde_json::from_str::<serde_json::Value>(output.trim()) {
+        Ok(root) => root,
+        Err(_) => return result(false, "output is NOT valid JSON".to_string()),
+    };
+    let number_at = |path: &str| -> Result<f64, String> {
+        let node =
+            json_at(&root, path).ok_or_else(|| format!("path {} not found in output", path))?;
+        node.as_f64()
+            .ok_or_else(|| format!("{} is {}, not a number", path, json_kind(node)))
+    };
+    let right = match number_at(&rule.right) {
+        Ok(right) => right,
+        Err(detail) => return result(false, detail),
+    };
+
+    let (array_path, each) = rule.left_parts();
+    let array = match json_at(&root, array_path) {
+        Some(serde_json::Value::Array(items)) => items,
+        Some(node) => {
+            return result(
+                false,
+                format!("{} is {}, not an array", array_path, json_kind(node)),
+            )
+        }
+        None => return result(false, format!("path {} not found in output", array_path)),
+    };
+
+    if rule.name == "count-matches" {
+        let count = array.len();
+        let passed = count as f64 == right;
+        let detail = if passed {
+            format!("{} has {} items, as {} says", rule.left, count, rule.right)
+        } else {
+            format!(
+                "{} has {} items but {} is {}",
+                rule.left, count, rule.right, right
+            )
+        };
+        return result(passed, detail);
+    }
+
+    let mut sum = 0.0;
+    for (i, item) in array.iter().enumerate() {
+        let value = match each {
+            Some(each) => json_at(item, each),
+            None => Some(item),
+        };
+        let located = || format!("{}[{}]{}", array_path, i, each.unwrap_or_default());
+        match value {
+            Some(value) => match value.as_f64() {
+                Some(n) => sum += n,
+                None => {
+                    return result(
+                        false,
+                        format!("{} is {}, not a number", located(), json_kind(value)),
+                    )
+                }
+            },
+            None => return result(false, format!("path {} not found in output", located())),
+        }
+    }
+    // Float sums drift (0.1 + 0.2), so allow for rounding
+    let passed = (sum - right).abs() <= 1e-9 * sum.abs().max(right.abs()).max(1.0);
+    let detail = if passed {
+        format!("sum of {} equals {} ({})", rule.left, rule.right, right)
+    } else {
+        format!(
+            "sum of {} is {} but {} is {}",
+            rule.left, sum, rule.right, right
+        )
+    };
+    result(passed, detail)
+}
+
+fn check_regex_capture(
+    output: &str,
+    pattern: &str,
+    group: usize,
+    equals: Option<&str>,
+    matches: Option<&str>,
+) -> AssertionResult {
+    let label = format!("regex_capture /{}/[{}]", pattern, group);
+    let fail = |detail: String| AssertionResult {
+        passed: false,
+        label: label.clone(),
+        detail,
+    };
+
+    let re = regex::Regex::new(pattern).expect("regex already validated at parse time");
+    let Some(caps) = re.captures(output) else {
+        return fail("pattern NOT matched".to_string());
+    };
+    let Some(captured) = caps.get(group).map(|m| m.as_str()) else {
+        return fail(format!("group {} did not capture", group));
+    };
+
+    if let Some(expected) = equals {
+        if captured != expected {
+            return fail(format!(
+                "captured \"{}\", expected \"{}\"",
+                captured, expected
+            ));
+        }
+    }
+    if let Some(sub) = matches {
+        let sub_re = regex::Regex::new(sub).expect("regex already validated at parse time");
+        if !sub_re.is_match(captured) {
+            return fail(format!(
+                "captured \"{}\" does NOT match /{}/",
+                captured, sub
+            ));
         }
+    }
+    AssertionResult {
+        passed: true,
+        label,
+        detail: format!("captured \"{}\"", captured),
+    }
+}
+
+fn check_expected(
+    output: &str,
+    column: &str,
+    mode: ExpectedMatch,
+    input: &HashMap<String, String>,
+) -> AssertionResult {
+    let label = match mode {
+        ExpectedMatch::Exact => format!("expected {{{{{}}}}}", column),
+        ExpectedMatch::Contains { .. } => format!("expected {{{{{}}}}} (contains)", column),
+        ExpectedMatch::Similarity(min) => {
+            format!("expected {{{{{}}}}} (~{:.0}%)", column, min * 100.0)
+        }
+    };
+    let Some(expected) = input.get(column) else {
         return AssertionResult {
-            passed: true,
-            label: "snapshot".to_string(),
-            detail: "created (first run)".to_string(),
+            passed: false,
+            label,
+            detail: format!("case has no '{}' input", column),
         };
+    };
+
+    let (passed, detail) = match mode {
+        ExpectedMatch::Exact => {
+            let passed = output.trim() == expected.trim();
+            let detail = if passed {
+                "output equals expected".to_string()
+            } else {
+                diff_summary(expected.trim(), output.trim())
+            };
+            (passed, detail)
+        }
+        ExpectedMatch::Contains { case_sensitive } => {
+            let passed = contains(output, expected, case_sensitive.unwrap_or(false));
+            let detail = if passed {
+                format!("found \"{}\" in output", truncate(expected, 60))
+            } else {
+                format!("\"{}\" NOT found in output", truncate(expected, 60))
+            };
+            (passed, detail)
+        }
+        ExpectedMatch::Similarity(min) => {
+            let score = similarity(expected.trim(), output.trim());
+            (
+                score >= min,
+                format!("{:.1}% similar to expected", score * 100.0),
+            )
+        }
+    };
+    AssertionResult {
+        passed,
+        label,
+        detail,
+    }
+}
+
+// ─── Snapshot logic ──────────────────────────────────────────────────────────
+
+/// Outcome of comparing an output against its saved snapshot, without writing.
+#[derive(Debug)]
+pub enum SnapshotComparison {
+    /// No snapshot file exists yet.
+    Missing,
+    /// The output matches the saved snapshot (ignoring surrounding whitespace).
+    Matches,
+    /// The output differs; `existing` is the saved content.
+    Differs { existing: String, summary: String },
+    /// The snapshot file exists but could not be read.
+    Error(String),
+}
+
+/// Path of the snapshot file for a given key.
+pub fn snapshot_path(snapshot_key: &str, snapshot_dir: &Path) -> PathBuf {
+    snapshot_dir.join(format!("{}.snap", snapshot_key))
+}
+
+/// Compare an output against its saved snapshot. Never touches the disk
+/// beyond reading.
+pub fn compare_snapshot(
+    output: &str,
+    snapshot_key: &str,
+    snapshot_dir: &Path,
+) -> SnapshotComparison {
+    compare_snapshot_as(output, snapshot_key, snapshot_dir, None)
+}
+
+/// `compare_snapshot`, but when both sides parse as JSON they are compared
+/// structurally, ignoring object key order and formatting. `json` forces
+/// that on (`Some(true)`, a difference if either side isn't JSON) or off.
+pub fn compare_snapshot_as(
+    output: &str,
+    snapshot_key: &str,
+    snapshot_dir: &Path,
+    json: Option<bool>,
+) -> SnapshotComparison {
+    let snap_file = snapshot_path(snapshot_key, snapshot_dir);
+
+    if !snap_file.exists() {
+        return SnapshotComparison::Missing;
     }
 
     let existing = match std::fs::read_to_string(&snap_file) {
         Ok(s) => s,
-        Err(e) => {
-            return AssertionResult {
-                passed: false,
-                label: "snapshot".to_string(),
-                detail: format!("failed to read snapshot: {}", e),
-            };
-        }
+        Err(e) => return SnapshotComparison::Error(format!("failed to read snapshot: {}", e)),
     };
 
+    compare_snapshot_text(existing, output, json)
+}
+
+/// Compare an output against snapshot content that has already been read.
+fn compare_snapshot_text(existing: String, output: &str, json: Option<bool>) -> SnapshotComparison {
     let normalized_existing = existing.trim();
     let normalized_output = output.trim();
 
     if normalized_output == normalized_existing {
-        AssertionResult {
-            passed: true,
-            label: "snapshot".to_string(),
-            detail: "matches saved snapshot".to_string(),
+        return SnapshotComparison::Matches;
+    }
+
+    if json != Some(false) {
+        let expected = serde_json::from_str::<serde_json::Value>(normalized_existing);
+        let actual = serde_json::from_str::<serde_json::Value>(normalized_output);
+        let summary = match (expected, actual) {
+            (Ok(expected), Ok(actual)) => match json_diff(&expected, &actual) {
+                None => return SnapshotComparison::Matches,
+                Some(summary) => Some(summary),
+            },
+            (Err(e), _) if json == Some(true) => Some(format!("snapshot is not valid JSON: {}", e)),
+            (_, Err(e)) if json == Some(true) => Some(format!("output is not valid JSON: {}", e)),
+            _ => None,
+        };
+        if let Some(summary) = summary {
+            return SnapshotComparison::Differs { existing, summary };
         }
-    } else {
-        let diff = diff_summary(normalized_existing, normalized_output);
-        AssertionResult {
-            passed: false,
-            label: "snapshot".to_string(),
-            detail: format!(
-                "differs from snapshot. {}. Run with --update-snapshots to accept.",
-                diff
+    }
+
+    let summary = diff_summary(normalized_existing, normalized_output);
+    SnapshotComparison::Differs { existing, summary }
+}
+
+/// Where `--snapshot-base` reads baseline snapshots from, for CI where the
+/// snapshot dir isn't persisted between runs.
+#[derive(Debug, Clone)]
+pub enum SnapshotBase {
+    /// A directory of `.snap` files laid out like the snapshot dir
+    Dir(PathBuf),
+    /// The snapshot dir as committed at a git ref, read with `git show`
+    Git {
+        reference: String,
+        snapshot_dir: PathBuf,
+        /// `.snap` files under the snapshot dir at the ref, relative to it
+        files: Arc<HashSet<String>>,
+        /// Baselines already read, by snapshot key
+        read: Arc<Mutex<HashMap<String, Result<String, String>>>>,
+    },
+}
+
+impl SnapshotBase {
+    /// `base` as a directory if there is one, else as a git ref whose tree
+    /// holds `snapshot_dir`, which must be inside the working directory's
+    /// repository.
+    pub fn resolve(base: &str, snapshot_dir: &Path) -> anyhow::Result<Self> {
+        if Path::new(base).is_dir() {
+            return Ok(Self::Dir(PathBuf::from(base)));
+        }
+        let is_ref = Command::new("git")
+            .args(["rev-parse", "--verify", "--quiet"])
+            .arg(format!("{}^{{commit}}", base))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !is_ref {
+            return Err(anyhow::anyhow!(
+                "--snapshot-base '{}' is neither a directory nor a git ref",
+                base
+            ));
+        }
+        let snapshot_dir = repo_relative(snapshot_dir)?;
+        Ok(Self::Git {
+            files: Arc::new(git_snapshot_files(base, &snapshot_dir)?),
+            reference: base.to_string(),
+            snapshot_dir,
+            read: Arc::default(),
+        })
+    }
+
+    /// The directory or ref, for result details.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Dir(dir) => dir.display().to_string(),
+            Self::Git { reference, .. } => reference.clone(),
+        }
+    }
+
+    /// The baseline snapshot for a key, or `None` if the base has none.
+    pub fn read(&self, snapshot_key: &str) -> Result<Option<String>, String> {
+        match self {
+            Self::Dir(dir) => {
+                let path = snapshot_path(snapshot_key, dir);
+                if !path.exists() {
+                    return Ok(None);
+                }
+                std::fs::read_to_string(&path)
+                    .map(Some)
+                    .map_err(|e| format!("failed to read baseline snapshot: {}", e))
+            }
+            Self::Git {
+                reference,
+                snapshot_dir,
+                files,
+                read,
+            } => {
+                if !files.contains(&format!("{}.snap", snapshot_key)) {
+                    return Ok(None);
+                }
+                if let Some(cached) = read.lock().expect("snapshots poisoned").get(snapshot_key) {
+                    return cached.clone().map(Some);
+                }
+                let baseline =
+                    git_show_snapshot(reference, &snapshot_path(snapshot_key, snapshot_dir));
+                read.lock()
+                    .expect("snapshots poisoned")
+                    .insert(snapshot_key.to_string(), baseline.clone());
+                baseline.map(Some)
+            }
+        }
+    }
+}
+
+/// `dir` relative to the root of the git repository holding the working
+/// directory, which is how a `REF:PATH` names it. A directory outside the
+/// repository can't have a baseline in it.
+fn repo_relative(dir: &Path) -> anyhow::Result<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .map_err(|e| anyhow::anyhow!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "failed to find the git repository: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let root = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let root = root.canonicalize().unwrap_or(root);
+
+    // The directory may not exist locally (it only has to at the ref), so
+    // `..` is resolved by hand when it can't be canonicalized
+    let absolute = std::env::current_dir()?.join(dir);
+    let absolute = absolute.canonicalize().unwrap_or(absolute);
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+        .strip_prefix(&root)
+        .map(Path::to_path_buf)
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "snapshot dir '{}' is outside the git repository at '{}'",
+                dir.display(),
+                root.display()
+            )
+        })
+}
+
+/// `path` (relative to the repository root) with `/` separators.
+fn git_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// The files under `snapshot_dir` in `reference`'s tree, relative to it.
+/// A ref without the directory has no baselines.
+fn git_snapshot_files(reference: &str, snapshot_dir: &Path) -> anyhow::Result<HashSet<String>> {
+    let tree = format!("{}:{}", reference, git_path(snapshot_dir));
+    let has_dir = Command::new("git")
+        .args(["cat-file", "-t"])
+        .arg(&tree)
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|output| output.status.success() && output.stdout.starts_with(b"tree"));
+    if !has_dir {
+        return Ok(HashSet::new());
+    }
+    let output = Command::new("git")
+        .args(["ls-tree", "-r", "-z", "--name-only", "--full-tree"])
+        .arg(&tree)
+        .output()
+        .map_err(|e| anyhow::anyhow!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "failed to list snapshots at '{}': {}",
+            reference,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// A snapshot file's contents at `reference`, known to be in its tree.
+fn git_show_snapshot(reference: &str, path: &Path) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("show")
+        .arg(format!("{}:{}", reference, git_path(path)))
+        .output()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "failed to read baseline snapshot from '{}': {}",
+            reference,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Write (or overwrite) the snapshot file for a key.
+pub fn write_snapshot(output: &str, snapshot_key: &str, snapshot_dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(snapshot_dir)
+        .map_err(|e| format!("failed to create snapshot dir: {}", e))?;
+    std::fs::write(snapshot_path(snapshot_key, snapshot_dir), output)
+        .map_err(|e| format!("failed to write snapshot: {}", e))
+}
+
+fn check_snapshot(
+    output: &str,
+    snapshot_key: &str,
+    snapshot_dir: &Path,
+    base: Option<&SnapshotBase>,
+    mode: SnapshotMode,
+    min_similarity: Option<f64>,
+    json: Option<bool>,
+) -> AssertionResult {
+    let mut label = match min_similarity {
+        Some(min) => format!("snapshot ~{:.0}%", min * 100.0),
+        None => "snapshot".to_string(),
+    };
+    if json == Some(true) {
+        label.push_str(" (json)");
+    }
+    let result = |passed: bool, detail: String| AssertionResult {
+        passed,
+        label: label.clone(),
+        detail,
+    };
+
+    if mode == SnapshotMode::Update {
+        return match write_snapshot(output, snapshot_key, snapshot_dir) {
+            Ok(()) => result(true, "updated".to_string()),
+            Err(e) => result(false, e),
+        };
+    }
+
+    // A baseline snapshot wins; without one the local file is used (and
+    // created on first run) as usual
+    let baseline = match base.map(|base| base.read(snapshot_key)) {
+        Some(Err(e)) => return result(false, e),
+        Some(Ok(Some(existing))) => Some(existing),
+        _ => None,
+    };
+    let (comparison, saved, differs, accept_hint) = match (baseline, base) {
+        (Some(existing), Some(base)) => {
+            let saved = format!("baseline snapshot ({})", base.describe());
+            (
+                compare_snapshot_text(existing, output, json),
+                saved.clone(),
+                saved,
+                "Update the baseline to accept.",
+            )
+        }
+        _ => (
+            compare_snapshot_as(output, snapshot_key, snapshot_dir, json),
+            "saved snapshot".to_string(),
+            "snapshot".to_string(),
+            "Run with --update-snapshots to accept.",
+        ),
+    };
+
+    match comparison {
+        SnapshotComparison::Missing if mode == SnapshotMode::Review => {
+            result(false, "no saved snapshot (pending review)".to_string())
+        }
+        SnapshotComparison::Missing if mode == SnapshotMode::Require => result(
+            false,
+            "no saved snapshot (--require-snapshots). Create it locally and commit it.".to_string(),
+        ),
+        SnapshotComparison::Missing => match write_snapshot(output, snapshot_key, snapshot_dir) {
+            Ok(()) => result(true, "created (first run)".to_string()),
+            Err(e) => result(false, e),
+        },
+        SnapshotComparison::Matches => result(true, format!("matches {}", saved)),
+        SnapshotComparison::Differs { existing, summary } => match min_similarity {
+            Some(min) => {
+                let score = similarity(existing.trim(), output.trim());
+                let passed = score >= min;
+                let mut detail = format!(
+                    "{:.1}% similar to {} (min {:.0}%)",
+                    score * 100.0,
+                    saved,
+                    min * 100.0
+                );
+                if !passed {
+                    detail.push_str(&format!(". {}. {}", summary, accept_hint));
+                }
+                result(passed, detail)
+            }
+            None => result(
+                false,
+                format!("differs from {}. {}. {}", differs, summary, accept_hint),
             ),
+        },
+        SnapshotComparison::Error(e) => result(false, e),
+    }
+}
+
+/// Word-level similarity between two texts in 0.0–1.0: one minus the edit
+/// distance over whitespace-separated tokens, normalized by the longer text.
+/// Working on words keeps long outputs cheap and ignores reflowed whitespace.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<&str> = a.split_whitespace().collect();
+    let b: Vec<&str> = b.split_whitespace().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - edit_distance(&a, &b) as f64 / longest as f64
+}
+
+/// Character-level Levenshtein distance. Counts Unicode scalar values, so
+/// `é` is one character however many bytes it takes; compose both sides
+/// (`nfc`) first if they may mix precomposed and combining accents.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    edit_distance(&a, &b)
+}
+
+/// Unicode NFC composition, so `e` + combining acute equals `é`.
+fn nfc(text: &str) -> String {
+    icu_normalizer::ComposingNormalizerBorrowed::new_nfc()
+        .normalize(text)
+        .into_owned()
+}
+
+/// Levenshtein distance over arbitrary sequences, keeping only two rows.
+fn edit_distance<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, x) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let cost = usize::from(x != y);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
         }
+        std::mem::swap(&mut prev, &mut curr);
     }
+    prev[b.len()]
 }
 
-fn diff_summary(expected: &str, actual: &str) -> String {
+/// One-line description of the first difference between two texts.
+pub fn diff_summary(expected: &str, actual: &str) -> String {
     let exp_lines: Vec<&str> = expected.lines().collect();
     let act_lines: Vec<&str> = actual.lines().collect();
 
@@ -217,10 +1524,66 @@ fn diff_summary(expected: &str, actual: &str) -> String {
     }
 }
 
-fn truncate(s: &str, max: usize) -> String {
-    if s.len() > max {
-        format!("{}…", &s[..max])
-    } else {
-        s.to_string()
+/// Path and values of the first structural difference between two JSON
+/// values, e.g. `First JSON diff at $.items[2].name: expected "a", got "b"`.
+/// Object key order is ignored; `None` when they are equal.
+pub fn json_diff(expected: &serde_json::Value, actual: &serde_json::Value) -> Option<String> {
+    first_json_diff("$", expected, actual).map(|d| format!("First JSON diff at {}", d))
+}
+
+fn first_json_diff(
+    path: &str,
+    expected: &serde_json::Value,
+    actual: &serde_json::Value,
+) -> Option<String> {
+    use serde_json::Value;
+    match (expected, actual) {
+        (Value::Object(exp), Value::Object(act)) => {
+            for (key, exp_value) in exp {
+                let child = format!("{}.{}", path, key);
+                match act.get(key) {
+                    Some(act_value) => {
+                        if let Some(diff) = first_json_diff(&child, exp_value, act_value) {
+                            return Some(diff);
+                        }
+                    }
+                    None => return Some(format!("{}: missing from output", child)),
+                }
+            }
+            act.keys()
+                .find(|key| !exp.contains_key(*key))
+                .map(|key| format!("{}.{}: not in snapshot", path, key))
+        }
+        (Value::Array(exp), Value::Array(act)) => {
+            for (i, (exp_item, act_item)) in exp.iter().zip(act).enumerate() {
+                let child = format!("{}[{}]", path, i);
+                if let Some(diff) = first_json_diff(&child, exp_item, act_item) {
+                    return Some(diff);
+                }
+            }
+            (exp.len() != act.len()).then(|| {
+                format!(
+                    "{}: snapshot has {} item(s), output has {}",
+                    path,
+                    exp.len(),
+                    act.len()
+                )
+            })
+        }
+        _ if expected == actual => None,
+        _ => Some(format!(
+            "{}: expected {}, got {}",
+            path,
+            truncate(&expected.to_string(), 40),
+            truncate(&actual.to_string(), 40)
+        )),
+    }
+}
+
+/// `s` cut to `max` characters, with an ellipsis if anything was cut.
+pub(crate) fn truncate(s: &str, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &s[..end]),
+        None => s.to_string(),
     }
 }
@@ -1,258 +1,2749 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use crate::{assertions, providers};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
 
 /// Top-level configuration parsed from the YAML test file.
-#[derive(Debug, Deserialize)]
+/// Serializing a loaded config gives the resolved view (`--dump-config`).
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Config {
     #[allow(dead_code)]
     pub version: String,
     #[serde(default)]
     pub defaults: Defaults,
+    /// Named overlays selectable with `--profile`
+    #[serde(default, serialize_with = "sorted")]
+    pub profiles: HashMap<String, Profile>,
+    /// Named assertion lists that tests pull in with `use: [name]`
+    #[serde(default, serialize_with = "sorted")]
+    pub assertion_sets: HashMap<String, Vec<Assertion>>,
     pub tests: Vec<TestDef>,
+    /// Shell commands `run` executes in order before the suite, e.g. to
+    /// start a local server; the run is aborted if one fails
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub setup: Vec<String>,
+    /// Shell commands `run` executes after the suite, even if setup or the
+    /// run failed or was interrupted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teardown: Vec<String>,
+    /// Keys in the file that no config struct reads, with their location
+    /// (reported by `--strict`)
+    #[serde(skip)]
+    pub unknown_fields: Vec<String>,
 }
 
 /// Default settings applied to all tests unless overridden.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Defaults {
     #[serde(default = "default_provider")]
     pub provider: String,
     #[serde(default = "default_model")]
     pub model: String,
-    #[serde(default = "default_temperature")]
-    pub temperature: f64,
+    /// Absent: `DEFAULT_TEMPERATURE`, or nothing for models that reject a
+    /// custom temperature. `null`: never sent.
+    #[serde(
+        default,
+        deserialize_with = "explicit_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub temperature: Option<Option<f64>>,
+    /// Clean up provider output before assertions (off by default)
+    #[serde(default, skip_serializing_if = "NormalizeOutput::is_off")]
+    pub normalize_output: NormalizeOutput,
+    /// Where snapshot files live, relative to the config file (default
+    /// `.snapshots` in the working directory)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_dir: Option<String>,
+    /// Stop sequences sent with every request (OpenAI only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// -2.0 to 2.0 (OpenAI only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    /// -2.0 to 2.0 (OpenAI only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    /// Cap on generated tokens (OpenAI and Anthropic; Anthropic defaults
+    /// to 1024)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Strip ANSI escape sequences and control characters from the output
+    /// before assertions (the report keeps the raw text)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip_control: bool,
+    /// How long one assertion may take to evaluate before it fails; a
+    /// `command` or `external` one is killed (default
+    /// `DEFAULT_ASSERTION_TIMEOUT_MS`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assertion_timeout_ms: Option<u64>,
+    /// Make `contains` and `not-contains` match case by default; an
+    /// assertion's own `case_sensitive` still wins
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub case_sensitive_contains: bool,
 }
 
+/// `defaults.assertion_timeout_ms` when unset: generous, since it's only
+/// there to stop a stuck command or check from hanging the run.
+pub const DEFAULT_ASSERTION_TIMEOUT_MS: u64 = 60_000;
+
 impl Default for Defaults {
     fn default() -> Self {
         Self {
             provider: default_provider(),
             model: default_model(),
-            temperature: default_temperature(),
+            temperature: None,
+            normalize_output: NormalizeOutput::default(),
+            snapshot_dir: None,
+            stop: Vec::new(),
+            frequency_penalty: None,
+            presence_penalty: None,
+            max_tokens: None,
+            strip_control: false,
+            assertion_timeout_ms: None,
+            case_sensitive_contains: false,
+        }
+    }
+}
+
+/// `defaults.normalize_output`: `true` strips a byte-order mark and turns
+/// CRLF line endings into LF; `nfc` also applies Unicode NFC normalization,
+/// so composed and decomposed accents compare equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum NormalizeOutput {
+    Enabled(bool),
+    Level(NormalizeLevel),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NormalizeLevel {
+    Nfc,
+}
+
+impl Default for NormalizeOutput {
+    fn default() -> Self {
+        NormalizeOutput::Enabled(false)
+    }
+}
+
+impl NormalizeOutput {
+    pub fn is_off(&self) -> bool {
+        *self == NormalizeOutput::Enabled(false)
+    }
+
+    /// Normalize `text` per this setting; returns it unchanged when off.
+    pub fn apply(&self, text: &str) -> String {
+        if self.is_off() {
+            return text.to_string();
+        }
+        let text = text
+            .strip_prefix('\u{feff}')
+            .unwrap_or(text)
+            .replace("\r\n", "\n");
+        match self {
+            NormalizeOutput::Level(NormalizeLevel::Nfc) => {
+                icu_normalizer::ComposingNormalizerBorrowed::new_nfc()
+                    .normalize(&text)
+                    .into_owned()
+            }
+            NormalizeOutput::Enabled(_) => text,
+        }
+    }
+}
+
+/// Remove ANSI escape sequences (colors, cursor movement, OSC titles and
+/// links) and other control characters from `text`, keeping newlines, tabs
+/// and carriage returns. For `strip_control`.
+pub fn strip_control(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' => match chars.next() {
+                // CSI: parameters and intermediates up to a final byte
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('\u{40}'..='\u{7e}').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC (and the other string sequences): up to BEL or ST
+                Some(']' | 'P' | '^' | '_' | 'X') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\u{7}' {
+                            break;
+                        }
+                        if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                // Two-character escapes such as ESC 7 or ESC (B
+                Some('(' | ')' | '*' | '+') => {
+                    chars.next();
+                }
+                Some(_) | None => {}
+            },
+            '\n' | '\t' | '\r' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A named set of overrides applied on top of `defaults` (e.g. `dev`, `ci`).
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+}
+
+/// Serialize a map with sorted keys so dumped configs are stable.
+pub(crate) fn sorted<S, V>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: Serialize,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn default_provider() -> String {
+    "openai".to_string()
+}
+fn default_model() -> String {
+    "gpt-4o-mini".to_string()
+}
+
+/// Temperature sent when `defaults.temperature` is absent.
+pub const DEFAULT_TEMPERATURE: f64 = 0.7;
+
+/// Tell an explicit `null` (`Some(None)`) apart from an absent key (`None`).
+fn explicit_null<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl Defaults {
+    /// Temperature to send for `model`, or `None` to leave it out of the
+    /// request.
+    pub fn temperature_for(&self, model: &str) -> Option<f64> {
+        resolve_temperature(self.temperature, model)
+    }
+}
+
+/// Resolve a `defaults.temperature` setting for `model`: an explicit value or
+/// `null` wins; when absent, reasoning models get nothing and everything else
+/// gets `DEFAULT_TEMPERATURE`.
+pub fn resolve_temperature(setting: Option<Option<f64>>, model: &str) -> Option<f64> {
+    match setting {
+        Some(temperature) => temperature,
+        None if providers::model_info(model).is_some_and(|info| !info.supports_temperature) => None,
+        None => Some(DEFAULT_TEMPERATURE),
+    }
+}
+
+/// A single test definition containing an ID, prompt template, and test cases.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct TestDef {
+    pub id: String,
+    pub prompt: String,
+    /// Provider for this test instead of `defaults.provider`; may be a
+    /// template like `{{provider}}`, resolved per case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Model for this test; may be a template like `{{model}}`, resolved
+    /// per case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Inline test cases
+    #[serde(default)]
+    pub cases: Vec<TestCase>,
+    /// Load test cases from a CSV or JSONL file (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cases_file: Option<String>,
+    /// How a CSV `cases_file` is delimited and quoted
+    #[serde(default, skip_serializing_if = "CasesFileOptions::is_default")]
+    pub cases_file_options: CasesFileOptions,
+    /// Read `cases_file` row by row during the run instead of up front
+    #[serde(default)]
+    pub stream: bool,
+    /// Resolved path of a streamed `cases_file` (set by `load_config`)
+    #[serde(skip)]
+    pub cases_path: Option<PathBuf>,
+    /// Default assertions to apply to all CSV rows
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+    /// Narrow the output before assertions (cases may override)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract: Option<Extract>,
+    /// Negative test: a provider error passes and a completion fails
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub expect_error: bool,
+    /// Substring the expected error must contain (implies `expect_error`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_contains: Option<String>,
+    /// Run assertions against the model's thinking plus its answer, instead
+    /// of the answer alone
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_thinking: bool,
+    /// Override `defaults.strip_control`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip_control: Option<bool>,
+    /// Assertion sets appended to every case (expanded by `load_config`)
+    #[serde(default, rename = "use", skip_serializing_if = "Vec::is_empty")]
+    pub use_sets: Vec<String>,
+    /// Soft cap on what all of this test's cases may cost together in one
+    /// run (USD); going over warns, or fails with `--enforce-budgets`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_usd: Option<f64>,
+    /// Ask for this many completions per call and assert on each of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    /// With `n`, whether every choice or just one has to pass
+    #[serde(default, skip_serializing_if = "ChoiceMode::is_all")]
+    pub choices: ChoiceMode,
+    /// Override `defaults.stop` (an empty list sends none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Override `defaults.frequency_penalty`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    /// Override `defaults.presence_penalty`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    /// Override `defaults.max_tokens`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Shell command run before this test's cases; if it fails, they fail
+    /// without being sent. A test with hooks runs its cases one at a time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    /// Shell command run after this test's cases, even if they failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+}
+
+/// CSV dialect of a `cases_file`. JSONL files ignore it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct CasesFileOptions {
+    /// Field separator, e.g. `"\t"` for TSV
+    pub delimiter: char,
+    /// Whether the first row names the columns. Without one, columns are
+    /// named `col1`, `col2`, ...
+    pub has_headers: bool,
+    /// Character that quotes fields containing the delimiter
+    pub quote: char,
+}
+
+impl Default for CasesFileOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            has_headers: true,
+            quote: '"',
+        }
+    }
+}
+
+impl CasesFileOptions {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Open `path` as CSV in this dialect.
+    fn reader(&self, path: &Path) -> anyhow::Result<csv::Reader<std::fs::File>> {
+        let byte = |name: &str, c: char| {
+            u8::try_from(c).ok().filter(u8::is_ascii).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "cases_file_options.{} must be a single ASCII character, got '{}'",
+                    name,
+                    c
+                )
+            })
+        };
+        csv::ReaderBuilder::new()
+            .delimiter(byte("delimiter", self.delimiter)?)
+            .quote(byte("quote", self.quote)?)
+            .has_headers(self.has_headers)
+            // Rows with the wrong field count are reported by `case_rows`
+            .flexible(true)
+            .from_path(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open CSV '{}': {}", path.display(), e))
+    }
+
+    /// Column names: the header row, or `col1`, `col2`, ... for as many
+    /// fields as the first row has.
+    fn headers(&self, rdr: &mut csv::Reader<std::fs::File>) -> anyhow::Result<csv::StringRecord> {
+        let headers = rdr.headers()?;
+        if self.has_headers {
+            return Ok(headers.clone());
+        }
+        Ok((1..=headers.len()).map(|i| format!("col{}", i)).collect())
+    }
+}
+
+/// How a test with `n > 1` combines its choices' assertion results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ChoiceMode {
+    /// Every choice must pass every assertion
+    #[default]
+    All,
+    /// One choice passing every assertion is enough
+    Any,
+}
+
+impl ChoiceMode {
+    fn is_all(&self) -> bool {
+        *self == ChoiceMode::All
+    }
+}
+
+/// A single test case with input variables and assertions to check.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct TestCase {
+    #[serde(serialize_with = "sorted")]
+    pub input: HashMap<String, String>,
+    #[serde(rename = "assert", default)]
+    pub assertions: Vec<Assertion>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract: Option<Extract>,
+    /// Override the test's `expect_error`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect_error: Option<bool>,
+    /// Override the test's `error_contains` (implies `expect_error`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_contains: Option<String>,
+    /// Images sent with the prompt: URLs, `data:` URIs or bare base64
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+}
+
+/// Post-processing step that turns the raw output into the text assertions
+/// run against. The full output is still recorded in results.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Extract {
+    /// Contents of the first fenced code block with this info string
+    /// (e.g. `json`); an empty string matches any fence.
+    CodeFence { code_fence: String },
+    /// A capture group of the first regex match (group 0 is the whole match).
+    Regex {
+        regex: String,
+        #[serde(default)]
+        group: usize,
+    },
+}
+
+impl Extract {
+    /// Apply the extraction, returning an error message if nothing matched.
+    pub fn apply(&self, output: &str) -> Result<String, String> {
+        match self {
+            Extract::CodeFence { code_fence } => {
+                extract_code_fence(output, code_fence).ok_or_else(|| {
+                    if code_fence.is_empty() {
+                        "no fenced code block found".to_string()
+                    } else {
+                        format!("no ```{} code block found", code_fence)
+                    }
+                })
+            }
+            Extract::Regex { regex, group } => {
+                let re = regex::Regex::new(regex)
+                    .map_err(|e| format!("invalid extract regex '{}': {}", regex, e))?;
+                let caps = re
+                    .captures(output)
+                    .ok_or_else(|| format!("extract regex /{}/ did not match", regex))?;
+                caps.get(*group)
+                    .map(|m| m.as_str().to_string())
+                    .ok_or_else(|| format!("extract regex /{}/ has no group {}", regex, group))
+            }
+        }
+    }
+}
+
+fn extract_code_fence(output: &str, lang: &str) -> Option<String> {
+    let mut lines = output.lines();
+    while let Some(line) = lines.next() {
+        let Some(info) = line.trim_start().strip_prefix("```") else {
+            continue;
+        };
+        if !lang.is_empty() && !info.trim().eq_ignore_ascii_case(lang) {
+            continue;
+        }
+        let body: Vec<&str> = lines
+            .by_ref()
+            .take_while(|l| !l.trim_start().starts_with("```"))
+            .collect();
+        return Some(body.join("\n"));
+    }
+    None
+}
+
+/// An assertion to evaluate against the LLM response: its type and that
+/// type's keys, plus the keys every type takes.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Assertion {
+    #[serde(flatten)]
+    pub check: AssertionType,
+    /// Skip (not fail) this assertion when the condition holds for the case,
+    /// e.g. `"{{mode}} == short"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_if: Option<String>,
+}
+
+/// Keys some assertion types take beside `value`: the key, the types that
+/// take it, and how to name them in the error for any other type.
+const ASSERTION_FLAGS: &[(&str, &[&str], &str)] = &[
+    (
+        "case_sensitive",
+        &["contains", "not-contains"],
+        "contains and not-contains assertions",
+    ),
+    ("json", &["snapshot"], "snapshot assertions"),
+];
+
+impl<'de> Deserialize<'de> for Assertion {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Errors are raised while reading the map, so they keep its location
+        struct Visitor;
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Assertion;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an assertion map with a 'type'")
+            }
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<Assertion, A::Error> {
+                use serde::de::value::MapAccessDeserializer;
+                use serde::de::Error;
+                let raw = serde_yaml::Value::deserialize(MapAccessDeserializer::new(map))?;
+                Assertion::from_value(raw).map_err(A::Error::custom)
+            }
+        }
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+impl Assertion {
+    /// Read an assertion from its YAML map. A flag on a type that doesn't
+    /// take it is a mistake rather than a typo for `--strict` to catch.
+    fn from_value(raw: serde_yaml::Value) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct Fields {
+            #[serde(flatten)]
+            check: AssertionType,
+            #[serde(default)]
+            skip_if: Option<String>,
+        }
+
+        if let Some(kind) = raw.get("type").and_then(serde_yaml::Value::as_str) {
+            for (flag, types, takes_it) in ASSERTION_FLAGS {
+                if raw.get(flag).is_some() && !types.contains(&kind) {
+                    return Err(format!(
+                        "'{}' only applies to {}, not {}",
+                        flag, takes_it, kind
+                    ));
+                }
+            }
+        }
+        let Fields { check, skip_if } = Fields::deserialize(raw).map_err(|e| e.to_string())?;
+        Ok(Self { check, skip_if })
+    }
+}
+
+/// An assertion as written in YAML, one variant per `type` (in
+/// `ASSERTION_TYPES` order). Each variant declares the shape of its
+/// `value`, so a malformed one, or one with a key its type doesn't take,
+/// is reported by `load_config` with its location. Other unknown keys
+/// beside `type` are ignored, as anywhere else in the config; `--strict`
+/// reports them.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AssertionType {
+    Contains {
+        #[serde(deserialize_with = "value_field")]
+        value: String,
+        /// Match case exactly (`true`) or ignore it (`false`). Unset,
+        /// `defaults.case_sensitive_contains` decides
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        case_sensitive: Option<bool>,
+    },
+    #[serde(rename = "not-contains")]
+    NotContains {
+        #[serde(deserialize_with = "value_field")]
+        value: String,
+        /// As for `contains`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        case_sensitive: Option<bool>,
+    },
+    Expected {
+        #[serde(default, deserialize_with = "value_field")]
+        value: FlagOr<ExpectedValue>,
+    },
+    FuzzyMatch {
+        #[serde(deserialize_with = "value_field")]
+        value: FuzzyMatchValue,
+    },
+    LatencyMax {
+        #[serde(deserialize_with = "value_field")]
+        value: u64,
+    },
+    Snapshot {
+        /// `true` for an exact match, or the similarity (0.0–1.0) the
+        /// output needs to the saved one
+        #[serde(default, alias = "similarity", deserialize_with = "value_field")]
+        value: FlagOr<f64>,
+        /// Compare as JSON (`true`) or as text (`false`). Unset, outputs
+        /// that parse as JSON on both sides compare as JSON
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        json: Option<bool>,
+    },
+    Regex {
+        #[serde(deserialize_with = "value_field")]
+        value: String,
+    },
+    RegexAny {
+        #[serde(deserialize_with = "value_field")]
+        value: Vec<String>,
+    },
+    RegexAll {
+        #[serde(deserialize_with = "value_field")]
+        value: Vec<String>,
+    },
+    RegexCapture {
+        #[serde(deserialize_with = "value_field")]
+        value: RegexCaptureValue,
+    },
+    JsonValid {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<bool>,
+    },
+    #[serde(rename = "valid_utf8")]
+    ValidUtf8 {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<bool>,
+    },
+    JsonArrayLength {
+        #[serde(deserialize_with = "value_field")]
+        value: JsonArrayLengthValue,
+    },
+    JsonConsistent {
+        #[serde(deserialize_with = "value_field")]
+        value: String,
+    },
+    Markdown {
+        /// `true` for well-formed Markdown, or the elements it must have
+        #[serde(default, deserialize_with = "value_field")]
+        value: FlagOr<Vec<String>>,
+    },
+    MinLength {
+        #[serde(deserialize_with = "value_field")]
+        value: u64,
+    },
+    MaxLength {
+        #[serde(deserialize_with = "value_field")]
+        value: u64,
+    },
+    LineCount {
+        #[serde(deserialize_with = "value_field")]
+        value: LineCountValue,
+    },
+    MaxTokens {
+        #[serde(deserialize_with = "value_field")]
+        value: u32,
+    },
+    NoRepetition {
+        #[serde(deserialize_with = "value_field")]
+        value: u64,
+    },
+    Command {
+        #[serde(deserialize_with = "value_field")]
+        value: CommandValue,
+    },
+    External {
+        #[serde(deserialize_with = "value_field")]
+        value: String,
+    },
+    AnyOf {
+        #[serde(alias = "value", deserialize_with = "assert_field")]
+        assert: Vec<Assertion>,
+    },
+    AllOf {
+        #[serde(alias = "value", deserialize_with = "assert_field")]
+        assert: Vec<Assertion>,
+    },
+}
+
+impl Assertion {
+    /// An assertion from a type name and a YAML value, the way the CLI takes
+    /// them (`sentinel assert --type`, `--assert-all type=value`).
+    pub fn from_parts(kind: &str, value: serde_yaml::Value) -> anyhow::Result<Self> {
+        if assertion_spec(kind).is_none() {
+            return Err(anyhow::anyhow!(
+                "unknown assertion type '{}'{}",
+                kind,
+                unknown_type_hint(kind)
+            ));
+        }
+        let mut map = serde_yaml::Mapping::new();
+        map.insert("type".into(), kind.into());
+        if !value.is_null() {
+            map.insert("value".into(), value);
+        }
+        serde_yaml::from_value(serde_yaml::Value::Mapping(map))
+            .map_err(|e| anyhow::anyhow!("{}: {}", kind, e))
+    }
+
+    /// The `type` name, as in `ASSERTION_TYPES`.
+    pub fn name(&self) -> &'static str {
+        self.check.name()
+    }
+
+    /// Check the value and turn it into an `AssertionKind`.
+    pub fn parse(&self) -> anyhow::Result<AssertionKind> {
+        self.check.parse()
+    }
+
+    /// The string value the case input is rendered into, for the types
+    /// whose value is a single string.
+    fn template(&self) -> Option<&str> {
+        match &self.check {
+            AssertionType::Contains { value, .. }
+            | AssertionType::NotContains { value, .. }
+            | AssertionType::Regex { value }
+            | AssertionType::JsonConsistent { value }
+            | AssertionType::External { value } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Whether the value is a template only known per case.
+    fn is_templated(&self) -> bool {
+        self.template().is_some_and(|s| s.contains("{{"))
+    }
+
+    fn is_regex(&self) -> bool {
+        matches!(
+            self.check,
+            AssertionType::Regex { .. }
+                | AssertionType::RegexAny { .. }
+                | AssertionType::RegexAll { .. }
+                | AssertionType::RegexCapture { .. }
+        )
+    }
+}
+
+impl AssertionType {
+    /// The `type` name, as in `ASSERTION_TYPES`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AssertionType::Contains { .. } => "contains",
+            AssertionType::NotContains { .. } => "not-contains",
+            AssertionType::Expected { .. } => "expected",
+            AssertionType::FuzzyMatch { .. } => "fuzzy_match",
+            AssertionType::LatencyMax { .. } => "latency_max",
+            AssertionType::Snapshot { .. } => "snapshot",
+            AssertionType::Regex { .. } => "regex",
+            AssertionType::RegexAny { .. } => "regex_any",
+            AssertionType::RegexAll { .. } => "regex_all",
+            AssertionType::RegexCapture { .. } => "regex_capture",
+            AssertionType::JsonValid { .. } => "json_valid",
+            AssertionType::ValidUtf8 { .. } => "valid_utf8",
+            AssertionType::JsonArrayLength { .. } => "json_array_length",
+            AssertionType::JsonConsistent { .. } => "json_consistent",
+            AssertionType::Markdown { .. } => "markdown",
+            AssertionType::MinLength { .. } => "min_length",
+            AssertionType::MaxLength { .. } => "max_length",
+            AssertionType::LineCount { .. } => "line_count",
+            AssertionType::MaxTokens { .. } => "max_tokens",
+            AssertionType::NoRepetition { .. } => "no_repetition",
+            AssertionType::Command { .. } => "command",
+            AssertionType::External { .. } => "external",
+            AssertionType::AnyOf { .. } => "any_of",
+            AssertionType::AllOf { .. } => "all_of",
+        }
+    }
+
+    /// Check what serde can't (ranges, regex syntax, JSON paths) and build
+    /// the `AssertionKind`.
+    pub fn parse(&self) -> anyhow::Result<AssertionKind> {
+        match self {
+            AssertionType::Contains {
+                value,
+                case_sensitive,
+            } => Ok(AssertionKind::Contains {
+                value: value.clone(),
+                case_sensitive: *case_sensitive,
+            }),
+            AssertionType::NotContains {
+                value,
+                case_sensitive,
+            } => Ok(AssertionKind::NotContains {
+                value: value.clone(),
+                case_sensitive: *case_sensitive,
+            }),
+            AssertionType::Expected { value } => parse_expected(value),
+            AssertionType::FuzzyMatch { value } => Ok(AssertionKind::FuzzyMatch {
+                expected: value.expected.0.clone(),
+                max_distance: value.max_distance,
+            }),
+            AssertionType::LatencyMax { value } => Ok(AssertionKind::LatencyMax(*value)),
+            AssertionType::Snapshot { value, json } => parse_snapshot(value, *json),
+            AssertionType::Regex { value } => {
+                check_regex(value)?;
+                Ok(AssertionKind::Regex(value.clone()))
+            }
+            AssertionType::RegexAny { value } => Ok(AssertionKind::RegexAny(parse_regex_list(
+                "regex_any",
+                value,
+            )?)),
+            AssertionType::RegexAll { value } => Ok(AssertionKind::RegexAll(parse_regex_list(
+                "regex_all",
+                value,
+            )?)),
+            AssertionType::RegexCapture { value } => parse_regex_capture(value),
+            AssertionType::JsonValid { .. } => Ok(AssertionKind::JsonValid),
+            AssertionType::ValidUtf8 { .. } => Ok(AssertionKind::ValidUtf8),
+            AssertionType::JsonArrayLength { value } => parse_json_array_length(value),
+            AssertionType::JsonConsistent { value } => {
+                parse_consistency_rule(value)?;
+                Ok(AssertionKind::JsonConsistent {
+                    rule: value.clone(),
+                })
+            }
+            AssertionType::Markdown { value } => parse_markdown(value),
+            AssertionType::MinLength { value } => Ok(AssertionKind::MinLength(*value)),
+            AssertionType::MaxLength { value } => Ok(AssertionKind::MaxLength(*value)),
+            AssertionType::LineCount { value } => parse_line_count(value),
+            AssertionType::MaxTokens { value } => Ok(AssertionKind::MaxTokens(*value)),
+            AssertionType::NoRepetition { value } => {
+                if *value == 0 {
+                    return Err(anyhow::anyhow!("no_repetition value must be at least 1"));
+                }
+                Ok(AssertionKind::NoRepetition {
+                    max_repeats: *value,
+                })
+            }
+            AssertionType::Command { value } => Ok(AssertionKind::Command(value.cmd.clone())),
+            AssertionType::External { value } => Ok(AssertionKind::External {
+                command: value.clone(),
+            }),
+            AssertionType::AnyOf { assert } => {
+                Ok(AssertionKind::AnyOf(parse_group("any_of", assert)?))
+            }
+            AssertionType::AllOf { assert } => {
+                Ok(AssertionKind::AllOf(parse_group("all_of", assert)?))
+            }
+        }
+    }
+}
+
+/// Deserialize an assertion's `value`, naming the key inside it that failed
+/// (`value.min: invalid type: ...`). An assertion is buffered to read its
+/// `type` first, which otherwise loses the path below the assertion itself.
+fn value_field<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    located("value", deserializer)
+}
+
+/// `value_field` for a group's `assert` list.
+fn assert_field<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    located("assert", deserializer)
+}
+
+fn located<'de, D, T>(key: &str, deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    use serde::de::Error;
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = match e.path().to_string() {
+            p if p == "." => key.to_string(),
+            p if p.starts_with('[') => format!("{}{}", key, p),
+            p => format!("{}.{}", key, p),
+        };
+        D::Error::custom(format!("{}: {}", path, e.into_inner()))
+    })
+}
+
+/// serde lists every variant when an assertion `type` is unknown; name the
+/// closest registered type instead.
+fn explain_parse_error(e: &serde_yaml::Error) -> String {
+    let msg = e.to_string();
+    let variants: Vec<String> = ASSERTION_TYPES
+        .iter()
+        .map(|spec| format!("`{}`", spec.name))
+        .collect();
+    let expected = format!("`, expected one of {}", variants.join(", "));
+    let Some((before, after)) = msg.split_once("unknown variant `") else {
+        return msg;
+    };
+    match after.split_once(&expected) {
+        Some((kind, rest)) => format!(
+            "{}unknown assertion type '{}'{}{}",
+            before,
+            kind,
+            unknown_type_hint(kind),
+            rest
+        ),
+        None => msg,
+    }
+}
+
+/// A value that may also be given as a bare `true`, or left out, for the
+/// type's plain form (`{ type: snapshot, value: true }` versus
+/// `value: 0.9`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum FlagOr<T> {
+    #[default]
+    Flag,
+    Value(T),
+}
+
+impl<T: Serialize> Serialize for FlagOr<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            FlagOr::Flag => serializer.serialize_bool(true),
+            FlagOr::Value(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for FlagOr<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+        use serde::de::IntoDeserializer;
+
+        struct Visitor<T>(std::marker::PhantomData<T>);
+        impl<'de, T: Deserialize<'de>> serde::de::Visitor<'de> for Visitor<T> {
+            type Value = FlagOr<T>;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("true or a value")
+            }
+            fn visit_bool<E: serde::de::Error>(self, _: bool) -> Result<FlagOr<T>, E> {
+                Ok(FlagOr::Flag)
+            }
+            fn visit_unit<E: serde::de::Error>(self) -> Result<FlagOr<T>, E> {
+                Ok(FlagOr::Flag)
+            }
+            fn visit_none<E: serde::de::Error>(self) -> Result<FlagOr<T>, E> {
+                Ok(FlagOr::Flag)
+            }
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<FlagOr<T>, E> {
+                T::deserialize(v.into_deserializer()).map(FlagOr::Value)
+            }
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<FlagOr<T>, E> {
+                T::deserialize(v.into_deserializer()).map(FlagOr::Value)
+            }
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<FlagOr<T>, E> {
+                T::deserialize(v.into_deserializer()).map(FlagOr::Value)
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<FlagOr<T>, E> {
+                T::deserialize(v.into_deserializer()).map(FlagOr::Value)
+            }
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                seq: A,
+            ) -> Result<FlagOr<T>, A::Error> {
+                T::deserialize(SeqAccessDeserializer::new(seq)).map(FlagOr::Value)
+            }
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<FlagOr<T>, A::Error> {
+                T::deserialize(MapAccessDeserializer::new(map)).map(FlagOr::Value)
+            }
+        }
+        deserializer.deserialize_any(Visitor(std::marker::PhantomData))
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for FlagOr<T> {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        format!("FlagOr_{}", T::schema_name()).into()
+    }
+
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn json_schema(generator: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "anyOf": [{ "type": "boolean" }, generator.subschema_for::<T>()],
+        })
+    }
+}
+
+/// JSON Schema of the YAML test file format (`sentinel schema`), generated
+/// from the config types so it can't drift from what `load_config` accepts.
+pub fn config_schema() -> schemars::Schema {
+    schemars::schema_for!(Config)
+}
+
+/// Shape of the `value` an assertion type expects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueShape {
+    String,
+    Number,
+    List,
+    Map,
+    /// No meaningful value; conventionally `true`
+    Flag,
+}
+
+impl std::fmt::Display for ValueShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ValueShape::String => "string",
+            ValueShape::Number => "number",
+            ValueShape::List => "list",
+            ValueShape::Map => "map",
+            ValueShape::Flag => "flag",
+        };
+        f.write_str(s)
+    }
+}
+
+/// One entry in the assertion registry.
+#[derive(Debug, Serialize)]
+pub struct AssertionSpec {
+    #[serde(rename = "type")]
+    pub name: &'static str,
+    pub value: ValueShape,
+    pub description: &'static str,
+    /// One-line YAML showing the type in use
+    pub example: &'static str,
+}
+
+/// Every assertion type, in documentation order, as `sentinel
+/// list-assertions` prints them. `AssertionType` has a variant for each, in
+/// the same order.
+pub const ASSERTION_TYPES: &[AssertionSpec] = &[
+    AssertionSpec {
+        name: "contains",
+        value: ValueShape::String,
+        description: "Output contains the string, ignoring case unless `case_sensitive`",
+        example: "{ type: contains, value: \"refund\" }",
+    },
+    AssertionSpec {
+        name: "not-contains",
+        value: ValueShape::String,
+        description: "Output does not contain the string, ignoring case unless `case_sensitive`",
+        example: "{ type: not-contains, value: \"As an AI\" }",
+    },
+    AssertionSpec {
+        name: "expected",
+        value: ValueShape::Map,
+        description: "Output equals, contains (`match`) or resembles the case's `expected` input",
+        example: "{ type: expected, value: { match: contains } }",
+    },
+    AssertionSpec {
+        name: "fuzzy_match",
+        value: ValueShape::Map,
+        description: "Output is within `max_distance` character edits of `expected`",
+        example: "{ type: fuzzy_match, value: { expected: \"Café au lait\", max_distance: 2 } }",
+    },
+    AssertionSpec {
+        name: "latency_max",
+        value: ValueShape::Number,
+        description: "Response time is at most N ms",
+        example: "{ type: latency_max, value: 5000 }",
+    },
+    AssertionSpec {
+        name: "snapshot",
+        value: ValueShape::Flag,
+        description: "Output matches the stored golden file (or is `similarity` close to it)",
+        example: "{ type: snapshot, value: true }",
+    },
+    AssertionSpec {
+        name: "regex",
+        value: ValueShape::String,
+        description: "Output matches the regular expression",
+        example: r"{ type: regex, value: '\d{3}-\d{4}' }",
+    },
+    AssertionSpec {
+        name: "regex_any",
+        value: ValueShape::List,
+        description: "Output matches at least one of the regular expressions",
+        example: "{ type: regex_any, value: [\"^Yes\", \"(?i)sure\"] }",
+    },
+    AssertionSpec {
+        name: "regex_all",
+        value: ValueShape::List,
+        description: "Output matches every one of the regular expressions",
+        example: r"{ type: regex_all, value: ['\d+', USD] }",
+    },
+    AssertionSpec {
+        name: "regex_capture",
+        value: ValueShape::Map,
+        description: "A regex capture group `equals` a value and/or `matches` another pattern",
+        example: r"{ type: regex_capture, value: { pattern: 'order #(\d+)', matches: '^\d{6}$' } }",
+    },
+    AssertionSpec {
+        name: "json_valid",
+        value: ValueShape::Flag,
+        description: "Output is valid JSON",
+        example: "{ type: json_valid, value: true }",
+    },
+    AssertionSpec {
+        name: "valid_utf8",
+        value: ValueShape::Flag,
+        description: "Output has no U+FFFD replacement characters (text that failed to decode)",
+        example: "{ type: valid_utf8, value: true }",
+    },
+    AssertionSpec {
+        name: "json_array_length",
+        value: ValueShape::Map,
+        description: "Output (or the array at `path`) is a JSON array with `equals`, `min` or `max` items",
+        example: "{ type: json_array_length, value: { path: $.ideas, equals: 5 } }",
+    },
+    AssertionSpec {
+        name: "json_consistent",
+        value: ValueShape::String,
+        description: "Two parts of the JSON output agree: `sum-equals <values> <total>` or `count-matches <array> <count>`",
+        example: "{ type: json_consistent, value: 'sum-equals $.items[*].price $.total' }",
+    },
+    AssertionSpec {
+        name: "markdown",
+        value: ValueShape::List,
+        description: "Output is well-formed Markdown (no unclosed code fence) with each listed element: heading, code, table, list or link",
+        example: "{ type: markdown, value: [heading, code] }",
+    },
+    AssertionSpec {
+        name: "min_length",
+        value: ValueShape::Number,
+        description: "Output is at least N characters",
+        example: "{ type: min_length, value: 50 }",
+    },
+    AssertionSpec {
+        name: "max_length",
+        value: ValueShape::Number,
+        description: "Output is at most N characters",
+        example: "{ type: max_length, value: 1000 }",
+    },
+    AssertionSpec {
+        name: "line_count",
+        value: ValueShape::Map,
+        description: "Output has between min and max non-empty lines",
+        example: "{ type: line_count, value: { min: 3, max: 10 } }",
+    },
+    AssertionSpec {
+        name: "max_tokens",
+        value: ValueShape::Number,
+        description: "Completion used at most N tokens (from API usage)",
+        example: "{ type: max_tokens, value: 500 }",
+    },
+    AssertionSpec {
+        name: "no_repetition",
+        value: ValueShape::Number,
+        description: "No word, phrase or line repeats more than N times in a row",
+        example: "{ type: no_repetition, value: 3 }",
+    },
+    AssertionSpec {
+        name: "command",
+        value: ValueShape::Map,
+        description: "External command (`cmd`) exits 0 with the output on stdin",
+        example: "{ type: command, value: { cmd: \"./check.sh\" } }",
+    },
+    AssertionSpec {
+        name: "external",
+        value: ValueShape::String,
+        description: "Program reads the output and case as JSON on stdin and prints `{\"passed\": bool, \"detail\": string}`",
+        example: "{ type: external, value: \"python3 checks/tone.py\" }",
+    },
+    AssertionSpec {
+        name: "any_of",
+        value: ValueShape::List,
+        description: "At least one nested assertion (`assert`) passes",
+        example: "{ type: any_of, assert: [{ type: contains, value: \"yes\" }, { type: contains, value: \"sure\" }] }",
+    },
+    AssertionSpec {
+        name: "all_of",
+        value: ValueShape::List,
+        description: "Every nested assertion (`assert`) passes",
+        example: "{ type: all_of, assert: [{ type: min_length, value: 10 }, { type: json_valid }] }",
+    },
+];
+
+/// Look up an assertion type in the registry.
+pub fn assertion_spec(name: &str) -> Option<&'static AssertionSpec> {
+    ASSERTION_TYPES.iter().find(|spec| spec.name == name)
+}
+
+/// All recognized assertion type strings.
+pub fn known_assertion_types() -> Vec<&'static str> {
+    ASSERTION_TYPES.iter().map(|spec| spec.name).collect()
+}
+
+/// A string, or a number taken as its text (`equals: 42`).
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct TextValue(pub String);
+
+impl JsonSchema for TextValue {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "TextValue".into()
+    }
+
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "type": ["string", "number"] })
+    }
+}
+
+impl<'de> Deserialize<'de> for TextValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = TextValue;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a string or number")
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<TextValue, E> {
+                Ok(TextValue(v.to_string()))
+            }
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<TextValue, E> {
+                Ok(TextValue(v.to_string()))
+            }
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<TextValue, E> {
+                Ok(TextValue(v.to_string()))
+            }
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<TextValue, E> {
+                Ok(TextValue(v.to_string()))
+            }
+        }
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Which capture group a `regex_capture` checks: by index or by name.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum GroupRef {
+    Index(u64),
+    Name(String),
+}
+
+impl<'de> Deserialize<'de> for GroupRef {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = GroupRef;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a group number or a group name")
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<GroupRef, E> {
+                Ok(GroupRef::Name(v.to_string()))
+            }
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<GroupRef, E> {
+                Ok(GroupRef::Index(v))
+            }
+        }
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Input column the `expected` assertion compares against by default.
+pub const DEFAULT_EXPECTED_COLUMN: &str = "expected";
+
+/// Similarity an `expected` assertion needs when `match: similarity` sets
+/// no `threshold`.
+pub const DEFAULT_EXPECTED_SIMILARITY: f64 = 0.9;
+
+/// The map form of an `expected` assertion's value.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(
+    deny_unknown_fields,
+    expecting = "true or a map like { match: contains }"
+)]
+pub struct ExpectedValue {
+    /// `exact` (default), `contains` or `similarity`
+    #[serde(default, rename = "match", skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// With `match: similarity`, the similarity needed (default 0.9)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    /// Input column to compare against (default `expected`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<String>,
+    /// With `match: contains`, as for `contains`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case_sensitive: Option<bool>,
+}
+
+/// `true` for an exact match on the `expected` column, or a map with any of
+/// `match` (exact, contains, similarity), `threshold`, `column` and
+/// `case_sensitive`.
+fn parse_expected(value: &FlagOr<ExpectedValue>) -> anyhow::Result<AssertionKind> {
+    let FlagOr::Value(ExpectedValue {
+        mode,
+        threshold,
+        column,
+        case_sensitive,
+    }) = value
+    else {
+        return Ok(AssertionKind::Expected {
+            column: DEFAULT_EXPECTED_COLUMN.to_string(),
+            mode: ExpectedMatch::Exact,
+        });
+    };
+    let threshold = *threshold;
+
+    if threshold.is_some_and(|t| t <= 0.0 || t > 1.0) {
+        return Err(anyhow::anyhow!(
+            "expected threshold must be a number between 0 and 1"
+        ));
+    }
+    let mode = match mode.as_deref() {
+        None | Some("exact") => ExpectedMatch::Exact,
+        Some("contains") => ExpectedMatch::Contains {
+            case_sensitive: *case_sensitive,
+        },
+        Some("similarity") => {
+            ExpectedMatch::Similarity(threshold.unwrap_or(DEFAULT_EXPECTED_SIMILARITY))
+        }
+        Some(other) => {
+            return Err(anyhow::anyhow!(
+                "expected match must be exact, contains or similarity (got {})",
+                other
+            ))
+        }
+    };
+    if threshold.is_some() && !matches!(mode, ExpectedMatch::Similarity(_)) {
+        return Err(anyhow::anyhow!(
+            "expected threshold only applies to match: similarity"
+        ));
+    }
+    if case_sensitive.is_some() && !matches!(mode, ExpectedMatch::Contains { .. }) {
+        return Err(anyhow::anyhow!(
+            "expected case_sensitive only applies to match: contains"
+        ));
+    }
+    Ok(AssertionKind::Expected {
+        column: column
+            .clone()
+            .unwrap_or_else(|| DEFAULT_EXPECTED_COLUMN.to_string()),
+        mode,
+    })
+}
+
+/// A `fuzzy_match` assertion's value: the `expected` text and the
+/// `max_distance` in character edits.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(
+    deny_unknown_fields,
+    expecting = "a map with 'expected' text and a 'max_distance'"
+)]
+pub struct FuzzyMatchValue {
+    pub expected: TextValue,
+    pub max_distance: usize,
+}
+
+/// `true` (or nothing) for an exact snapshot, a fraction in (0, 1] for a
+/// similarity threshold.
+fn parse_snapshot(value: &FlagOr<f64>, json: Option<bool>) -> anyhow::Result<AssertionKind> {
+    let min_similarity = match *value {
+        FlagOr::Flag => None,
+        FlagOr::Value(f) if f > 0.0 && f <= 1.0 => Some(f),
+        FlagOr::Value(_) => {
+            return Err(anyhow::anyhow!(
+                "snapshot similarity must be a number between 0 and 1"
+            ))
+        }
+    };
+    Ok(AssertionKind::Snapshot {
+        min_similarity,
+        json,
+    })
+}
+
+fn parse_regex_list(kind: &str, patterns: &[String]) -> anyhow::Result<Vec<String>> {
+    if patterns.is_empty() {
+        return Err(anyhow::anyhow!("{} needs at least one pattern", kind));
+    }
+    for pattern in patterns {
+        check_regex(pattern)?;
+    }
+    Ok(patterns.to_vec())
+}
+
+/// Element kinds a `markdown` assertion can require.
+pub const MARKDOWN_ELEMENTS: &[&str] = &["heading", "code", "table", "list", "link"];
+
+fn parse_markdown(value: &FlagOr<Vec<String>>) -> anyhow::Result<AssertionKind> {
+    // `true` (or no value) requires no particular elements
+    let requires = match value {
+        FlagOr::Flag => Vec::new(),
+        FlagOr::Value(elements) => elements.clone(),
+    };
+    if let Some(unknown) = requires
+        .iter()
+        .find(|e| !MARKDOWN_ELEMENTS.contains(&e.as_str()))
+    {
+        return Err(anyhow::anyhow!(
+            "markdown element '{}' is not one of: {}",
+            unknown,
+            MARKDOWN_ELEMENTS.join(", ")
+        ));
+    }
+    Ok(AssertionKind::Markdown { requires })
+}
+
+/// A `json_array_length` assertion's value: an optional JSONPath-style
+/// `path` and at least one of `equals`, `min` and `max`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(
+    deny_unknown_fields,
+    expecting = "a map with 'equals', 'min' and/or 'max'"
+)]
+pub struct JsonArrayLengthValue {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<u64>,
+}
+
+fn parse_json_array_length(value: &JsonArrayLengthValue) -> anyhow::Result<AssertionKind> {
+    let JsonArrayLengthValue {
+        path,
+        min,
+        max,
+        equals,
+    } = value.clone();
+    if min.is_none() && max.is_none() && equals.is_none() {
+        return Err(anyhow::anyhow!(
+            "json_array_length needs 'equals', 'min' and/or 'max'"
+        ));
+    }
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(anyhow::anyhow!(
+                "json_array_length min ({}) is greater than max ({})",
+                min,
+                max
+            ));
+        }
+    }
+    if let Some(path) = &path {
+        parse_json_path(path)?;
+    }
+    Ok(AssertionKind::JsonArrayLength {
+        path,
+        min,
+        max,
+        equals,
+    })
+}
+
+/// Rules a `json_consistent` assertion can check.
+pub const CONSISTENCY_RULES: &[&str] = &["sum-equals", "count-matches"];
+
+/// A `json_consistent` rule: `<name> <left path> <right path>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsistencyRule {
+    /// One of `CONSISTENCY_RULES`
+    pub name: String,
+    /// The numbers summed (`sum-equals`) or the array counted
+    /// (`count-matches`). `sum-equals` allows one `[*]` step, taking the
+    /// rest of the path from every element
+    pub left: String,
+    /// The number the left side must equal
+    pub right: String,
+}
+
+impl ConsistencyRule {
+    /// The left path split at its `[*]`: the array, then the path inside
+    /// each element (`None` without a wildcard).
+    pub fn left_parts(&self) -> (&str, Option<&str>) {
+        match self.left.split_once("[*]") {
+            Some((array, each)) => (array, Some(each)),
+            None => (&self.left, None),
+        }
+    }
+}
+
+/// Parse a `json_consistent` rule such as `sum-equals $.items[*].price $.total`.
+pub fn parse_consistency_rule(rule: &str) -> anyhow::Result<ConsistencyRule> {
+    let parts: Vec<&str> = rule.split_whitespace().collect();
+    let [name, left, right] = parts[..] else {
+        return Err(anyhow::anyhow!(
+            "json_consistent rule '{}' must be '<rule> <path> <path>', e.g. 'count-matches $.items $.count'",
+            rule
+        ));
+    };
+    if !CONSISTENCY_RULES.contains(&name) {
+        let hint = find_closest(name, CONSISTENCY_RULES)
+            .map(|s| format!(". Did you mean '{}'?", s))
+            .unwrap_or_default();
+        return Err(anyhow::anyhow!(
+            "json_consistent rule '{}' is not one of: {}{}",
+            name,
+            CONSISTENCY_RULES.join(", "),
+            hint
+        ));
+    }
+    let rule = ConsistencyRule {
+        name: name.to_string(),
+        left: left.to_string(),
+        right: right.to_string(),
+    };
+    match rule.left_parts() {
+        (_, Some(_)) if name == "count-matches" => {
+            return Err(anyhow::anyhow!(
+                "count-matches takes an array path without [*], e.g. $.items"
+            ))
+        }
+        (_, Some(each)) if each.contains("[*]") => {
+            return Err(anyhow::anyhow!(
+                "json_consistent path '{}' may use [*] only once",
+                left
+            ))
+        }
+        (array, each) => {
+            parse_json_path(array)?;
+            parse_json_path(each.unwrap_or_default())?;
+        }
+    }
+    if right.contains("[*]") {
+        return Err(anyhow::anyhow!(
+            "json_consistent path '{}' must point at a single number, without [*]",
+            right
+        ));
+    }
+    parse_json_path(right)?;
+    Ok(rule)
+}
+
+/// One step of a JSON path: an object key or an array index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonPathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Parse a JSONPath-style path such as `$.items[0].tags`: an optional
+/// leading `$`, then `.key` and `[index]` steps. A bare first key
+/// (`items[0]`) is accepted too; `$` alone is the root.
+pub fn parse_json_path(path: &str) -> anyhow::Result<Vec<JsonPathSegment>> {
+    let invalid = |why: &str| anyhow::anyhow!("invalid JSON path '{}': {}", path, why);
+    let mut rest = path.trim();
+    rest = rest.strip_prefix('$').unwrap_or(rest);
+    let mut segments = Vec::new();
+    let mut first = true;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(|| invalid("unclosed '['"))?;
+            let index = after[..end]
+                .trim()
+                .parse()
+                .map_err(|_| invalid("array index must be a number"))?;
+            segments.push(JsonPathSegment::Index(index));
+            rest = &after[end + 1..];
+        } else {
+            let after = match rest.strip_prefix('.') {
+                Some(after) => after,
+                None if first && !path.trim_start().starts_with('$') => rest,
+                None => return Err(invalid("expected '.' or '['")),
+            };
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(invalid("empty key"));
+            }
+            segments.push(JsonPathSegment::Key(after[..end].to_string()));
+            rest = &after[end..];
+        }
+        first = false;
+    }
+    Ok(segments)
+}
+
+/// A `line_count` assertion's value: `min` and/or `max` line counts, and
+/// `count_blank: true` to count blank lines too.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, expecting = "a map with 'min' and/or 'max'")]
+pub struct LineCountValue {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub count_blank: bool,
+}
+
+fn parse_line_count(value: &LineCountValue) -> anyhow::Result<AssertionKind> {
+    let LineCountValue {
+        min,
+        max,
+        count_blank,
+    } = *value;
+    if min.is_none() && max.is_none() {
+        return Err(anyhow::anyhow!(
+            "line_count value must be a map with 'min' and/or 'max'"
+        ));
+    }
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(anyhow::anyhow!(
+                "line_count min ({}) is greater than max ({})",
+                min,
+                max
+            ));
+        }
+    }
+    Ok(AssertionKind::LineCount {
+        min,
+        max,
+        count_blank,
+    })
+}
+
+/// A `regex_capture` assertion's value: a `pattern`, an optional `group`
+/// (index or name; defaults to the first group, or the whole match if there
+/// are none) and optional `equals`/`matches` checks on the captured text.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, expecting = "a map with a string 'pattern'")]
+pub struct RegexCaptureValue {
+    pub pattern: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<GroupRef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<TextValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matches: Option<String>,
+}
+
+fn parse_regex_capture(value: &RegexCaptureValue) -> anyhow::Result<AssertionKind> {
+    let RegexCaptureValue {
+        pattern,
+        group,
+        equals,
+        matches,
+    } = value.clone();
+    check_regex(&pattern)?;
+    let re = regex::Regex::new(&pattern)?;
+
+    let group = match group {
+        None => usize::from(re.captures_len() > 1),
+        Some(GroupRef::Index(n)) => {
+            if n >= re.captures_len() as u64 {
+                return Err(anyhow::anyhow!(
+                    "regex_capture pattern '{}' has no group {}",
+                    pattern,
+                    n
+                ));
+            }
+            n as usize
+        }
+        Some(GroupRef::Name(name)) => re
+            .capture_names()
+            .position(|n| n == Some(name.as_str()))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "regex_capture pattern '{}' has no group named '{}'",
+                    pattern,
+                    name
+                )
+            })?,
+    };
+    if let Some(sub) = &matches {
+        check_regex(sub)?;
+    }
+
+    Ok(AssertionKind::RegexCapture {
+        pattern,
+        group,
+        equals: equals.map(|e| e.0),
+        matches,
+    })
+}
+
+/// Validate a regex at parse time.
+fn check_regex(pattern: &str) -> anyhow::Result<()> {
+    regex::Regex::new(pattern).map_err(|e| {
+        let msg = e.to_string();
+        let hint = if msg.contains("look-around") || msg.contains("backreferences") {
+            "\n(the regex crate has no look-around or backreferences; \
+             use --no-validate-regex to defer this check to run time)"
+        } else {
+            ""
+        };
+        anyhow::anyhow!("invalid regex '{}':\n{}{}", pattern, msg, hint)
+    })?;
+    Ok(())
+}
+
+/// A `command` assertion's value.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, expecting = "a map with a 'cmd' string")]
+pub struct CommandValue {
+    /// Run with `sh -c`; the output arrives on stdin
+    pub cmd: String,
+}
+
+fn parse_group(kind: &str, children: &[Assertion]) -> anyhow::Result<Vec<AssertionKind>> {
+    if children.is_empty() {
+        return Err(anyhow::anyhow!("{} needs at least one assertion", kind));
+    }
+    children
+        .iter()
+        .map(Assertion::parse)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(|e| anyhow::anyhow!("{}: {}", kind, e))
+}
+
+/// Known providers.
+pub const KNOWN_PROVIDERS: &[&str] = &["openai", "anthropic", "vertex", "webhook"];
+
+/// Provider value that picks the provider from the model names.
+pub const AUTO_PROVIDER: &str = "auto";
+
+/// Parsed assertion with strong types.
+#[derive(Debug, Clone)]
+pub enum AssertionKind {
+    /// Substring check; `case_sensitive` unset ignores case
+    Contains {
+        value: String,
+        case_sensitive: Option<bool>,
+    },
+    NotContains {
+        value: String,
+        case_sensitive: Option<bool>,
+    },
+    /// Compares the output against the case's `column` input value
+    Expected {
+        column: String,
+        mode: ExpectedMatch,
+    },
+    /// Character-level edit distance between the trimmed output and
+    /// `expected` is at most `max_distance`
+    FuzzyMatch {
+        expected: String,
+        max_distance: usize,
+    },
+    LatencyMax(u64),
+    /// Matches the saved golden file; with `min_similarity`, passes when the
+    /// output is at least that similar (0.0–1.0) instead of identical.
+    /// `json` forces structural JSON comparison on or off (default: detect)
+    Snapshot {
+        min_similarity: Option<f64>,
+        json: Option<bool>,
+    },
+    Regex(String),
+    /// Passes if at least one pattern matches
+    RegexAny(Vec<String>),
+    /// Passes if every pattern matches
+    RegexAll(Vec<String>),
+    /// Capture `group` of the first match of `pattern` must equal `equals`
+    /// and/or match `matches`
+    RegexCapture {
+        pattern: String,
+        group: usize,
+        equals: Option<String>,
+        matches: Option<String>,
+    },
+    JsonValid,
+    /// No U+FFFD replacement characters, which mark bytes a gateway or the
+    /// HTTP client couldn't decode
+    ValidUtf8,
+    /// The output parsed as JSON, or the node at `path`, is an array whose
+    /// length meets every bound given
+    JsonArrayLength {
+        path: Option<String>,
+        min: Option<u64>,
+        max: Option<u64>,
+        equals: Option<u64>,
+    },
+    /// Two values in the JSON output agree, by a rule from
+    /// `parse_consistency_rule`
+    JsonConsistent {
+        rule: String,
+    },
+    /// Output parses as Markdown without an unclosed code fence and contains
+    /// every element kind in `requires` (see `MARKDOWN_ELEMENTS`)
+    Markdown {
+        requires: Vec<String>,
+    },
+    MinLength(u64),
+    MaxLength(u64),
+    /// Lines of the trimmed output within `min..=max`; blank lines only
+    /// count with `count_blank`
+    LineCount {
+        min: Option<u64>,
+        max: Option<u64>,
+        count_blank: bool,
+    },
+    /// Upper bound on the completion tokens reported by the API
+    MaxTokens(u32),
+    /// No word, phrase or line repeated more than `max_repeats` times in a row
+    NoRepetition {
+        max_repeats: u64,
+    },
+    /// Passes if at least one child passes
+    AnyOf(Vec<AssertionKind>),
+    /// Passes if every child passes
+    AllOf(Vec<AssertionKind>),
+    /// External command: receives output on stdin, passes on exit code 0
+    Command(String),
+    /// External program speaking the JSON contract: case on stdin,
+    /// `{passed, detail}` on stdout
+    External {
+        command: String,
+    },
+}
+
+/// How an `expected` assertion compares the output with the input value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpectedMatch {
+    /// Equal, ignoring surrounding whitespace
+    Exact,
+    /// Contains the value; like `contains`, `case_sensitive` unset ignores
+    /// case
+    Contains { case_sensitive: Option<bool> },
+    /// At least this word-level similarity (0.0–1.0)
+    Similarity(f64),
+}
+
+impl AssertionKind {
+    /// Whether the result depends only on the output text, so identical
+    /// outputs can reuse a previous evaluation. Snapshots touch the disk,
+    /// latency depends on the call, and commands and `expected` see the case
+    /// context.
+    pub fn is_cacheable(&self) -> bool {
+        match self {
+            AssertionKind::AnyOf(children) | AssertionKind::AllOf(children) => {
+                children.iter().all(AssertionKind::is_cacheable)
+            }
+            other => !matches!(
+                other,
+                AssertionKind::Snapshot { .. }
+                    | AssertionKind::LatencyMax(_)
+                    | AssertionKind::MaxTokens(_)
+                    | AssertionKind::Command(_)
+                    | AssertionKind::External { .. }
+                    | AssertionKind::Expected { .. }
+            ),
+        }
+    }
+
+    /// Give `contains`/`not-contains` assertions (nested ones too) that
+    /// don't set `case_sensitive` the suite's default.
+    pub fn default_case_sensitive(&mut self, default: bool) {
+        match self {
+            AssertionKind::Contains { case_sensitive, .. }
+            | AssertionKind::NotContains { case_sensitive, .. }
+            | AssertionKind::Expected {
+                mode: ExpectedMatch::Contains { case_sensitive },
+                ..
+            } => {
+                case_sensitive.get_or_insert(default);
+            }
+            AssertionKind::AnyOf(children) | AssertionKind::AllOf(children) => {
+                for child in children {
+                    child.default_case_sensitive(default);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Render a prompt template by substituting `{{key}}` placeholders with values.
+pub fn render_prompt(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut result = template.to_string();
+    for (key, value) in vars {
+        let placeholder = format!("{{{{{}}}}}", key);
+        result = result.replace(&placeholder, value);
+    }
+    result
+}
+
+/// Names of the `{{key}}` placeholders in a template, in order of first use.
+pub fn template_vars(template: &str) -> Vec<String> {
+    let mut vars: Vec<String> = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else { break };
+        let name = &after[..end];
+        if !name.is_empty() && !vars.iter().any(|v| v == name) {
+            vars.push(name.to_string());
+        }
+        rest = &after[end + 2..];
+    }
+    vars
+}
+
+/// Evaluate a `skip_if` condition against a case's input variables.
+///
+/// After `{{var}}` substitution the condition is either `a == b`, `a != b`,
+/// or a bare value that is true unless empty, `false`, or `0`. Operands are
+/// trimmed and may be wrapped in single or double quotes.
+pub fn eval_condition(expr: &str, vars: &HashMap<String, String>) -> Result<bool, String> {
+    let rendered = render_prompt(expr, vars);
+    if rendered.contains("{{") && rendered.contains("}}") {
+        return Err(format!("unresolved variable in condition '{}'", expr));
+    }
+
+    let unquote = |s: &str| -> String {
+        let s = s.trim();
+        let quoted = s.len() >= 2
+            && ((s.starts_with('"') && s.ends_with('"'))
+                || (s.starts_with('\'') && s.ends_with('\'')));
+        if quoted {
+            s[1..s.len() - 1].to_string()
+        } else {
+            s.to_string()
+        }
+    };
+
+    if let Some((lhs, rhs)) = rendered.split_once("==") {
+        return Ok(unquote(lhs) == unquote(rhs));
+    }
+    if let Some((lhs, rhs)) = rendered.split_once("!=") {
+        return Ok(unquote(lhs) != unquote(rhs));
+    }
+
+    let value = unquote(&rendered);
+    Ok(!(value.is_empty() || value == "0" || value.eq_ignore_ascii_case("false")))
+}
+
+// Helper to render assertions (e.g., contains: "{{expected}}")
+fn render_assertions(assertions: &[Assertion], vars: &HashMap<String, String>) -> Vec<Assertion> {
+    assertions
+        .iter()
+        .map(|a| {
+            // Only string values in assertions can be templated, plus the
+            // children of groups
+            let mut rendered = a.clone();
+            match &mut rendered.check {
+                AssertionType::Contains { value, .. }
+                | AssertionType::NotContains { value, .. }
+                | AssertionType::Regex { value }
+                | AssertionType::JsonConsistent { value }
+                | AssertionType::External { value } => *value = render_prompt(value, vars),
+                AssertionType::AnyOf { assert } | AssertionType::AllOf { assert } => {
+                    *assert = render_assertions(assert, vars)
+                }
+                _ => {}
+            }
+            rendered
+        })
+        .collect()
+}
+
+/// Lazily yields one input map per data row of a case file.
+pub type CaseRows = Box<dyn Iterator<Item = anyhow::Result<HashMap<String, String>>> + Send>;
+
+/// Open a `cases_file` for row-by-row reading. Files ending in `.jsonl` are
+/// read as one JSON object per line; everything else is read as CSV in the
+/// `options` dialect. Rows are parsed on demand, so the file is never held
+/// in memory at once.
+pub fn case_rows(path: &Path, options: &CasesFileOptions) -> anyhow::Result<CaseRows> {
+    let is_jsonl = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jsonl"));
+
+    if is_jsonl {
+        let file = std::fs::File::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open JSONL '{}': {}", path.display(), e))?;
+        let display = path.display().to_string();
+        let rows = BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(l) if l.trim().is_empty()))
+            .map(move |(i, line)| {
+                let line = line.map_err(|e| {
+                    anyhow::anyhow!("Failed to read line {} of '{}': {}", i + 1, display, e)
+                })?;
+                let object: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(&line).map_err(|e| {
+                        anyhow::anyhow!(
+                            "Failed to parse JSONL line {} in '{}': {}",
+                            i + 1,
+                            display,
+                            e
+                        )
+                    })?;
+                Ok(object
+                    .into_iter()
+                    .map(|(k, v)| match v {
+                        serde_json::Value::String(s) => (k, s),
+                        other => (k, other.to_string()),
+                    })
+                    .collect())
+            });
+        return Ok(Box::new(rows));
+    }
+
+    let mut rdr = options.reader(path)?;
+    let headers = options.headers(&mut rdr)?;
+    let display = path.display().to_string();
+    let rows = rdr.into_records().map(move |result| {
+        let record = result
+            .map_err(|e| anyhow::anyhow!("Failed to parse CSV record in '{}': {}", display, e))?;
+        if record.len() != headers.len() {
+            return Err(anyhow::anyhow!(
+                "CSV '{}' line {} has {} fields, expected {}",
+                display,
+                record.position().map_or(0, |p| p.line()),
+                record.len(),
+                headers.len()
+            ));
+        }
+        Ok(record
+            .iter()
+            .enumerate()
+            .filter_map(|(i, field)| headers.get(i).map(|h| (h.to_string(), field.to_string())))
+            .collect())
+    });
+    Ok(Box::new(rows))
+}
+
+/// Fail fast when a CSV `cases_file` lacks a column the prompt, a templated
+/// model/provider or an `expected` assertion uses, rather than reporting an
+/// unresolved template on every row. JSONL rows have no header to check, and
+/// a missing file is reported where rows are read.
+fn check_csv_columns(test: &TestDef, path: &Path) -> anyhow::Result<()> {
+    let is_csv = !path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jsonl"));
+    if !is_csv || !path.exists() {
+        return Ok(());
+    }
+
+    let options = &test.cases_file_options;
+    let mut rdr = options.reader(path)?;
+    let headers = options.headers(&mut rdr)?;
+    let has_column = |name: &str| headers.iter().any(|h| h == name);
+    let columns = || headers.iter().collect::<Vec<_>>().join(", ");
+
+    for assertion in &test.assertions {
+        if let Ok(AssertionKind::Expected { column, .. }) = assertion.parse() {
+            if !has_column(&column) {
+                return Err(anyhow::anyhow!(
+                    "Test '{}': expected assertion reads column '{}' but CSV '{}' has no such column (columns: {})",
+                    test.id,
+                    column,
+                    path.display(),
+                    columns()
+                ));
+            }
+        }
+    }
+
+    let templates = [
+        Some(&test.prompt),
+        test.model.as_ref(),
+        test.provider.as_ref(),
+    ];
+    let mut missing: Vec<String> = Vec::new();
+    for var in templates
+        .into_iter()
+        .flatten()
+        .flat_map(|t| template_vars(t))
+    {
+        if !has_column(&var) && !missing.contains(&var) {
+            missing.push(var);
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    Err(anyhow::anyhow!(
+        "Test '{}': CSV '{}' has no column for {} (columns: {})",
+        test.id,
+        path.display(),
+        missing
+            .iter()
+            .map(|v| format!("{{{{{}}}}}", v))
+            .collect::<Vec<_>>()
+            .join(", "),
+        columns()
+    ))
+}
+
+impl TestDef {
+    /// Stop sequences and penalties for this test's requests: its own
+    /// settings, falling back to `defaults` one by one.
+    pub fn completion_params(&self, defaults: &Defaults) -> providers::CompletionParams {
+        providers::CompletionParams {
+            stop: self.stop.clone().unwrap_or_else(|| defaults.stop.clone()),
+            frequency_penalty: self.frequency_penalty.or(defaults.frequency_penalty),
+            presence_penalty: self.presence_penalty.or(defaults.presence_penalty),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+            omit_temperature: false,
+            request_id: None,
+        }
+    }
+
+    /// Whether the test has a `before` or `after` command.
+    pub fn has_hooks(&self) -> bool {
+        self.before.is_some() || self.after.is_some()
+    }
+
+    /// Build a case from a data-file row, applying the test-level assertions
+    /// (rendering templates like `{{expected}}` against the row).
+    pub fn case_from_row(&self, input: HashMap<String, String>) -> TestCase {
+        let assertions = render_assertions(&self.assertions, &input);
+        TestCase {
+            input,
+            assertions,
+            extract: None,
+            expect_error: None,
+            error_contains: None,
+            images: Vec::new(),
+        }
+    }
+}
+
+/// Malformed `cases_file` rows listed in a load error before the rest are
+/// only counted.
+const MAX_MALFORMED_SHOWN: usize = 10;
+
+/// Config path that means "read the YAML from stdin".
+pub const STDIN_PATH: &str = "-";
+
+/// Most rows one `cases_file` may load up front unless `--max-cases` says
+/// otherwise; past it, a file is more likely a mistake than a suite.
+pub const DEFAULT_MAX_CASES: usize = 100_000;
+
+/// Knobs for `load_config_with`.
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Rows a non-streamed `cases_file` may have (`--max-cases`)
+    pub max_cases: usize,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            max_cases: DEFAULT_MAX_CASES,
+        }
+    }
+}
+
+/// Load and parse a Config from a YAML file path, or from stdin when the
+/// path is `-`. Also loads any referenced CSV/JSONL files, unless the test
+/// streams them.
+pub fn load_config(path: &str) -> anyhow::Result<Config> {
+    load_config_with(path, &LoadOptions::default())
+}
+
+pub fn load_config_with(path: &str, options: &LoadOptions) -> anyhow::Result<Config> {
+    if path == STDIN_PATH {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .map_err(|e| anyhow::anyhow!("Failed to read config from stdin: {}", e))?;
+        return parse_config_with(&content, "<stdin>", None, options);
+    }
+
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read config file '{}': {}", path, e))?;
+    let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+    parse_config_with(&content, path, Some(base_dir), options)
+}
+
+/// Parse config YAML from memory. `source` names it in errors. Relative
+/// `cases_file` paths resolve against `base_dir`; without one (stdin) there
+/// is no file to be relative to, so they are rejected. A relative
+/// `snapshot_dir` resolves against `base_dir` too, or the working directory.
+pub fn parse_config(
+    content: &str,
+    source: &str,
+    base_dir: Option<&Path>,
+) -> anyhow::Result<Config> {
+    parse_config_with(content, source, base_dir, &LoadOptions::default())
+}
+
+pub fn parse_config_with(
+    content: &str,
+    source: &str,
+    base_dir: Option<&Path>,
+    options: &LoadOptions,
+) -> anyhow::Result<Config> {
+    let mut config: Config = serde_yaml::from_str(content).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to parse config file '{}': {}",
+            source,
+            explain_parse_error(&e)
+        )
+    })?;
+    // serde ignores unknown keys, so find them in a second, untyped pass
+    let raw: serde_yaml::Value = serde_yaml::from_str(content)?;
+    config.unknown_fields = unknown_fields(&raw);
+
+    if let (Some(dir), Some(base)) = (&config.defaults.snapshot_dir, base_dir) {
+        config.defaults.snapshot_dir = Some(base.join(dir).display().to_string());
+    }
+
+    for test in &mut config.tests {
+        expand_assertion_sets(test, &config.assertion_sets);
+
+        if let Some(cases_file) = &test.cases_file {
+            let cases_path = match base_dir {
+                Some(dir) => dir.join(cases_file),
+                None if Path::new(cases_file).is_absolute() => PathBuf::from(cases_file),
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "Test '{}': cases_file '{}' is relative, but the config was read from {}; use an absolute path",
+                        test.id,
+                        cases_file,
+                        source
+                    ))
+                }
+            };
+
+            check_csv_columns(test, &cases_path)?;
+
+            if test.stream {
+                // Rows are read by the runner as capacity frees up.
+                test.cases_path = Some(cases_path);
+                continue;
+            }
+
+            // Keep going past a malformed row so they're all reported at once
+            let mut malformed = Vec::new();
+            for (i, row) in case_rows(&cases_path, &test.cases_file_options)?.enumerate() {
+                if i == options.max_cases {
+                    return Err(anyhow::anyhow!(
+                        "Test '{}': cases_file '{}' exceeded {} rows; use --max-cases to raise the limit, or stream: true to read it during the run",
+                        test.id,
+                        cases_file,
+                        options.max_cases
+                    ));
+                }
+                match row {
+                    Ok(row) => {
+                        let case = test.case_from_row(row);
+                        test.cases.push(case);
+                    }
+                    Err(e) => malformed.push(e.to_string()),
+                }
+            }
+            match malformed.len() {
+                0 => {}
+                1 => return Err(anyhow::anyhow!("{}", malformed.remove(0))),
+                n => {
+                    let more = n.saturating_sub(MAX_MALFORMED_SHOWN);
+                    malformed.truncate(MAX_MALFORMED_SHOWN);
+                    if more > 0 {
+                        malformed.push(format!("... and {} more", more));
+                    }
+                    return Err(anyhow::anyhow!(
+                        "Test '{}': {} malformed rows in cases_file '{}':\n  {}",
+                        test.id,
+                        n,
+                        cases_file,
+                        malformed.join("\n  ")
+                    ));
+                }
+            }
         }
     }
+
+    Ok(config)
 }
 
-fn default_provider() -> String {
-    "openai".to_string()
+// ─── Strict Mode ─────────────────────────────────────────────────────────────
+
+/// Keys an assertion type also accepts through `#[serde(alias)]`, which the
+/// schema doesn't list: `(type, alias, field)`.
+const ASSERTION_ALIASES: &[(&str, &str, &str)] = &[
+    ("snapshot", "similarity", "value"),
+    ("any_of", "value", "assert"),
+    ("all_of", "value", "assert"),
+];
+
+/// Walk the raw YAML alongside `config_schema()` and list every key the
+/// config structs don't read, as
+/// `"tests[0].cases[1]: unknown field 'assertt'. Did you mean 'assert'?"`.
+/// Deriving the known keys from the schema keeps this in step with the
+/// structs, down to the keys of each assertion type and of its value.
+fn unknown_fields(raw: &serde_yaml::Value) -> Vec<String> {
+    let schema = config_schema();
+    let mut walk = KeyWalk {
+        defs: schema.get("$defs"),
+        found: Vec::new(),
+    };
+    walk.check(raw, schema.as_value(), "");
+    walk.found
 }
-fn default_model() -> String {
-    "gpt-4o-mini".to_string()
+
+struct KeyWalk<'a> {
+    defs: Option<&'a serde_json::Value>,
+    found: Vec<String>,
 }
-fn default_temperature() -> f64 {
-    0.7
+
+impl<'a> KeyWalk<'a> {
+    fn check(&mut self, raw: &serde_yaml::Value, schema: &'a serde_json::Value, path: &str) {
+        let mut branches = Vec::new();
+        self.branches(schema, &mut branches);
+
+        match raw {
+            serde_yaml::Value::Mapping(map) => {
+                // Of a tagged enum, only the variant named by `type` applies
+                let tag = map.get("type").and_then(|t| t.as_str());
+                branches.retain(|branch| match branch.pointer("/properties/type/const") {
+                    Some(name) => name.as_str() == tag,
+                    None => true,
+                });
+                let mut fields: BTreeMap<&str, &serde_json::Value> = BTreeMap::new();
+                for branch in &branches {
+                    if let Some(properties) = branch.get("properties").and_then(|p| p.as_object()) {
+                        fields.extend(properties.iter().map(|(k, v)| (k.as_str(), v)));
+                    }
+                }
+                let entries = branches
+                    .iter()
+                    .find_map(|branch| branch.get("additionalProperties"))
+                    .filter(|entries| entries.is_object());
+                if fields.is_empty() && entries.is_none() {
+                    return;
+                }
+
+                for (key, value) in map {
+                    let Some(key) = key.as_str() else { continue };
+                    let field = ASSERTION_ALIASES
+                        .iter()
+                        .find(|(kind, alias, _)| Some(*kind) == tag && *alias == key)
+                        .map_or(key, |(_, _, field)| field);
+                    let child = match path {
+                        "" => key.to_string(),
+                        _ => format!("{}.{}", path, key),
+                    };
+                    if let Some(field) = fields.get(field) {
+                        self.check(value, field, &child);
+                    } else if let Some(entries) = entries {
+                        self.check(value, entries, &child);
+                    } else {
+                        let known: Vec<&str> = fields.keys().copied().collect();
+                        let hint = find_closest(key, &known)
+                            .map(|s| format!(". Did you mean '{}'?", s))
+                            .unwrap_or_default();
+                        let at = if path.is_empty() { "top level" } else { path };
+                        self.found
+                            .push(format!("{}: unknown field '{}'{}", at, key, hint));
+                    }
+                }
+            }
+            serde_yaml::Value::Sequence(items) => {
+                if let Some(item) = branches.iter().find_map(|branch| branch.get("items")) {
+                    for (i, value) in items.iter().enumerate() {
+                        self.check(value, item, &format!("{}[{}]", path, i));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The schemas a value of `schema` may match: itself, the definition it
+    /// refers to, and its `anyOf`/`oneOf`/`allOf` branches.
+    fn branches(&self, schema: &'a serde_json::Value, out: &mut Vec<&'a serde_json::Value>) {
+        let target = schema
+            .get("$ref")
+            .and_then(|r| r.as_str())
+            .and_then(|r| r.strip_prefix("#/$defs/"))
+            .and_then(|name| self.defs?.get(name));
+        if let Some(target) = target {
+            self.branches(target, out);
+        }
+        out.push(schema);
+        for key in ["anyOf", "oneOf", "allOf"] {
+            for branch in schema
+                .get(key)
+                .and_then(|b| b.as_array())
+                .into_iter()
+                .flatten()
+            {
+                self.branches(branch, out);
+            }
+        }
+    }
 }
 
-/// A single test definition containing an ID, prompt template, and test cases.
-#[derive(Debug, Deserialize)]
-pub struct TestDef {
-    pub id: String,
-    pub prompt: String,
-    #[serde(default)]
-    #[allow(dead_code)]
-    pub provider: Option<String>,
-    #[serde(default)]
-    pub model: Option<String>,
-    /// Inline test cases
-    #[serde(default)]
-    pub cases: Vec<TestCase>,
-    /// Load test cases from a CSV file (optional)
-    pub cases_file: Option<String>,
-    /// Default assertions to apply to all CSV rows
-    #[serde(default)]
-    pub assertions: Vec<Assertion>,
+/// Append the assertions of every set in `test.use_sets`. Inline cases get
+/// them rendered against their input; data-file rows get them through the
+/// test-level assertions, rendered per row. Unknown set names are left for
+/// `validate_config` to report.
+fn expand_assertion_sets(test: &mut TestDef, sets: &HashMap<String, Vec<Assertion>>) {
+    let used: Vec<Assertion> = test
+        .use_sets
+        .iter()
+        .filter_map(|name| sets.get(name))
+        .flatten()
+        .cloned()
+        .collect();
+    append_assertions(test, &used);
 }
 
-/// A single test case with input variables and assertions to check.
-#[derive(Debug, Clone, Deserialize)]
-pub struct TestCase {
-    pub input: HashMap<String, String>,
-    #[serde(rename = "assert")]
-    pub assertions: Vec<Assertion>,
+/// Append `extra` to every case of `test`: rendered per input for the
+/// cases already present, and via the test-level assertions for data-file
+/// rows that are read later.
+fn append_assertions(test: &mut TestDef, extra: &[Assertion]) {
+    if extra.is_empty() {
+        return;
+    }
+
+    for case in &mut test.cases {
+        let rendered = render_assertions(extra, &case.input);
+        case.assertions.extend(rendered);
+    }
+    if test.cases_file.is_some() {
+        test.assertions.extend_from_slice(extra);
+    }
 }
 
-/// An assertion to evaluate against the LLM response.
-#[derive(Debug, Clone, Deserialize)]
-pub struct Assertion {
-    #[serde(rename = "type")]
-    pub kind: String,
-    pub value: serde_yaml::Value,
+/// Read an assertion value given on the command line as YAML, so `500` is a
+/// number and `[a, b]` a list; anything that isn't valid YAML stays a string.
+pub fn parse_value_arg(value: &str) -> serde_yaml::Value {
+    serde_yaml::from_str(value).unwrap_or_else(|_| serde_yaml::Value::String(value.to_string()))
 }
 
-/// All recognized assertion type strings.
-pub const KNOWN_ASSERTION_TYPES: &[&str] = &[
-    "contains",
-    "not-contains",
-    "latency_max",
-    "snapshot",
-    "regex",
-    "json_valid",
-    "min_length",
-    "max_length",
-];
+/// Parse a `--assert-all` argument: `type=value` (the value is read as a
+/// YAML scalar, so `max_length=500` is a number) or a bare `type` for
+/// assertions that take no value.
+pub fn parse_assertion_arg(arg: &str) -> anyhow::Result<Assertion> {
+    let (kind, value) = match arg.split_once('=') {
+        Some((kind, value)) => (kind.trim(), parse_value_arg(value)),
+        None => (arg.trim(), serde_yaml::Value::Bool(true)),
+    };
+    let assertion = Assertion::from_parts(kind, value)
+        .map_err(|e| anyhow::anyhow!("--assert-all '{}': {}", arg, e))?;
+    assertion
+        .parse()
+        .map_err(|e| anyhow::anyhow!("--assert-all '{}': {}", arg, e))?;
+    Ok(assertion)
+}
 
-/// Known providers.
-pub const KNOWN_PROVIDERS: &[&str] = &["openai", "anthropic", "webhook"];
+/// Append assertions to every case of every test (`--assert-all`).
+pub fn apply_global_assertions(config: &mut Config, assertions: &[Assertion]) {
+    for test in &mut config.tests {
+        append_assertions(test, assertions);
+    }
+}
 
-/// Parsed assertion with strong types.
-#[derive(Debug)]
-pub enum AssertionKind {
-    Contains(String),
-    NotContains(String),
-    LatencyMax(u64),
-    Snapshot,
-    Regex(String),
-    JsonValid,
-    MinLength(u64),
-    MaxLength(u64),
+/// Overlay the named profile onto the config defaults.
+/// Returns the profile's concurrency override, if any.
+pub fn apply_profile(config: &mut Config, name: &str) -> anyhow::Result<Option<usize>> {
+    let profile = config.profiles.get(name).ok_or_else(|| {
+        let mut available: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+        available.sort_unstable();
+        if available.is_empty() {
+            anyhow::anyhow!("Unknown profile '{}': no profiles defined in config", name)
+        } else {
+            anyhow::anyhow!(
+                "Unknown profile '{}'. Available: {}",
+                name,
+                available.join(", ")
+            )
+        }
+    })?;
+
+    if let Some(ref provider) = profile.provider {
+        config.defaults.provider = provider.clone();
+    }
+    if let Some(ref model) = profile.model {
+        config.defaults.model = model.clone();
+    }
+    if let Some(temperature) = profile.temperature {
+        config.defaults.temperature = Some(Some(temperature));
+    }
+
+    Ok(profile.concurrency)
 }
 
-impl AssertionKind {
-    pub fn from_raw(kind: &str, value: &serde_yaml::Value) -> anyhow::Result<Self> {
-        match kind {
-            "contains" => {
-                let s = value
-                    .as_str()
-                    .ok_or_else(|| anyhow::anyhow!("contains value must be a string"))?;
-                Ok(AssertionKind::Contains(s.to_string()))
-            }
-            "not-contains" => {
-                let s = value
-                    .as_str()
-                    .ok_or_else(|| anyhow::anyhow!("not-contains value must be a string"))?;
-                Ok(AssertionKind::NotContains(s.to_string()))
-            }
-            "latency_max" => {
-                let ms = value
-                    .as_u64()
-                    .or_else(|| value.as_f64().map(|f| f as u64))
-                    .ok_or_else(|| anyhow::anyhow!("latency_max value must be a number"))?;
-                Ok(AssertionKind::LatencyMax(ms))
-            }
-            "snapshot" => Ok(AssertionKind::Snapshot),
-            "regex" => {
-                let pattern = value
-                    .as_str()
-                    .ok_or_else(|| anyhow::anyhow!("regex value must be a string pattern"))?;
-                // Validate the regex at parse time
-                regex::Regex::new(pattern)
-                    .map_err(|e| anyhow::anyhow!("invalid regex '{}': {}", pattern, e))?;
-                Ok(AssertionKind::Regex(pattern.to_string()))
-            }
-            "json_valid" => Ok(AssertionKind::JsonValid),
-            "min_length" => {
-                let n = value
-                    .as_u64()
-                    .or_else(|| value.as_f64().map(|f| f as u64))
-                    .ok_or_else(|| anyhow::anyhow!("min_length value must be a number"))?;
-                Ok(AssertionKind::MinLength(n))
-            }
-            "max_length" => {
-                let n = value
-                    .as_u64()
-                    .or_else(|| value.as_f64().map(|f| f as u64))
-                    .ok_or_else(|| anyhow::anyhow!("max_length value must be a number"))?;
-                Ok(AssertionKind::MaxLength(n))
-            }
-            other => Err(anyhow::anyhow!("unknown assertion type: {}", other)),
+/// The provider implied by every model that runs on the default provider
+/// (the default model plus the overrides of tests that don't set their own
+/// `provider`), for `provider: "auto"`.
+pub fn infer_provider(config: &Config) -> anyhow::Result<&'static str> {
+    let default_model = config.defaults.model.as_str();
+    let provider = auto_provider_for(default_model)?;
+    // Templated models are only known per case
+    let models = config
+        .tests
+        .iter()
+        .filter(|t| t.provider.is_none())
+        .filter_map(|t| t.model.as_deref())
+        .filter(|m| !m.contains("{{"));
+    for model in models {
+        let other = auto_provider_for(model)?;
+        if other != provider {
+            return Err(anyhow::anyhow!(
+                "provider \"auto\" is ambiguous: '{}' is {} but '{}' is {}",
+                default_model,
+                provider,
+                model,
+                other
+            ));
         }
     }
+    Ok(provider)
 }
 
-/// Render a prompt template by substituting `{{key}}` placeholders with values.
-pub fn render_prompt(template: &str, vars: &HashMap<String, String>) -> String {
-    let mut result = template.to_string();
-    for (key, value) in vars {
-        let placeholder = format!("{{{{{}}}}}", key);
-        result = result.replace(&placeholder, value);
+/// The provider for a test's own `provider: "auto"`, from its model.
+pub fn infer_test_provider(test: &TestDef, defaults: &Defaults) -> anyhow::Result<&'static str> {
+    let model = test.model.as_deref().unwrap_or(&defaults.model);
+    if model.contains("{{") {
+        return Err(anyhow::anyhow!(
+            "Test '{}': provider \"auto\" needs a fixed model, not '{}'; set provider explicitly",
+            test.id,
+            model
+        ));
     }
-    result
+    auto_provider_for(model).map_err(|e| anyhow::anyhow!("Test '{}': {}", test.id, e))
 }
 
-// Helper to render assertions (e.g., contains: "{{expected}}")
-fn render_assertions(assertions: &[Assertion], vars: &HashMap<String, String>) -> Vec<Assertion> {
-    assertions
-        .iter()
-        .map(|a| {
-            // Only string values in assertions can be templated
-            let new_value = if let Some(s) = a.value.as_str() {
-                serde_yaml::Value::String(render_prompt(s, vars))
-            } else {
-                a.value.clone()
-            };
-            Assertion {
-                kind: a.kind.clone(),
-                value: new_value,
-            }
-        })
-        .collect()
+fn auto_provider_for(model: &str) -> anyhow::Result<&'static str> {
+    providers::provider_for_model(model).ok_or_else(|| {
+        anyhow::anyhow!(
+            "provider \"auto\" can't tell which provider serves model '{}'; set provider explicitly",
+            model
+        )
+    })
 }
 
-/// Load and parse a Config from a YAML file path.
-/// Also loads any referenced CSV files.
-pub fn load_config(path: &str) -> anyhow::Result<Config> {
-    let content = std::fs::read_to_string(path)
-        .map_err(|e| anyhow::anyhow!("Failed to read config file '{}': {}", path, e))?;
-    let mut config: Config = serde_yaml::from_str(&content)
-        .map_err(|e| anyhow::anyhow!("Failed to parse config file '{}': {}", path, e))?;
+/// Replace `provider: "auto"`, in `defaults` or on a test, with the
+/// inferred provider. Call after
+/// `apply_profile`, since a profile can change the model.
+pub fn resolve_provider(config: &mut Config) -> anyhow::Result<()> {
+    if config.defaults.provider == AUTO_PROVIDER {
+        config.defaults.provider = infer_provider(config)?.to_string();
+    }
+    for test in &mut config.tests {
+        if test.provider.as_deref() == Some(AUTO_PROVIDER) {
+            let provider = infer_test_provider(test, &config.defaults)?;
+            test.provider = Some(provider.to_string());
+        }
+    }
+    Ok(())
+}
 
-    // Resolve CSV files
-    let base_dir = std::path::Path::new(path)
-        .parent()
-        .unwrap_or_else(|| std::path::Path::new("."));
+/// Knobs for `validate_config_with`.
+#[derive(Debug, Clone, Default)]
+pub struct ValidateOptions {
+    /// Don't compile regex patterns up front (`--no-validate-regex`); a bad
+    /// pattern then fails only its own assertion at run time
+    pub skip_regex: bool,
+    /// Report keys no config struct reads (`--strict`), which serde would
+    /// otherwise ignore silently
+    pub strict: bool,
+    /// Report warnings as errors (`--warnings-as-errors`), so they block the
+    /// run too
+    pub warnings_as_errors: bool,
+}
 
-    for test in &mut config.tests {
-        if let Some(csv_file) = &test.cases_file {
-            let csv_path = base_dir.join(csv_file);
-            let mut rdr = csv::Reader::from_path(&csv_path).map_err(|e| {
-                anyhow::anyhow!("Failed to open CSV '{}': {}", csv_path.display(), e)
-            })?;
-
-            let headers = rdr.headers()?.clone();
-
-            for result in rdr.records() {
-                let record = result.map_err(|e| {
-                    anyhow::anyhow!(
-                        "Failed to parse CSV record in '{}': {}",
-                        csv_path.display(),
-                        e
-                    )
-                })?;
+/// How serious a validation issue is. Errors block a run; warnings are shown
+/// but don't, unless `warnings_as_errors` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
 
-                let mut input = HashMap::new();
-                for (i, field) in record.iter().enumerate() {
-                    if let Some(header) = headers.get(i) {
-                        input.insert(header.to_string(), field.to_string());
-                    }
-                }
+/// One problem found by `validate_config`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
 
-                // Apply test-level assertions (rendering templates if needed)
-                let assertions = render_assertions(&test.assertions, &input);
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
 
-                test.cases.push(TestCase { input, assertions });
-            }
+/// Stable hash of the resolved config (FNV-1a over its JSON form), so a run
+/// can be traced back to the exact inputs that produced it. Map keys are
+/// serialized sorted, so the hash doesn't depend on `HashMap` order.
+pub fn config_hash(config: &Config) -> String {
+    let json = serde_json::to_vec(config).unwrap_or_default();
+    let hash = json.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Most stop sequences the OpenAI API accepts per request.
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Range checks for stop sequences and penalties, as the OpenAI API
+/// enforces them.
+fn sampling_issues(
+    stop: &[String],
+    frequency_penalty: Option<f64>,
+    presence_penalty: Option<f64>,
+    max_tokens: Option<u32>,
+) -> Vec<String> {
+    let mut issues = Vec::new();
+    if stop.len() > MAX_STOP_SEQUENCES {
+        issues.push(format!(
+            "stop has {} sequences; at most {} are allowed",
+            stop.len(),
+            MAX_STOP_SEQUENCES
+        ));
+    }
+    if stop.iter().any(|s| s.is_empty()) {
+        issues.push("stop sequences must not be empty".to_string());
+    }
+    for (name, penalty) in [
+        ("frequency_penalty", frequency_penalty),
+        ("presence_penalty", presence_penalty),
+    ] {
+        if let Some(p) = penalty.filter(|p| !(-2.0..=2.0).contains(p)) {
+            issues.push(format!("{} {} is out of range [-2.0, 2.0]", name, p));
         }
     }
+    if max_tokens == Some(0) {
+        issues.push("max_tokens must be at least 1".to_string());
+    }
+    issues
+}
 
-    Ok(config)
+/// Validate a config for logical errors. Returns errors first, then warnings.
+pub fn validate_config(config: &Config) -> Vec<ValidationIssue> {
+    validate_config_with(config, &ValidateOptions::default())
 }
 
-/// Validate a config for logical errors. Returns a list of warnings/errors.
-pub fn validate_config(config: &Config) -> Vec<String> {
+pub fn validate_config_with(config: &Config, options: &ValidateOptions) -> Vec<ValidationIssue> {
     let mut issues = Vec::new();
+    // Suspicious but runnable configs
+    let mut warnings = Vec::new();
 
-    if !KNOWN_PROVIDERS.contains(&config.defaults.provider.as_str()) {
+    // Parse an assertion's value, skipping templated values (only known per
+    // case) and, with `skip_regex`, regex patterns
+    let check_value = |assertion: &Assertion| -> Option<String> {
+        if assertion.is_templated() || (options.skip_regex && assertion.is_regex()) {
+            return None;
+        }
+        assertion.parse().err().map(|e| e.to_string())
+    };
+
+    if options.strict {
+        for field in &config.unknown_fields {
+            issues.push(field.clone());
+        }
+    }
+
+    if config.defaults.provider == AUTO_PROVIDER {
+        if let Err(e) = infer_provider(config) {
+            issues.push(e.to_string());
+        }
+    } else if !KNOWN_PROVIDERS.contains(&config.defaults.provider.as_str()) {
         issues.push(format!(
-            "Unknown default provider '{}'. Known: {}",
+            "Unknown default provider '{}'. Known: {} (or {})",
             config.defaults.provider,
-            KNOWN_PROVIDERS.join(", ")
+            KNOWN_PROVIDERS.join(", "),
+            AUTO_PROVIDER
         ));
     }
 
-    if config.defaults.temperature < 0.0 || config.defaults.temperature > 2.0 {
-        issues.push(format!(
-            "Temperature {} is out of range [0.0, 2.0]",
-            config.defaults.temperature
-        ));
+    for (stage, commands) in [("setup", &config.setup), ("teardown", &config.teardown)] {
+        for (i, cmd) in commands.iter().enumerate() {
+            if cmd.trim().is_empty() {
+                issues.push(format!("{} command {} is empty", stage, i + 1));
+            }
+        }
+    }
+
+    if let Some(Some(temperature)) = config.defaults.temperature {
+        if !(0.0..=2.0).contains(&temperature) {
+            issues.push(format!(
+                "Temperature {} is out of range [0.0, 2.0]",
+                temperature
+            ));
+        }
+    }
+
+    issues.extend(
+        sampling_issues(
+            &config.defaults.stop,
+            config.defaults.frequency_penalty,
+            config.defaults.presence_penalty,
+            config.defaults.max_tokens,
+        )
+        .into_iter()
+        .map(|issue| format!("defaults: {}", issue)),
+    );
+    if config.defaults.assertion_timeout_ms == Some(0) {
+        issues.push("defaults: assertion_timeout_ms must be at least 1".to_string());
+    }
+
+    // Model-specific temperature limits (default model plus per-test overrides)
+    let mut models: Vec<&str> = vec![config.defaults.model.as_str()];
+    for test in &config.tests {
+        if let Some(model) = test.model.as_deref().filter(|m| !m.contains("{{")) {
+            if !models.contains(&model) {
+                models.push(model);
+            }
+        }
+    }
+    for model in models {
+        let Some(temperature) = config.defaults.temperature_for(model) else {
+            continue;
+        };
+        let Some(info) = providers::model_info(model) else {
+            continue;
+        };
+        // The runner leaves the temperature out for such models, so this
+        // only means the setting has no effect there
+        if !info.supports_temperature {
+            if temperature != providers::API_DEFAULT_TEMPERATURE {
+                warnings.push(format!(
+                    "Model '{}' does not support a custom temperature (got {}); it is left out of requests",
+                    model, temperature
+                ));
+            }
+        } else if temperature > info.max_temperature {
+            issues.push(format!(
+                "Temperature {} exceeds the maximum of {} for model '{}'",
+                temperature, info.max_temperature, model
+            ));
+        }
     }
 
     if config.tests.is_empty() {
@@ -269,6 +2760,100 @@ pub fn validate_config(config: &Config) -> Vec<String> {
             issues.push(format!("Test '{}': prompt is empty", test.id));
         }
 
+        for (hook, cmd) in [("before", &test.before), ("after", &test.after)] {
+            if cmd.as_deref().is_some_and(|c| c.trim().is_empty()) {
+                issues.push(format!("Test '{}': {} command is empty", test.id, hook));
+            }
+        }
+
+        if let Some(provider) = test.provider.as_deref().filter(|p| !p.contains("{{")) {
+            if provider == AUTO_PROVIDER {
+                if let Err(e) = infer_test_provider(test, &config.defaults) {
+                    issues.push(e.to_string());
+                }
+            } else if !KNOWN_PROVIDERS.contains(&provider) {
+                issues.push(format!(
+                    "Test '{}': unknown provider '{}'. Known: {}",
+                    test.id,
+                    provider,
+                    KNOWN_PROVIDERS.join(", ")
+                ));
+            }
+        }
+
+        for name in &test.use_sets {
+            if !config.assertion_sets.contains_key(name) {
+                let mut available: Vec<&str> =
+                    config.assertion_sets.keys().map(String::as_str).collect();
+                available.sort_unstable();
+                let hint = match find_closest(name, &available) {
+                    Some(s) => format!(". Did you mean '{}'?", s),
+                    None if available.is_empty() => ". No assertion_sets are defined".to_string(),
+                    None => format!(". Available: {}", available.join(", ")),
+                };
+                issues.push(format!(
+                    "Test '{}': unknown assertion set '{}'{}",
+                    test.id, name, hint
+                ));
+            }
+        }
+
+        if test.budget_usd.is_some_and(|budget| budget <= 0.0) {
+            issues.push(format!(
+                "Test '{}': budget_usd must be greater than 0",
+                test.id
+            ));
+        }
+
+        if test.n == Some(0) {
+            issues.push(format!("Test '{}': n must be at least 1", test.id));
+        }
+        if test.choices == ChoiceMode::Any && test.n.unwrap_or(1) < 2 {
+            warnings.push(format!(
+                "Test '{}': choices: any has no effect without n > 1",
+                test.id
+            ));
+        }
+
+        issues.extend(
+            sampling_issues(
+                test.stop.as_deref().unwrap_or_default(),
+                test.frequency_penalty,
+                test.presence_penalty,
+                test.max_tokens,
+            )
+            .into_iter()
+            .map(|issue| format!("Test '{}': {}", test.id, issue)),
+        );
+        let provider = test
+            .provider
+            .as_deref()
+            .unwrap_or(&config.defaults.provider);
+        if provider != AUTO_PROVIDER && !provider.contains("{{") {
+            let capabilities = providers::provider_capabilities(provider, "");
+            if !capabilities.supports_sampling
+                && !test.completion_params(&config.defaults).is_empty()
+            {
+                warnings.push(format!(
+                    "Test '{}': stop and penalties aren't supported there; provider '{}' ignores them",
+                    test.id, provider
+                ));
+            }
+            if !capabilities.returns_usage && test.budget_usd.is_some() {
+                warnings.push(format!(
+                    "Test '{}': budget_usd is set, but provider '{}' may not report token usage, so its cost can read as $0",
+                    test.id, provider
+                ));
+            }
+        }
+
+        if test.stream && test.cases_file.is_none() {
+            warnings.push(format!(
+                "Test '{}': stream is set but no cases_file is given",
+                test.id
+            ));
+        }
+
         if test.cases.is_empty() && test.cases_file.is_none() {
             issues.push(format!(
                 "Test '{}': no test cases defined (inline or CSV)",
@@ -280,50 +2865,87 @@ pub fn validate_config(config: &Config) -> Vec<String> {
         // We only validate inline cases here fully. CSV cases are loaded dynamically.
         // But we should validate the "template" assertions if present.
         for (i, assertion) in test.assertions.iter().enumerate() {
-            if !KNOWN_ASSERTION_TYPES.contains(&assertion.kind.as_str()) {
-                // Fuzzy match logic repeated...
-                let suggestion = find_closest(&assertion.kind, KNOWN_ASSERTION_TYPES);
-                let hint = suggestion
-                    .map(|s| format!(". Did you mean '{}'?", s))
-                    .unwrap_or_default();
+            if let Some(e) = check_value(assertion) {
                 issues.push(format!(
-                    "Test '{}', default assertion {}: unknown type '{}'{}",
+                    "Test '{}', default assertion {} ({}): {}",
                     test.id,
                     i + 1,
-                    assertion.kind,
-                    hint
+                    assertion.name(),
+                    e
                 ));
             }
         }
 
-        for (ci, case) in test.cases.iter().enumerate() {
-            if case.assertions.is_empty() {
+        if let Some(Extract::Regex { regex, .. }) =
+            test.extract.as_ref().filter(|_| !options.skip_regex)
+        {
+            if let Err(e) = regex::Regex::new(regex) {
                 issues.push(format!(
+                    "Test '{}': invalid extract regex '{}': {}",
+                    test.id, regex, e
+                ));
+            }
+        }
+
+        for (ci, case) in test.cases.iter().enumerate() {
+            if let Some(Extract::Regex { regex, .. }) =
+                case.extract.as_ref().filter(|_| !options.skip_regex)
+            {
+                if let Err(e) = regex::Regex::new(regex) {
+                    issues.push(format!(
+                        "Test '{}', case {}: invalid extract regex '{}': {}",
+                        test.id,
+                        ci + 1,
+                        regex,
+                        e
+                    ));
+                }
+            }
+
+            let expects_error = case.expect_error.unwrap_or(test.expect_error)
+                || case.error_contains.is_some()
+                || test.error_contains.is_some();
+            if case.assertions.is_empty() && !expects_error {
+                warnings.push(format!(
                     "Test '{}', case {}: no assertions defined",
                     test.id,
                     ci + 1
                 ));
             }
 
-            for assertion in &case.assertions {
-                if !KNOWN_ASSERTION_TYPES.contains(&assertion.kind.as_str()) {
-                    let suggestion = find_closest(&assertion.kind, KNOWN_ASSERTION_TYPES);
-                    let hint = suggestion
-                        .map(|s| format!(". Did you mean '{}'?", s))
-                        .unwrap_or_default();
+            for (ai, assertion) in case.assertions.iter().enumerate() {
+                if let Some(ref cond) = assertion.skip_if {
+                    if let Err(e) = eval_condition(cond, &case.input) {
+                        issues.push(format!(
+                            "Test '{}', case {}: skip_if {}",
+                            test.id,
+                            ci + 1,
+                            e
+                        ));
+                    }
+                }
+
+                if let Some(e) = check_value(assertion) {
                     issues.push(format!(
-                        "Test '{}', case {}: unknown assertion type '{}'{}",
+                        "Test '{}', case {}, assertion {} ({}): {}",
                         test.id,
                         ci + 1,
-                        assertion.kind,
-                        hint
+                        ai + 1,
+                        assertion.name(),
+                        e
+                    ));
+                }
+            }
+
+            if !case.images.is_empty() {
+                let model = test.model.as_deref().unwrap_or(&config.defaults.model);
+                if providers::model_info(model).is_some_and(|info| !info.supports_vision) {
+                    warnings.push(format!(
+                        "Test '{}', case {}: has images, but model '{}' doesn't accept image input",
+                        test.id,
+                        ci + 1,
+                        model
                     ));
-                } else if let Err(e) = AssertionKind::from_raw(&assertion.kind, &assertion.value) {
-                    // Only validate concrete values, skip template strings
-                    let is_template = assertion.value.as_str().map_or(false, |s| s.contains("{{"));
-                    if !is_template {
-                        issues.push(format!("Test '{}', case {}: {}", test.id, ci + 1, e));
-                    }
                 }
             }
 
@@ -335,37 +2957,54 @@ pub fn validate_config(config: &Config) -> Vec<String> {
                     ci + 1
                 ));
             }
+
+            for (field, template) in [("model", &test.model), ("provider", &test.provider)] {
+                let Some(template) = template.as_deref().filter(|t| t.contains("{{")) else {
+                    continue;
+                };
+                let resolved = render_prompt(template, &case.input);
+                if resolved.trim().is_empty() || resolved.contains("{{") {
+                    issues.push(format!(
+                        "Test '{}', case {}: {} '{}' resolves to '{}'",
+                        test.id,
+                        ci + 1,
+                        field,
+                        template,
+                        resolved
+                    ));
+                }
+            }
         }
     }
 
+    let warning_severity = if options.warnings_as_errors {
+        Severity::Error
+    } else {
+        Severity::Warning
+    };
     issues
+        .into_iter()
+        .map(|message| (Severity::Error, message))
+        .chain(
+            warnings
+                .into_iter()
+                .map(|message| (warning_severity, message)),
+        )
+        .map(|(severity, message)| ValidationIssue { severity, message })
+        .collect()
+}
+
+/// `". Did you mean 'x'?"` for a mistyped assertion type, or nothing.
+pub(crate) fn unknown_type_hint(kind: &str) -> String {
+    find_closest(kind, &known_assertion_types())
+        .map(|s| format!(". Did you mean '{}'?", s))
+        .unwrap_or_default()
 }
 
 fn find_closest<'a>(input: &str, candidates: &[&'a str]) -> Option<&'a str> {
     candidates
         .iter()
-        .filter(|c| levenshtein(input, c) <= 3)
-        .min_by_key(|c| levenshtein(input, c))
+        .filter(|c| assertions::levenshtein(input, c) <= 3)
+        .min_by_key(|c| assertions::levenshtein(input, c))
         .copied()
 }
-
-fn levenshtein(a: &str, b: &str) -> usize {
-    let a: Vec<char> = a.chars().collect();
-    let b: Vec<char> = b.chars().collect();
-    let mut matrix = vec![vec![0usize; b.len() + 1]; a.len() + 1];
-    for i in 0..=a.len() {
-        matrix[i][0] = i;
-    }
-    for j in 0..=b.len() {
-        matrix[0][j] = j;
-    }
-    for i in 1..=a.len() {
-        for j in 1..=b.len() {
-            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
-            matrix[i][j] = (matrix[i - 1][j] + 1)
-                .min(matrix[i][j - 1] + 1)
-                .min(matrix[i - 1][j - 1] + cost);
-        }
-    }
-    matrix[a.len()][b.len()]
-}
@@ -1,16 +1,21 @@
-mod assertions;
-mod config;
-mod providers;
-mod report;
-mod runner;
 mod watch;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::*;
-use runner::Verbosity;
+use prompt_sentinel::assertions::SnapshotMode;
+use prompt_sentinel::runner::Verbosity;
+use prompt_sentinel::{assertions, config, providers, report, runner};
 use serde::Serialize;
 use std::sync::Arc;
 
+/// Exit code when `--filter` matches none of the config's tests.
+const EXIT_NO_MATCHING_TESTS: i32 = 2;
+
+/// Exit code when every case passed but `--fail-on-cost` was exceeded, a
+/// test went over its budget with `--enforce-budgets`, or a model had no
+/// pricing with `--require-pricing`.
+const EXIT_COST_EXCEEDED: i32 = 3;
+
 #[derive(Parser)]
 #[command(
     name = "sentinel",
@@ -22,11 +27,13 @@ struct Cli {
     command: Commands,
 }
 
+// Parsed once per process, so the size of `Run` doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Run prompt regression tests
     Run {
-        /// Path to the YAML test file (default: tests.yaml)
+        /// Path to the YAML test file, or - for stdin (default: tests.yaml)
         #[arg(short, long, default_value = "tests.yaml")]
         file: String,
 
@@ -34,6 +41,23 @@ enum Commands {
         #[arg(long, default_value_t = false)]
         json: bool,
 
+        /// Also write the JSON report to this file (text output is unchanged)
+        #[arg(long, value_name = "PATH")]
+        json_out: Option<String>,
+
+        /// Emit JSON as the old bare array of results (deprecated)
+        #[arg(long, default_value_t = false)]
+        json_legacy: bool,
+
+        /// Print JSON reports on a single line instead of pretty-printed
+        #[arg(long, default_value_t = false)]
+        compact: bool,
+
+        /// Output format: text, json (same as --json), or jsonl to stream one
+        /// result per line as each case completes, then a summary line
+        #[arg(long, value_enum, conflicts_with = "json")]
+        format: Option<OutputFormat>,
+
         /// Upload results to Prompt Sentinel dashboard
         #[arg(long, default_value_t = false)]
         upload: bool,
@@ -42,30 +66,108 @@ enum Commands {
         #[arg(long)]
         token: Option<String>,
 
-        /// Max number of concurrent API requests (default: 5)
-        #[arg(short, long, default_value_t = 5)]
-        concurrency: usize,
+        /// Max number of concurrent API requests (default: 5, or the profile's)
+        #[arg(short, long)]
+        concurrency: Option<usize>,
+
+        /// Use the provider in this shared library (a cdylib exporting
+        /// `register_provider`) in place of `defaults.provider`
+        #[arg(long, value_name = "PATH")]
+        provider_plugin: Option<String>,
 
         /// Per-request timeout in milliseconds (default: 30000)
         #[arg(short, long, default_value_t = 30000)]
         timeout: u64,
 
+        /// Multiply the timeout by this much on each retry, so a slow request
+        /// gets more time instead of timing out again (e.g. 1.5: 1x, 1.5x, 2.25x)
+        #[arg(long, value_name = "X", default_value_t = 1.0)]
+        timeout_multiplier: f64,
+
+        /// Seed the jitter added to retry delays. Jitter is on by default
+        /// (random per run); a seed makes a run's delays repeat exactly, on
+        /// any platform or toolchain (see them with --verbose)
+        #[arg(long, value_name = "N")]
+        retry_jitter_seed: Option<u64>,
+
         /// Update all snapshot files to match current output
         #[arg(long, default_value_t = false)]
         update_snapshots: bool,
 
+        /// Fail snapshot assertions that have no saved snapshot instead of
+        /// creating it, so CI only passes against committed snapshots
+        #[arg(long, default_value_t = false, conflicts_with = "update_snapshots")]
+        require_snapshots: bool,
+
+        /// Read and write snapshots here instead of `defaults.snapshot_dir`
+        /// (or .snapshots)
+        #[arg(long, value_name = "PATH")]
+        snapshot_dir: Option<String>,
+
+        /// Compare against the snapshots in this directory, or in the
+        /// snapshot dir as committed at this git ref, before the local ones
+        #[arg(long, value_name = "DIR|REF")]
+        snapshot_base: Option<String>,
+
+        /// Add this text (and a blank line) before every test's rendered prompt
+        #[arg(long, value_name = "TEXT")]
+        prompt_prefix: Option<String>,
+
+        /// Add this text (after a blank line) to the end of every rendered prompt
+        #[arg(long, value_name = "TEXT")]
+        prompt_suffix: Option<String>,
+
+        /// Strip ANSI escape sequences and control characters from every
+        /// output before assertions (like `strip_control: true`)
+        #[arg(long, default_value_t = false)]
+        strip_control: bool,
+
         /// Skip config validation before running
         #[arg(long, default_value_t = false)]
         no_validate: bool,
 
+        /// Don't compile regex patterns during validation; a pattern the regex
+        /// crate rejects then fails only its own assertion at run time
+        #[arg(long, default_value_t = false)]
+        no_validate_regex: bool,
+
+        /// Treat unknown config keys (e.g. a misspelled `assertt:`) as errors
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+
+        /// Let validation warnings (e.g. a case without assertions) block the
+        /// run, not just errors
+        #[arg(long, default_value_t = false)]
+        warnings_as_errors: bool,
+
         /// Only run tests whose ID contains this pattern
         #[arg(long)]
         filter: Option<String>,
 
+        /// Only run cases whose input has KEY set to VALUE, including
+        /// `cases_file` rows (repeatable; every pair must match)
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_input_filter)]
+        input_filter: Vec<(String, String)>,
+
+        /// Most rows a cases_file may load before the config is rejected
+        /// (streamed files aren't limited)
+        #[arg(long, value_name = "N", default_value_t = config::DEFAULT_MAX_CASES)]
+        max_cases: usize,
+
+        /// Reuse the cases this results file (a --json/--json-out report or
+        /// --format jsonl output) already finished and run only the rest
+        #[arg(long, value_name = "RESULTS")]
+        resume: Option<String>,
+
         /// Generate an HTML report file
         #[arg(long)]
         report: Option<Option<String>>,
 
+        /// Write the run manifest (config hash, versions, git commit) to a
+        /// JSON file (default: .sentinel-manifest.json)
+        #[arg(long, value_name = "PATH")]
+        manifest: Option<Option<String>>,
+
         /// Show full LLM output for each test
         #[arg(short, long, default_value_t = false)]
         verbose: bool,
@@ -73,6 +175,97 @@ enum Commands {
         /// Only show summary (no per-test output)
         #[arg(short, long, default_value_t = false)]
         quiet: bool,
+
+        /// Progress display: bar, dots (a . or F per case, for CI logs), or
+        /// auto (bar on a terminal, dots otherwise)
+        #[arg(long, value_enum, default_value_t = ProgressArg::Auto)]
+        progress: ProgressArg,
+
+        /// Skip the health check: one tiny request sent before the suite that
+        /// aborts the run on a rejected API key or an unreachable endpoint
+        #[arg(long, default_value_t = false)]
+        no_health_check: bool,
+
+        /// The health check now runs by default; kept for older scripts
+        #[arg(
+            long,
+            default_value_t = false,
+            hide = true,
+            conflicts_with = "no_health_check"
+        )]
+        preflight: bool,
+
+        /// Apply a named profile from the config's `profiles` block
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Highlight cases costing more than this many USD (yellow)
+        #[arg(long)]
+        cost_warn: Option<f64>,
+
+        /// Highlight cases costing more than this many USD (red)
+        #[arg(long)]
+        cost_crit: Option<f64>,
+
+        /// Run every case N times
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        repeat: u32,
+
+        /// Fail repeated cases whose outputs differ between runs (needs --repeat)
+        #[arg(long, default_value_t = false)]
+        assert_deterministic: bool,
+
+        /// Run each test's cases one at a time, in order (tests still run in parallel)
+        #[arg(long, default_value_t = false)]
+        serial_per_test: bool,
+
+        /// Start cases test by test in config order, instead of taking one
+        /// case from each test in turn
+        #[arg(long, default_value_t = false)]
+        in_order: bool,
+
+        /// Append an assertion to every case, as `type=value` (repeatable),
+        /// e.g. --assert-all 'not-contains=As an AI'
+        #[arg(long, value_name = "TYPE=VALUE")]
+        assert_all: Vec<String>,
+
+        /// Exit with code 3 if the run's total cost exceeds this many USD
+        /// (the run still completes)
+        #[arg(long, value_name = "USD")]
+        fail_on_cost: Option<f64>,
+
+        /// Exit with code 3 if any test spends more than its `budget_usd`
+        /// (otherwise overages are only reported)
+        #[arg(long, default_value_t = false)]
+        enforce_budgets: bool,
+
+        /// Exit with code 3 if any case used a model without pricing data,
+        /// whose cost would otherwise be reported as $0
+        #[arg(long, default_value_t = false)]
+        require_pricing: bool,
+
+        /// After the run, add each test's average cost and latency to its
+        /// `id:` line in the config file as a comment
+        #[arg(long, default_value_t = false)]
+        annotate_costs: bool,
+
+        /// List the N most expensive cases after the summary (and in the report)
+        #[arg(long, value_name = "N")]
+        top_cost: Option<usize>,
+
+        /// Repeat up to N failing cases, with their first failed assertion,
+        /// at the end of the output (0 to turn off)
+        #[arg(long, value_name = "N", default_value_t = runner::DEFAULT_MAX_FAILURES_SHOWN)]
+        max_failures_shown: usize,
+
+        /// Don't list failing cases one by one; collapse identical failures
+        /// into a count with a few example inputs
+        #[arg(long, default_value_t = false)]
+        group_failures: bool,
+
+        /// Print the fully-resolved config (YAML, or JSON with --json) and exit
+        #[arg(long, default_value_t = false)]
+        dump_config: bool,
     },
 
     /// Watch for file changes and re-run tests automatically
@@ -85,6 +278,10 @@ enum Commands {
         #[arg(long, default_value_t = false)]
         json: bool,
 
+        /// Print JSON reports on a single line instead of pretty-printed
+        #[arg(long, default_value_t = false)]
+        compact: bool,
+
         /// Upload results to Prompt Sentinel dashboard (default: false)
         #[arg(long, default_value_t = false)]
         upload: bool,
@@ -93,9 +290,14 @@ enum Commands {
         #[arg(long)]
         token: Option<String>,
 
-        /// Max number of concurrent API requests (default: 5)
-        #[arg(short, long, default_value_t = 5)]
-        concurrency: usize,
+        /// Max number of concurrent API requests (default: 5, or the profile's)
+        #[arg(short, long)]
+        concurrency: Option<usize>,
+
+        /// Use the provider in this shared library (a cdylib exporting
+        /// `register_provider`) in place of `defaults.provider`
+        #[arg(long, value_name = "PATH")]
+        provider_plugin: Option<String>,
 
         /// Per-request timeout in milliseconds (default: 30000)
         #[arg(short, long, default_value_t = 30000)]
@@ -105,10 +307,26 @@ enum Commands {
         #[arg(long, default_value_t = false)]
         update_snapshots: bool,
 
+        /// Read and write snapshots here instead of `defaults.snapshot_dir`
+        #[arg(long, value_name = "PATH")]
+        snapshot_dir: Option<String>,
+
         /// Skip config validation
         #[arg(long, default_value_t = false)]
         no_validate: bool,
 
+        /// Defer regex validation to run time
+        #[arg(long, default_value_t = false)]
+        no_validate_regex: bool,
+
+        /// Treat unknown config keys (e.g. a misspelled `assertt:`) as errors
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+
+        /// Let validation warnings skip the cycle, not just errors
+        #[arg(long, default_value_t = false)]
+        warnings_as_errors: bool,
+
         /// Only run tests whose ID contains this pattern
         #[arg(long)]
         filter: Option<String>,
@@ -124,19 +342,213 @@ enum Commands {
         /// Only show summary
         #[arg(short, long, default_value_t = false)]
         quiet: bool,
+
+        /// Apply a named profile from the config's `profiles` block
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Serve the latest HTML report on this port, refreshing each cycle
+        #[arg(long)]
+        serve: Option<u16>,
+
+        /// Re-run only cases whose prompt or parameters changed since the
+        /// last cycle; unchanged cases reuse their previous result
+        #[arg(long, default_value_t = false)]
+        only_changed_cases: bool,
     },
 
     /// Validate a test configuration file without running any tests
     Validate {
-        /// Path to the YAML test file (default: tests.yaml)
+        /// Path to the YAML test file, or - for stdin (default: tests.yaml)
+        #[arg(short, long, default_value = "tests.yaml")]
+        file: String,
+
+        /// Apply a named profile from the config's `profiles` block
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Don't compile regex patterns
+        #[arg(long, default_value_t = false)]
+        no_validate_regex: bool,
+
+        /// Report unknown config keys (e.g. a misspelled `assertt:`)
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+
+        /// Fail on warnings as well as errors
+        #[arg(long, default_value_t = false)]
+        warnings_as_errors: bool,
+
+        /// Most rows a cases_file may load before the config is rejected
+        /// (streamed files aren't limited)
+        #[arg(long, value_name = "N", default_value_t = config::DEFAULT_MAX_CASES)]
+        max_cases: usize,
+    },
+
+    /// Manage snapshot files
+    Snapshots {
+        #[command(subcommand)]
+        command: SnapshotsCommand,
+    },
+
+    /// Check that the configured provider is reachable and the API key works
+    Check {
+        /// Path to the YAML test file, or - for stdin (default: tests.yaml)
+        #[arg(short, long, default_value = "tests.yaml")]
+        file: String,
+
+        /// Use the provider in this shared library (a cdylib exporting
+        /// `register_provider`) in place of `defaults.provider`
+        #[arg(long, value_name = "PATH")]
+        provider_plugin: Option<String>,
+
+        /// Probe timeout in milliseconds (default: 30000)
+        #[arg(short, long, default_value_t = 30000)]
+        timeout: u64,
+    },
+
+    /// List the supported assertion types, the value each expects and an example
+    ListAssertions {
+        /// Print the registry as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+
+    /// List the supported providers and the env vars each reads
+    ListProviders {
+        /// Print the registry as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+
+    /// Evaluate one assertion against sample text, without a config
+    Assert {
+        /// Assertion type, e.g. regex
+        #[arg(long = "type")]
+        kind: String,
+
+        /// Assertion value, read as YAML (`500` is a number, `[a, b]` a
+        /// list); omit for types that take none, like json_valid
+        #[arg(long)]
+        value: Option<String>,
+
+        /// Text to check (default: read from stdin)
+        #[arg(long)]
+        text: Option<String>,
+    },
+
+    /// Summarize a saved results file (--json-out, --json or --format jsonl
+    /// output) without re-running anything
+    Stats {
+        /// Path to the results file
+        file: String,
+
+        /// Print the aggregates as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+
+    /// Run the suite against two models and report how far their outputs
+    /// drift apart, case by case
+    CompareModels {
+        /// Baseline model
+        model_a: String,
+
+        /// Model to compare against the baseline
+        model_b: String,
+
+        /// Path to the YAML test file, or - for stdin (default: tests.yaml)
         #[arg(short, long, default_value = "tests.yaml")]
         file: String,
+
+        /// Max number of concurrent API requests (default: 5)
+        #[arg(short, long, default_value_t = 5)]
+        concurrency: usize,
+
+        /// Per-request timeout in milliseconds (default: 30000)
+        #[arg(short, long, default_value_t = 30000)]
+        timeout: u64,
+
+        /// Only run tests whose ID contains this pattern
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// How many of the most divergent cases to show (default: 5)
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+
+        /// Print the comparison as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
     },
 
+    /// Print a JSON Schema of the YAML test file format, for editor
+    /// completion and external validation
+    Schema,
+
     /// Initialize a new Prompt Sentinel project in the current directory
     Init,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    #[value(alias = "json-lines")]
+    Jsonl,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ProgressArg {
+    Auto,
+    Bar,
+    Dots,
+}
+
+impl From<ProgressArg> for runner::ProgressMode {
+    fn from(arg: ProgressArg) -> Self {
+        match arg {
+            ProgressArg::Auto => runner::ProgressMode::Auto,
+            ProgressArg::Bar => runner::ProgressMode::Bar,
+            ProgressArg::Dots => runner::ProgressMode::Dots,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum SnapshotsCommand {
+    /// Run the suite and accept or reject each changed snapshot
+    Review {
+        /// Path to the YAML test file, or - for stdin (default: tests.yaml)
+        #[arg(short, long, default_value = "tests.yaml")]
+        file: String,
+
+        /// Accept every changed snapshot without prompting
+        #[arg(long, default_value_t = false, conflicts_with = "reject_all")]
+        accept_all: bool,
+
+        /// Reject every changed snapshot without prompting (report only)
+        #[arg(long, default_value_t = false)]
+        reject_all: bool,
+
+        /// Max number of concurrent API requests (default: 5)
+        #[arg(short, long, default_value_t = 5)]
+        concurrency: usize,
+
+        /// Per-request timeout in milliseconds (default: 30000)
+        #[arg(short, long, default_value_t = 30000)]
+        timeout: u64,
+
+        /// Only run tests whose ID contains this pattern
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// Read and write snapshots here instead of `defaults.snapshot_dir`
+        #[arg(long, value_name = "PATH")]
+        snapshot_dir: Option<String>,
+    },
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let _ = dotenvy::dotenv();
@@ -147,16 +559,55 @@ async fn main() -> anyhow::Result<()> {
         Commands::Run {
             file,
             json,
+            json_out,
+            json_legacy,
+            compact,
+            format,
             upload,
             token,
             concurrency,
+            provider_plugin,
             timeout,
+            timeout_multiplier,
+            retry_jitter_seed,
             update_snapshots,
+            require_snapshots,
+            snapshot_dir,
+            snapshot_base,
+            prompt_prefix,
+            prompt_suffix,
+            strip_control,
             no_validate,
+            no_validate_regex,
+            strict,
+            warnings_as_errors,
             filter,
+            input_filter,
+            max_cases,
+            resume,
             report: report_flag,
+            manifest: manifest_flag,
             verbose,
             quiet,
+            progress,
+            no_health_check,
+            preflight: _,
+            profile,
+            cost_warn,
+            cost_crit,
+            repeat,
+            assert_deterministic,
+            serial_per_test,
+            in_order,
+            assert_all,
+            fail_on_cost,
+            enforce_budgets,
+            require_pricing,
+            annotate_costs,
+            top_cost,
+            max_failures_shown,
+            group_failures,
+            dump_config,
         } => {
             // Resolve verbosity
             let verbosity = if quiet {
@@ -166,42 +617,169 @@ async fn main() -> anyhow::Result<()> {
             } else {
                 Verbosity::Normal
             };
+            let json = json || format == Some(OutputFormat::Json);
+            let jsonl = format == Some(OutputFormat::Jsonl);
+            // Either way stdout is reserved for machine-readable output
+            let machine_output = json || jsonl;
+
+            // 1. Load config (+ profile overlay)
+            let mut cfg = config::load_config_with(&file, &config::LoadOptions { max_cases })?;
+            let profile_concurrency = match profile {
+                Some(ref name) => config::apply_profile(&mut cfg, name)?,
+                None => None,
+            };
+            let concurrency = concurrency
+                .or(profile_concurrency)
+                .unwrap_or(runner::DEFAULT_CONCURRENCY);
+            config::resolve_provider(&mut cfg)?;
+            let global_assertions = assert_all
+                .iter()
+                .map(|arg| config::parse_assertion_arg(arg))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            config::apply_global_assertions(&mut cfg, &global_assertions);
+
+            if timeout_multiplier < 1.0 || !timeout_multiplier.is_finite() {
+                return Err(anyhow::anyhow!(
+                    "--timeout-multiplier must be at least 1.0 (got {})",
+                    timeout_multiplier
+                ));
+            }
+
+            if assert_deterministic && repeat < 2 {
+                return Err(anyhow::anyhow!(
+                    "--assert-deterministic needs at least two runs per case (--repeat 2 or more)"
+                ));
+            }
 
-            // 1. Load config
-            let cfg = config::load_config(&file)?;
+            if dump_config {
+                let dumped = if json {
+                    serde_json::to_string_pretty(&cfg)?
+                } else {
+                    serde_yaml::to_string(&cfg)?
+                };
+                println!("{}", dumped);
+                return Ok(());
+            }
 
             // 2. Auto-validate (unless --no-validate)
             if !no_validate {
-                let issues = config::validate_config(&cfg);
-                if !issues.is_empty() {
-                    if !json {
-                        eprintln!(
-                            "\n  {} Config validation found {} issue(s):\n",
-                            "✗".red().bold(),
-                            issues.len()
-                        );
-                        for issue in &issues {
-                            eprintln!("    {} {}", "•".red(), issue);
-                        }
+                let options = config::ValidateOptions {
+                    skip_regex: no_validate_regex,
+                    strict,
+                    warnings_as_errors,
+                };
+                let issues = config::validate_config_with(&cfg, &options);
+                let has_errors = issues.iter().any(|i| i.is_error());
+                if !issues.is_empty() && !machine_output {
+                    eprintln!(
+                        "\n  {} Config validation found {} issue(s):\n",
+                        if has_errors {
+                            "✗".red().bold()
+                        } else {
+                            "⚠".yellow().bold()
+                        },
+                        issues.len()
+                    );
+                    for issue in &issues {
+                        eprintln!("    {} {}", issue_bullet(issue), issue);
+                    }
+                    if has_errors {
                         eprintln!(
                             "\n  {} Fix these issues or use {} to skip.\n",
                             "→".bright_cyan(),
                             "--no-validate".bold()
                         );
+                    } else {
+                        eprintln!();
                     }
+                }
+                if has_errors {
                     std::process::exit(1);
                 }
             }
 
+            // A filter that matches nothing is almost always a typo, so don't
+            // report an empty run as a success
+            if let Some(ref pattern) = filter {
+                let matched = cfg.tests.iter().any(|t| t.id.contains(pattern.as_str()));
+                if !matched && !cfg.tests.is_empty() {
+                    eprintln!(
+                        "\n  {} --filter '{}' matches none of the {} test(s):\n",
+                        "✗".red().bold(),
+                        pattern,
+                        cfg.tests.len()
+                    );
+                    for test in &cfg.tests {
+                        eprintln!("    {} {}", "•".red(), test.id);
+                    }
+                    eprintln!();
+                    std::process::exit(EXIT_NO_MATCHING_TESTS);
+                }
+            }
+
+            let snapshot_base = match snapshot_base {
+                Some(ref base) => Some(assertions::SnapshotBase::resolve(
+                    base,
+                    &runner::snapshot_dir(&cfg, snapshot_dir.as_deref().map(std::path::Path::new)),
+                )?),
+                None => None,
+            };
+            let resume = match resume {
+                Some(ref path) => Some((
+                    path,
+                    Arc::new(runner::CompletedResults::from_results(
+                        report::load_results(std::path::Path::new(path))?,
+                    )),
+                )),
+                None => None,
+            };
+
             // 3. Create provider
-            let provider_name = cfg.defaults.provider.as_str();
-            let provider = providers::create_provider(provider_name)?;
-            let provider = Arc::from(provider);
+            let provider: Arc<dyn providers::LlmProvider> = match provider_plugin {
+                Some(ref path) => {
+                    Arc::new(providers::PluginProvider::load(std::path::Path::new(path))?)
+                }
+                None => Arc::from(providers::create_provider(&cfg.defaults.provider)?),
+            };
+
+            // Setup comes before the health check, which may need the service it
+            // starts. From here on every way out runs the teardown first.
+            let setup = runner::run_setup(&cfg, runner::SUITE_COMMAND_TIMEOUT);
+            if let Err(e) = teardown_on_interrupt(&cfg, setup).await {
+                run_suite_teardown(&cfg).await;
+                return Err(e);
+            }
+
+            // Only hard failures abort; anything else may be transient and
+            // is left to the per-case retry logic.
+            if !no_health_check {
+                let status = teardown_on_interrupt(
+                    &cfg,
+                    run_preflight(
+                        &*provider,
+                        &cfg,
+                        timeout,
+                        machine_output || verbosity == Verbosity::Quiet,
+                    ),
+                )
+                .await;
+                match status {
+                    runner::PreflightStatus::AuthFailure(_)
+                    | runner::PreflightStatus::NetworkFailure(_) => {
+                        eprintln!(
+                            "  Aborted before running any cases (skip this check with --no-health-check)"
+                        );
+                        run_suite_teardown(&cfg).await;
+                        std::process::exit(1)
+                    }
+                    _ => {}
+                }
+            }
 
             // 4. Show filter info + run tests
             let filter_ref = filter.as_deref();
 
-            if !json && verbosity != Verbosity::Quiet {
+            if !machine_output && verbosity != Verbosity::Quiet {
                 let all_tests: usize = cfg.tests.iter().map(|t| t.cases.len()).sum();
                 let filtered_tests: usize = cfg
                     .tests
@@ -210,8 +788,9 @@ async fn main() -> anyhow::Result<()> {
                         Some(p) => t.id.contains(p),
                         None => true,
                     })
-                    .map(|t| t.cases.len())
-                    .sum();
+                    .flat_map(|t| &t.cases)
+                    .filter(|c| runner::input_matches(c, &input_filter))
+                    .count();
 
                 if let Some(ref pat) = filter {
                     println!(
@@ -222,42 +801,148 @@ async fn main() -> anyhow::Result<()> {
                         all_tests
                     );
                 }
+                if !input_filter.is_empty() {
+                    let pairs: Vec<String> = input_filter
+                        .iter()
+                        .map(|(k, v)| format!("{}={}", k, v))
+                        .collect();
+                    println!(
+                        "\n  {} Filtering cases by input {}",
+                        "🔍".bright_cyan(),
+                        pairs.join(", ").bold()
+                    );
+                }
+                if let Some((path, ref done)) = resume {
+                    println!(
+                        "\n  {} Resuming from {}: {} finished case(s) will be reused",
+                        "↻".bright_cyan(),
+                        path.bold(),
+                        done.len()
+                    );
+                }
 
+                let streamed = cfg
+                    .tests
+                    .iter()
+                    .filter(|t| filter_ref.is_none_or(|p| t.id.contains(p)))
+                    .any(|t| t.cases_path.is_some());
+
+                let repeat_info = if repeat > 1 {
+                    format!(" × {} runs", repeat)
+                } else {
+                    String::new()
+                };
                 println!(
-                    "\n  {} Running {} test case(s) with concurrency={}, timeout={}ms...\n",
+                    "\n  {} Running {} test case(s){}{} with concurrency={}, timeout={}ms...\n",
                     "⚡".bright_yellow(),
                     filtered_tests,
+                    if streamed { " + streamed rows" } else { "" },
+                    repeat_info,
                     concurrency,
                     timeout
                 );
             }
 
-            let results = runner::run_all_tests(
-                &cfg,
-                provider,
+            let options = runner::RunOptions {
                 concurrency,
-                verbosity,
-                json,
-                update_snapshots,
-                timeout,
-                filter_ref,
+                timeout_ms: timeout,
+                timeout_multiplier,
+                snapshot_mode: if update_snapshots {
+                    SnapshotMode::Update
+                } else if require_snapshots {
+                    SnapshotMode::Require
+                } else {
+                    SnapshotMode::Check
+                },
+                snapshot_dir: snapshot_dir.map(std::path::PathBuf::from),
+                snapshot_base,
+                prompt_prefix,
+                prompt_suffix,
+                strip_control,
+                retry_jitter_seed,
+                filter: filter.clone(),
+                input_filter,
+                resume: resume.map(|(_, done)| done),
+                show_progress: !machine_output && verbosity != Verbosity::Quiet,
+                progress: progress.into(),
+                repeat,
+                assert_deterministic,
+                serial_per_test,
+                schedule: if in_order {
+                    runner::Schedule::InOrder
+                } else {
+                    runner::Schedule::RoundRobin
+                },
+                ..Default::default()
+            };
+            let manifest = report::RunManifest::new(&cfg, &file);
+            let stream_result = |result: &runner::CaseResult| {
+                if jsonl {
+                    println!("{}", report::JsonLine::Result(result).to_line());
+                }
+            };
+            let results = teardown_on_interrupt(
+                &cfg,
+                runner::run_all_tests_with(&cfg, provider, &options, stream_result),
             )
             .await;
+            run_suite_teardown(&cfg).await;
+            let cost_thresholds = runner::CostThresholds {
+                warn: cost_warn,
+                crit: cost_crit,
+            };
+            let budget_overages = runner::budget_overages(&cfg, &results);
 
             // 5. Output results
+            let json_report = if json || json_out.is_some() {
+                if json_legacy {
+                    eprintln!(
+                        "  {} --json-legacy (a bare array of results) is deprecated; \
+                         read `results` from the default JSON report instead",
+                        "⚠".yellow()
+                    );
+                    Some(report::to_json(&results, compact)?)
+                } else {
+                    let run_report = report::RunReport::new(&manifest, &results);
+                    Some(report::to_json(&run_report, compact)?)
+                }
+            } else {
+                None
+            };
+            if let (Some(path), Some(body)) = (&json_out, &json_report) {
+                std::fs::write(path, format!("{}\n", body))?;
+            }
             if json {
-                let json_output = serde_json::to_string_pretty(&results)?;
-                println!("{}", json_output);
+                println!("{}", json_report.as_deref().unwrap_or_default());
+            } else if jsonl {
+                println!(
+                    "{}",
+                    report::JsonLine::summary(&manifest, &results).to_line()
+                );
             } else {
-                runner::print_results(&results, verbosity);
+                let print_options = runner::PrintOptions {
+                    verbosity,
+                    cost_thresholds,
+                    top_cost,
+                    budget_overages: budget_overages.clone(),
+                    max_failures_shown,
+                    group_failures,
+                };
+                runner::print_results(&results, &print_options);
             }
 
             // 6. Generate HTML report
             if let Some(report_path) = report_flag {
                 let path = report_path.unwrap_or_else(|| "report.html".to_string());
                 let path = std::path::Path::new(&path);
-                let generated = report::generate_report(&results, path)?;
-                if !json {
+                let report_options = report::ReportOptions {
+                    cost_thresholds,
+                    top_cost,
+                    manifest: Some(manifest.clone()),
+                    ..Default::default()
+                };
+                let generated = report::generate_report(&results, path, &report_options)?;
+                if !machine_output {
                     println!(
                         "  {} HTML report saved to {}",
                         "📊".bright_cyan(),
@@ -267,6 +952,51 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
 
+            if annotate_costs {
+                if file == config::STDIN_PATH {
+                    eprintln!(
+                        "  {} --annotate-costs needs a config file, not stdin; skipped",
+                        "⚠".yellow()
+                    );
+                } else {
+                    let yaml = std::fs::read_to_string(&file)?;
+                    let annotations = runner::annotate_costs(&yaml, &cfg.tests, &results);
+                    for id in &annotations.skipped {
+                        eprintln!(
+                            "  {} --annotate-costs: test '{}' is written in flow style; not annotated",
+                            "⚠".yellow(),
+                            id
+                        );
+                    }
+                    if annotations.text != yaml {
+                        std::fs::write(&file, &annotations.text)?;
+                    }
+                    if !machine_output {
+                        println!(
+                            "  {} Annotated {} test(s) in {} with cost and latency",
+                            "💰".bright_cyan(),
+                            annotations.annotated,
+                            file.bold()
+                        );
+                        println!();
+                    }
+                }
+            }
+
+            if let Some(manifest_path) = manifest_flag {
+                let path =
+                    manifest_path.unwrap_or_else(|| report::DEFAULT_MANIFEST_PATH.to_string());
+                manifest.write(std::path::Path::new(&path))?;
+                if !machine_output {
+                    println!(
+                        "  {} Run manifest saved to {}",
+                        "📊".bright_cyan(),
+                        path.bold()
+                    );
+                    println!();
+                }
+            }
+
             // 7. Upload
             if upload {
                 let resolved_token = token
@@ -280,25 +1010,70 @@ async fn main() -> anyhow::Result<()> {
             }
 
             // 8. Exit code
+            let over_cost = fail_on_cost
+                .and_then(|limit| runner::over_cost_limit(&results, limit).map(|t| (t, limit)));
+            if let Some((total, limit)) = over_cost {
+                eprintln!(
+                    "  {} Total cost ${:.4} exceeds --fail-on-cost ${:.4}",
+                    "💸".red(),
+                    total,
+                    limit
+                );
+            }
+
+            let over_budget = enforce_budgets && !budget_overages.is_empty();
+            if over_budget {
+                eprintln!(
+                    "  {} {} test(s) exceeded their budget_usd (--enforce-budgets)",
+                    "💸".red(),
+                    budget_overages.len()
+                );
+            }
+
+            let unpriced = if require_pricing {
+                runner::unpriced_models(&results)
+            } else {
+                Vec::new()
+            };
+            if !unpriced.is_empty() {
+                eprintln!(
+                    "  {} No pricing data for {} (--require-pricing); their cost was reported as $0",
+                    "💸".red(),
+                    unpriced.join(", ")
+                );
+            }
+
             let all_passed = results.iter().all(|r| r.passed);
             if !all_passed {
                 std::process::exit(1);
             }
+            if over_cost.is_some() || over_budget || !unpriced.is_empty() {
+                std::process::exit(EXIT_COST_EXCEEDED);
+            }
         }
 
         Commands::Watch {
             file,
             json,
+            compact,
             upload,
             token,
             concurrency,
+            provider_plugin,
             timeout,
             update_snapshots,
+            snapshot_dir,
             no_validate,
+            no_validate_regex,
+            strict,
+            warnings_as_errors,
             filter,
             report: report_flag,
             verbose,
             quiet,
+            profile,
+            serve,
+            only_changed_cases,
         } => {
             let verbosity = if quiet {
                 Verbosity::Quiet
@@ -308,24 +1083,162 @@ async fn main() -> anyhow::Result<()> {
                 Verbosity::Normal
             };
 
+            if file == config::STDIN_PATH {
+                return Err(anyhow::anyhow!(
+                    "watch needs a config file on disk; it can't watch stdin"
+                ));
+            }
+
             watch::run_watch_loop(
                 &file,
                 json,
+                compact,
                 upload,
                 token,
                 concurrency,
+                provider_plugin.as_deref(),
                 timeout,
                 update_snapshots,
+                snapshot_dir,
                 no_validate,
+                config::ValidateOptions {
+                    skip_regex: no_validate_regex,
+                    strict,
+                    warnings_as_errors,
+                },
                 filter,
                 report_flag,
                 verbosity,
+                profile,
+                serve,
+                only_changed_cases,
+            )
+            .await?;
+        }
+
+        Commands::Validate {
+            file,
+            profile,
+            no_validate_regex,
+            strict,
+            warnings_as_errors,
+            max_cases,
+        } => {
+            let options = config::ValidateOptions {
+                skip_regex: no_validate_regex,
+                strict,
+                warnings_as_errors,
+            };
+            let load = config::LoadOptions { max_cases };
+            run_validate(&file, profile.as_deref(), &load, &options)?;
+        }
+
+        Commands::Snapshots {
+            command:
+                SnapshotsCommand::Review {
+                    file,
+                    accept_all,
+                    reject_all,
+                    concurrency,
+                    timeout,
+                    filter,
+                    snapshot_dir,
+                },
+        } => {
+            run_snapshot_review(
+                &file,
+                accept_all,
+                reject_all,
+                concurrency,
+                timeout,
+                filter.as_deref(),
+                snapshot_dir.as_deref(),
             )
             .await?;
         }
 
-        Commands::Validate { file } => {
-            run_validate(&file)?;
+        Commands::Check {
+            file,
+            provider_plugin,
+            timeout,
+        } => {
+            let mut cfg = config::load_config(&file)?;
+            config::resolve_provider(&mut cfg)?;
+            let provider: Box<dyn providers::LlmProvider> = match provider_plugin {
+                Some(ref path) => {
+                    Box::new(providers::PluginProvider::load(std::path::Path::new(path))?)
+                }
+                None => providers::create_provider(&cfg.defaults.provider)?,
+            };
+            println!();
+            let status = run_preflight(&*provider, &cfg, timeout, false).await;
+            println!();
+            if !matches!(status, runner::PreflightStatus::Ok { .. }) {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::ListAssertions { json } => {
+            if json {
+                println!("{}", serde_json::to_string_pretty(config::ASSERTION_TYPES)?);
+            } else {
+                run_list_assertions();
+            }
+        }
+
+        Commands::ListProviders { json } => {
+            if json {
+                println!("{}", serde_json::to_string_pretty(providers::PROVIDERS)?);
+            } else {
+                run_list_providers();
+            }
+        }
+
+        Commands::Assert { kind, value, text } => {
+            run_assert(&kind, value.as_deref(), text)?;
+        }
+
+        Commands::Stats { file, json } => {
+            let results = report::load_results(std::path::Path::new(&file))?;
+            let stats = runner::RunStats::new(&results);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                run_stats(&file, &stats);
+            }
+        }
+
+        Commands::CompareModels {
+            model_a,
+            model_b,
+            file,
+            concurrency,
+            timeout,
+            filter,
+            top,
+            json,
+        } => {
+            let options = runner::RunOptions {
+                concurrency,
+                timeout_ms: timeout,
+                snapshot_mode: SnapshotMode::Review,
+                filter,
+                show_progress: !json,
+                ..Default::default()
+            };
+            let comparison = run_compare_models(&file, &model_a, &model_b, &options).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&comparison)?);
+            } else {
+                runner::print_model_comparison(&comparison, top);
+            }
+        }
+
+        Commands::Schema => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&config::config_schema())?
+            );
         }
 
         Commands::Init => {
@@ -338,17 +1251,35 @@ async fn main() -> anyhow::Result<()> {
 
 // ─── sentinel validate ──────────────────────────────────────────────────────
 
-fn run_validate(file: &str) -> anyhow::Result<()> {
+/// Parse an `--input-filter` pair. The value may itself contain `=`.
+fn parse_input_filter(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", s)),
+    }
+}
+
+fn run_validate(
+    file: &str,
+    profile: Option<&str>,
+    load: &config::LoadOptions,
+    options: &config::ValidateOptions,
+) -> anyhow::Result<()> {
+    let shown = if file == config::STDIN_PATH {
+        "<stdin>"
+    } else {
+        file
+    };
     println!();
     println!(
         "  {} {} {}",
         "⚡".bright_yellow(),
         "Validating".bold(),
-        file.bold()
+        shown.bold()
     );
     println!();
 
-    let cfg = match config::load_config(file) {
+    let mut cfg = match config::load_config_with(file, load) {
         Ok(cfg) => cfg,
         Err(e) => {
             println!("  {} {}", "✗".red().bold(), e);
@@ -358,9 +1289,18 @@ fn run_validate(file: &str) -> anyhow::Result<()> {
     };
     println!("  {} YAML syntax is valid", "✓".green().bold());
 
-    let issues = config::validate_config(&cfg);
+    if let Some(name) = profile {
+        if let Err(e) = config::apply_profile(&mut cfg, name) {
+            println!("  {} {}", "✗".red().bold(), e);
+            println!();
+            std::process::exit(1);
+        }
+        println!("  {} Applied profile '{}'", "✓".green().bold(), name);
+    }
+
+    let issues = config::validate_config_with(&cfg, options);
 
-    if issues.is_empty() {
+    if !issues.iter().any(|i| i.is_error()) {
         let total_cases: usize = cfg.tests.iter().map(|t| t.cases.len()).sum();
         let total_assertions: usize = cfg
             .tests
@@ -369,7 +1309,19 @@ fn run_validate(file: &str) -> anyhow::Result<()> {
             .map(|c| c.assertions.len())
             .sum();
 
-        println!("  {} All checks passed", "✓".green().bold());
+        if issues.is_empty() {
+            println!("  {} All checks passed", "✓".green().bold());
+        } else {
+            println!(
+                "  {} No errors, {} warning(s):",
+                "✓".green().bold(),
+                issues.len()
+            );
+            println!();
+            for issue in &issues {
+                println!("    {} {}", issue_bullet(issue), issue);
+            }
+        }
         println!();
         println!(
             "  {} {} test(s), {} case(s), {} assertion(s)",
@@ -388,7 +1340,7 @@ fn run_validate(file: &str) -> anyhow::Result<()> {
         println!("  {} Found {} issue(s):", "✗".red().bold(), issues.len());
         println!();
         for issue in &issues {
-            println!("    {} {}", "•".red(), issue);
+            println!("    {} {}", issue_bullet(issue), issue);
         }
         println!();
         std::process::exit(1);
@@ -398,6 +1350,459 @@ fn run_validate(file: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Marker for a validation issue: a red bullet for errors, a yellow warning
+/// sign for warnings.
+fn issue_bullet(issue: &config::ValidationIssue) -> ColoredString {
+    if issue.is_error() {
+        "•".red()
+    } else {
+        "⚠".yellow()
+    }
+}
+
+// ─── Suite setup/teardown ───────────────────────────────────────────────────
+
+/// Run the config's `teardown` commands, reporting failures without
+/// changing the run's outcome.
+async fn run_suite_teardown(cfg: &config::Config) {
+    for e in runner::run_teardown(cfg, runner::SUITE_COMMAND_TIMEOUT).await {
+        eprintln!("  {} {}", "⚠".yellow(), e);
+    }
+}
+
+/// Await `fut`, but if Ctrl-C comes first, run the teardown and exit 130.
+/// Without teardown commands Ctrl-C keeps its default behavior.
+async fn teardown_on_interrupt<T>(
+    cfg: &config::Config,
+    fut: impl std::future::Future<Output = T>,
+) -> T {
+    if cfg.teardown.is_empty() {
+        return fut.await;
+    }
+    tokio::select! {
+        out = fut => out,
+        _ = tokio::signal::ctrl_c() => {
+            eprintln!("\n  {} Interrupted, running teardown...", "⚠".yellow());
+            run_suite_teardown(cfg).await;
+            std::process::exit(130);
+        }
+    }
+}
+
+// ─── sentinel snapshots review ──────────────────────────────────────────────
+
+async fn run_snapshot_review(
+    file: &str,
+    accept_all: bool,
+    reject_all: bool,
+    concurrency: usize,
+    timeout: u64,
+    filter: Option<&str>,
+    snapshot_dir: Option<&str>,
+) -> anyhow::Result<()> {
+    use std::io::{BufRead, Write};
+
+    let mut cfg = config::load_config(file)?;
+    config::resolve_provider(&mut cfg)?;
+    let provider = Arc::from(providers::create_provider(&cfg.defaults.provider)?);
+
+    println!();
+    println!(
+        "  {} {}",
+        "📸".bright_cyan(),
+        "Running suite to collect snapshot changes...".bold()
+    );
+    println!();
+
+    let options = runner::RunOptions {
+        concurrency,
+        timeout_ms: timeout,
+        snapshot_mode: SnapshotMode::Review,
+        filter: filter.map(str::to_string),
+        show_progress: true,
+        snapshot_dir: snapshot_dir.map(std::path::PathBuf::from),
+        ..Default::default()
+    };
+    let results = runner::run_all_tests(&cfg, provider, &options).await;
+
+    let snapshot_dir = runner::snapshot_dir(&cfg, options.snapshot_dir.as_deref());
+    let snapshot_dir = snapshot_dir.as_path();
+    let diffs = runner::collect_snapshot_diffs(&results, snapshot_dir);
+
+    if diffs.is_empty() {
+        println!("  {} All snapshots match.", "✓".green().bold());
+        println!();
+        return Ok(());
+    }
+
+    println!(
+        "  {} {} snapshot(s) changed",
+        "→".bright_cyan(),
+        diffs.len()
+    );
+    println!();
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut accepted = 0;
+    let mut rejected = 0;
+
+    for (i, diff) in diffs.iter().enumerate() {
+        println!(
+            "  [{}/{}] {} │ {}",
+            i + 1,
+            diffs.len(),
+            diff.test_id.bold(),
+            diff.input_label.bright_black()
+        );
+        println!("       {}", diff.summary.yellow());
+
+        let accept = if accept_all {
+            true
+        } else if reject_all {
+            false
+        } else {
+            if let Some(ref existing) = diff.existing {
+                println!("       {}", "saved:".red().bold());
+                for line in existing.trim().lines() {
+                    println!("       │ {}", line.red());
+                }
+            }
+            println!("       {}", "current:".green().bold());
+            for line in diff.output.trim().lines() {
+                println!("       │ {}", line.green());
+            }
+
+            print!("       Accept? [a]ccept / [r]eject / [q]uit: ");
+            std::io::stdout().flush()?;
+            match lines.next().transpose()?.as_deref().map(str::trim) {
+                Some("a") | Some("accept") | Some("y") => true,
+                Some("q") | Some("quit") | None => break,
+                _ => false,
+            }
+        };
+
+        if accept {
+            assertions::write_snapshot(&diff.output, &diff.snapshot_key, snapshot_dir)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            accepted += 1;
+            println!("       {} accepted", "✓".green());
+        } else {
+            rejected += 1;
+            println!("       {} rejected", "✗".red());
+        }
+        println!();
+    }
+
+    println!(
+        "  {} {} accepted, {} rejected, {} skipped",
+        "●".bright_cyan(),
+        accepted,
+        rejected,
+        diffs.len() - accepted - rejected
+    );
+    println!();
+
+    Ok(())
+}
+
+// ─── sentinel compare-models ────────────────────────────────────────────────
+
+/// Run the suite once per model and pair up the outputs. Per-test `model`
+/// overrides are dropped so both runs send every case to the model under
+/// comparison; snapshots are compared but never written.
+async fn run_compare_models(
+    file: &str,
+    model_a: &str,
+    model_b: &str,
+    options: &runner::RunOptions,
+) -> anyhow::Result<runner::ModelComparison> {
+    let mut cfg = config::load_config(file)?;
+    config::resolve_provider(&mut cfg)?;
+    let provider: Arc<dyn providers::LlmProvider> =
+        Arc::from(providers::create_provider(&cfg.defaults.provider)?);
+    for test in &mut cfg.tests {
+        test.model = None;
+    }
+
+    let mut runs = Vec::with_capacity(2);
+    for model in [model_a, model_b] {
+        if options.show_progress {
+            println!();
+            println!(
+                "  {} Running suite against {}",
+                "→".bright_cyan(),
+                model.bold()
+            );
+            println!();
+        }
+        cfg.defaults.model = model.to_string();
+        runs.push(runner::run_all_tests(&cfg, provider.clone(), options).await);
+    }
+
+    Ok(runner::ModelComparison::new(
+        model_a, &runs[0], model_b, &runs[1],
+    ))
+}
+
+// ─── Preflight ───────────────────────────────────────────────────────────────
+
+/// Probe the provider once and print the outcome.
+async fn run_preflight(
+    provider: &dyn providers::LlmProvider,
+    cfg: &config::Config,
+    timeout: u64,
+    json: bool,
+) -> runner::PreflightStatus {
+    let provider_name = provider.name().unwrap_or(&cfg.defaults.provider);
+    let status = runner::preflight_check(
+        provider,
+        provider_name,
+        &cfg.defaults.model,
+        cfg.defaults.temperature,
+        timeout,
+    )
+    .await;
+
+    match &status {
+        runner::PreflightStatus::Ok { latency_ms } => {
+            if !json {
+                println!(
+                    "  {} Provider '{}' is reachable ({}ms)",
+                    "✓".green().bold(),
+                    provider_name,
+                    latency_ms
+                );
+            }
+        }
+        runner::PreflightStatus::AuthFailure(msg) => {
+            eprintln!(
+                "  {} Provider '{}' rejected the API key: {}",
+                "✗".red().bold(),
+                provider_name,
+                msg
+            );
+        }
+        runner::PreflightStatus::NetworkFailure(msg) => {
+            eprintln!(
+                "  {} Provider '{}' is unreachable: {}",
+                "✗".red().bold(),
+                provider_name,
+                msg
+            );
+        }
+        runner::PreflightStatus::Failure(msg) => {
+            eprintln!(
+                "  {} Provider '{}' preflight returned an error: {}",
+                "⚠".yellow(),
+                provider_name,
+                msg
+            );
+        }
+    }
+
+    status
+}
+
+// ─── sentinel assert ────────────────────────────────────────────────────────
+
+fn run_assert(kind: &str, value: Option<&str>, text: Option<String>) -> anyhow::Result<()> {
+    use std::io::Read;
+
+    let value = value.map_or(serde_yaml::Value::Bool(true), config::parse_value_arg);
+    let text = match text {
+        Some(text) => text,
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| anyhow::anyhow!("Failed to read text from stdin: {}", e))?;
+            text
+        }
+    };
+
+    let result = assertions::check_text(
+        kind,
+        &value,
+        &text,
+        std::path::Path::new(runner::DEFAULT_SNAPSHOT_DIR),
+    )?;
+    let icon = if result.passed {
+        "✓".green()
+    } else {
+        "✗".red()
+    };
+    println!("  {} {} — {}", icon, result.label.dimmed(), result.detail);
+    if !result.passed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// ─── sentinel list-assertions ───────────────────────────────────────────────
+
+fn run_list_assertions() {
+    let width = config::ASSERTION_TYPES
+        .iter()
+        .map(|spec| spec.name.len())
+        .max()
+        .unwrap_or(0);
+    println!();
+    for spec in config::ASSERTION_TYPES {
+        println!(
+            "  {:<width$}  {:<6}  {}",
+            spec.name.bold(),
+            spec.value.to_string().dimmed(),
+            spec.description,
+            width = width
+        );
+        println!(
+            "  {:<width$}  {}",
+            "",
+            spec.example.dimmed(),
+            width = width + 8
+        );
+    }
+    println!();
+}
+
+fn run_list_providers() {
+    let width = providers::PROVIDERS
+        .iter()
+        .map(|spec| spec.name.len())
+        .max()
+        .unwrap_or(0);
+    println!();
+    for spec in providers::PROVIDERS {
+        println!(
+            "  {:<width$}  {}",
+            spec.name.bold(),
+            spec.description,
+            width = width
+        );
+        let mut required = spec.required_env.join(", ");
+        if !spec.required_any.is_empty() {
+            required = format!("{} and one of {}", required, spec.required_any.join(", "));
+        }
+        println!(
+            "  {:<width$}  requires {}",
+            "",
+            required.cyan(),
+            width = width
+        );
+        if !spec.optional_env.is_empty() {
+            println!(
+                "  {:<width$}  optional {}",
+                "",
+                spec.optional_env.join(", ").dimmed(),
+                width = width
+            );
+        }
+        let capabilities = &spec.capabilities;
+        let unsupported: Vec<&str> = [
+            (capabilities.supports_temperature, "temperature"),
+            (capabilities.returns_usage, "token usage"),
+            (capabilities.supports_sampling, "stop/penalties"),
+        ]
+        .into_iter()
+        .filter(|(supported, _)| !supported)
+        .map(|(_, name)| name)
+        .collect();
+        if !unsupported.is_empty() {
+            println!(
+                "  {:<width$}  lacks    {}",
+                "",
+                unsupported.join(", ").yellow(),
+                width = width
+            );
+        }
+    }
+    println!();
+}
+
+// ─── sentinel stats ─────────────────────────────────────────────────────────
+
+/// How many of the most-failing assertions `sentinel stats` lists.
+const STATS_FAILING_ASSERTIONS: usize = 10;
+
+fn run_stats(file: &str, stats: &runner::RunStats) {
+    println!();
+    println!("  {} {}", "Stats for".bold(), file);
+    println!();
+    println!(
+        "  {} {}/{} passed ({:.1}%)",
+        if stats.failed == 0 {
+            "✓".green().bold()
+        } else {
+            "✗".red().bold()
+        },
+        stats.passed,
+        stats.total,
+        stats.pass_rate() * 100.0
+    );
+    println!(
+        "  {} {} tokens ({} prompt, {} completion) · ${:.6}",
+        "💰".bright_yellow(),
+        stats.tokens.total_tokens,
+        stats.tokens.prompt_tokens,
+        stats.tokens.completion_tokens,
+        stats.cost_usd
+    );
+    let latency = &stats.latency_ms;
+    println!(
+        "  {} latency p50 {}ms · p90 {}ms · p95 {}ms · p99 {}ms · max {}ms",
+        "⏱".bright_cyan(),
+        latency.p50,
+        latency.p90,
+        latency.p95,
+        latency.p99,
+        latency.max
+    );
+
+    if !stats.tests.is_empty() {
+        let width = stats
+            .tests
+            .iter()
+            .map(|t| t.test_id.len())
+            .max()
+            .unwrap_or(0);
+        println!();
+        println!("  {}", "Per test:".bold());
+        for test in &stats.tests {
+            let counts = format!("{}/{}", test.passed, test.total);
+            println!(
+                "    {:<width$}  {:>9}  ${:.5}  ~{}ms",
+                test.test_id,
+                if test.passed == test.total {
+                    counts.green()
+                } else {
+                    counts.red()
+                },
+                test.cost_usd,
+                test.mean_latency_ms,
+                width = width
+            );
+        }
+    }
+
+    if !stats.failing_assertions.is_empty() {
+        println!();
+        println!("  {}", "Most-failing assertions:".bold());
+        for failing in stats
+            .failing_assertions
+            .iter()
+            .take(STATS_FAILING_ASSERTIONS)
+        {
+            println!(
+                "    {} {}",
+                format!("{}×", failing.failed).red().bold(),
+                failing.label
+            );
+        }
+    }
+    println!();
+}
+
 // ─── sentinel init ───────────────────────────────────────────────────────────
 
 fn run_init() -> anyhow::Result<()> {
@@ -458,6 +1863,12 @@ OPENAI_API_KEY=sk-your-key-here
 # Anthropic (required if using provider: "anthropic")
 ANTHROPIC_API_KEY=sk-ant-your-key-here
 
+# Vertex AI (required if using provider: "vertex")
+# VERTEX_PROJECT=my-gcp-project
+# VERTEX_REGION=us-central1
+# GOOGLE_APPLICATION_CREDENTIALS=/path/to/service-account.json
+# (or VERTEX_ACCESS_TOKEN=... in place of the credentials file)
+
 # Custom webhook (required if using provider: "webhook")
 # WEBHOOK_URL=http://localhost:8080/complete
 
@@ -1,22 +1,147 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::Client;
-use serde::Serialize;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Token usage returned by the LLM API.
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TokenUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// Tokens spent on hidden reasoning, where the API reports them
+    /// separately. Already included in `completion_tokens`.
+    pub reasoning_tokens: u32,
 }
 
+/// A provider answered with a non-success HTTP status. The message reads as
+/// before (`OpenAI API error (401 Unauthorized): ...`); callers that need to
+/// tell failures apart downcast the error to this and look at `status`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: String) -> Self {
+        Self { status, message }
+    }
+
+    /// Whether the provider rejected the credentials (401/403).
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(
+            self.status,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        )
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
 /// Result of a completion call — text output + token usage.
 #[derive(Debug)]
 pub struct CompletionResult {
+    /// The final answer, without any thinking/reasoning content
     pub text: String,
     pub usage: TokenUsage,
+    /// Thinking content returned alongside the answer (Anthropic extended thinking)
+    pub thinking: Option<String>,
+    /// Every choice's text when more than one was requested (`text` is the
+    /// first); empty otherwise
+    pub choices: Vec<String>,
+    /// Why generation stopped, as the API reported it (`stop`, `length`,
+    /// `end_turn`, `max_tokens`, ...)
+    pub finish_reason: Option<String>,
+}
+
+impl CompletionResult {
+    /// Whether `finish_reason` says the output was cut off by the token limit.
+    pub fn truncated(&self) -> bool {
+        is_truncation(self.finish_reason.as_deref())
+    }
+}
+
+/// OpenAI reports a token-limit stop as `length`, Anthropic as `max_tokens`.
+pub fn is_truncation(finish_reason: Option<&str>) -> bool {
+    matches!(finish_reason, Some("length" | "max_tokens"))
+}
+
+/// `max_tokens` sent to Anthropic (where it's required) unless configured.
+pub const DEFAULT_ANTHROPIC_MAX_TOKENS: u32 = 1024;
+
+/// Optional generation controls beyond model and temperature. Providers send
+/// the ones their API understands and ignore the rest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CompletionParams {
+    /// Stop generating at any of these sequences
+    pub stop: Vec<String>,
+    pub frequency_penalty: Option<f64>,
+    pub presence_penalty: Option<f64>,
+    /// Cap on generated tokens (OpenAI and Anthropic)
+    pub max_tokens: Option<u32>,
+    /// Leave `temperature` out of the request body, for models that reject
+    /// it. The temperature argument is then ignored
+    pub omit_temperature: bool,
+    /// Sent as `X-Request-Id` by the built-in providers. Not a generation
+    /// setting, so it's left out of the serialized form
+    #[serde(skip)]
+    pub request_id: Option<String>,
+}
+
+impl CompletionParams {
+    /// Whether any OpenAI-only setting is set (`max_tokens` and
+    /// `request_id` are understood more widely).
+    pub fn is_empty(&self) -> bool {
+        self.stop.is_empty() && self.frequency_penalty.is_none() && self.presence_penalty.is_none()
+    }
+}
+
+/// `User-Agent` of the built-in providers unless `SENTINEL_USER_AGENT` is set.
+pub const USER_AGENT: &str = concat!("prompt-sentinel/", env!("CARGO_PKG_VERSION"));
+
+/// HTTP client for the built-in providers, identifying itself with
+/// `SENTINEL_USER_AGENT` or `USER_AGENT`.
+fn http_client() -> Client {
+    let user_agent =
+        std::env::var("SENTINEL_USER_AGENT").unwrap_or_else(|_| USER_AGENT.to_string());
+    Client::builder()
+        .user_agent(user_agent)
+        .build()
+        .unwrap_or_else(|_| Client::new())
+}
+
+/// Add the `X-Request-Id` header when the request carries an ID.
+fn with_request_id(
+    request: reqwest::RequestBuilder,
+    params: &CompletionParams,
+) -> reqwest::RequestBuilder {
+    match &params.request_id {
+        Some(id) => request.header("X-Request-Id", id),
+        None => request,
+    }
+}
+
+/// Error for providers that can only return one completion per call.
+fn single_choice_only(n: u32) -> Result<()> {
+    if n > 1 {
+        return Err(anyhow::anyhow!(
+            "This provider does not support n > 1 (multiple choices)"
+        ));
+    }
+    Ok(())
 }
 
 /// Trait for LLM providers. All providers must implement async completion.
@@ -28,6 +153,108 @@ pub trait LlmProvider: Send + Sync {
         model: &str,
         temperature: f64,
     ) -> Result<CompletionResult>;
+
+    /// Like `complete`, with images (URLs, data URIs or bare base64) sent
+    /// alongside the prompt. Providers without image support reject them.
+    async fn complete_with_images(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+    ) -> Result<CompletionResult> {
+        if !images.is_empty() {
+            return Err(anyhow::anyhow!(
+                "This provider does not support image inputs"
+            ));
+        }
+        self.complete(prompt, model, temperature).await
+    }
+
+    /// Like `complete_with_images`, asking for `n` alternative completions
+    /// at once (returned in `choices`). Providers that can only return one
+    /// reject `n > 1`.
+    async fn complete_choices(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+        n: u32,
+    ) -> Result<CompletionResult> {
+        single_choice_only(n)?;
+        self.complete_with_images(prompt, images, model, temperature)
+            .await
+    }
+
+    /// Like `complete_choices`, with stop sequences, penalties and a request
+    /// ID. Providers ignore whatever they don't support.
+    async fn complete_with_params(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+        n: u32,
+        _params: &CompletionParams,
+    ) -> Result<CompletionResult> {
+        self.complete_choices(prompt, images, model, temperature, n)
+            .await
+    }
+
+    /// The name the provider registered under, for one that isn't known by
+    /// a `defaults.provider` name (a plugin).
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// What the provider reports it supports, for one missing from
+    /// `PROVIDERS` (a plugin).
+    fn capabilities(&self) -> Option<ProviderCapabilities> {
+        None
+    }
+}
+
+/// An image input, as given in the config.
+enum ImageSource<'a> {
+    Url(&'a str),
+    Base64 { media_type: &'a str, data: &'a str },
+}
+
+impl<'a> ImageSource<'a> {
+    /// `http(s)://` URLs are passed by reference; `data:` URIs and bare
+    /// base64 are sent inline (a bare payload's type is sniffed from its
+    /// first bytes, defaulting to PNG).
+    fn parse(image: &'a str) -> Self {
+        if image.starts_with("http://") || image.starts_with("https://") {
+            return ImageSource::Url(image);
+        }
+        if let Some((media_type, data)) = image
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(";base64,"))
+        {
+            return ImageSource::Base64 { media_type, data };
+        }
+        let media_type = match image.get(..4) {
+            Some("/9j/") => "image/jpeg",
+            Some("R0lG") => "image/gif",
+            Some("UklG") => "image/webp",
+            _ => "image/png",
+        };
+        ImageSource::Base64 {
+            media_type,
+            data: image,
+        }
+    }
+
+    fn to_url(&self) -> String {
+        match self {
+            ImageSource::Url(url) => url.to_string(),
+            ImageSource::Base64 { media_type, data } => {
+                format!("data:{};base64,{}", media_type, data)
+            }
+        }
+    }
 }
 
 // ─── OpenAI ──────────────────────────────────────────────────────────────────
@@ -46,7 +273,7 @@ impl OpenAiProvider {
             .unwrap_or_else(|_| "https://api.openai.com".to_string());
         Ok(Self {
             api_key,
-            client: Client::new(),
+            client: http_client(),
             base_url,
         })
     }
@@ -55,7 +282,7 @@ impl OpenAiProvider {
     pub fn with_base_url(api_key: String, base_url: String) -> Self {
         Self {
             api_key,
-            client: Client::new(),
+            client: http_client(),
             base_url,
         }
     }
@@ -69,41 +296,138 @@ impl LlmProvider for OpenAiProvider {
         model: &str,
         temperature: f64,
     ) -> Result<CompletionResult> {
-        let body = json!({
+        self.complete_with_images(prompt, &[], model, temperature)
+            .await
+    }
+
+    async fn complete_with_images(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+    ) -> Result<CompletionResult> {
+        self.complete_choices(prompt, images, model, temperature, 1)
+            .await
+    }
+
+    async fn complete_choices(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+        n: u32,
+    ) -> Result<CompletionResult> {
+        self.complete_with_params(
+            prompt,
+            images,
+            model,
+            temperature,
+            n,
+            &CompletionParams::default(),
+        )
+        .await
+    }
+
+    async fn complete_with_params(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+        n: u32,
+        params: &CompletionParams,
+    ) -> Result<CompletionResult> {
+        let content = if images.is_empty() {
+            json!(prompt)
+        } else {
+            let mut parts = vec![json!({"type": "text", "text": prompt})];
+            parts.extend(images.iter().map(|image| {
+                json!({
+                    "type": "image_url",
+                    "image_url": {"url": ImageSource::parse(image).to_url()},
+                })
+            }));
+            json!(parts)
+        };
+        let mut body = json!({
             "model": model,
-            "messages": [{"role": "user", "content": prompt}],
-            "temperature": temperature,
+            "messages": [{"role": "user", "content": content}],
         });
+        if !params.omit_temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if n > 1 {
+            body["n"] = json!(n);
+        }
+        if !params.stop.is_empty() {
+            body["stop"] = json!(params.stop);
+        }
+        if let Some(penalty) = params.frequency_penalty {
+            body["frequency_penalty"] = json!(penalty);
+        }
+        if let Some(penalty) = params.presence_penalty {
+            body["presence_penalty"] = json!(penalty);
+        }
+        if let Some(max_tokens) = params.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+        }
 
-        let resp = self
+        let request = self
             .client
             .post(format!("{}/v1/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&body)
-            .send()
-            .await?;
+            .header("Authorization", format!("Bearer {}", self.api_key));
+        let resp = with_request_id(request, params).json(&body).send().await?;
 
         let status = resp.status();
         let text = resp.text().await?;
 
         if !status.is_success() {
-            return Err(anyhow::anyhow!("OpenAI API error ({}): {}", status, text));
+            return Err(
+                ApiError::new(status, format!("OpenAI API error ({}): {}", status, text)).into(),
+            );
         }
 
         let json: serde_json::Value = serde_json::from_str(&text)?;
         let content = json["choices"][0]["message"]["content"]
             .as_str()
             .ok_or_else(|| anyhow::anyhow!("Unexpected OpenAI response format: {}", text))?;
+        let choices = if n > 1 {
+            json["choices"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|choice| {
+                    choice["message"]["content"]
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("Unexpected OpenAI response format: {}", text)
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
 
         let usage = TokenUsage {
             prompt_tokens: json["usage"]["prompt_tokens"].as_u64().unwrap_or(0) as u32,
             completion_tokens: json["usage"]["completion_tokens"].as_u64().unwrap_or(0) as u32,
             total_tokens: json["usage"]["total_tokens"].as_u64().unwrap_or(0) as u32,
+            reasoning_tokens: json["usage"]["completion_tokens_details"]["reasoning_tokens"]
+                .as_u64()
+                .unwrap_or(0) as u32,
         };
 
         Ok(CompletionResult {
             text: content.to_string(),
             usage,
+            thinking: None,
+            choices,
+            finish_reason: json["choices"][0]["finish_reason"]
+                .as_str()
+                .map(str::to_string),
         })
     }
 }
@@ -113,17 +437,51 @@ impl LlmProvider for OpenAiProvider {
 pub struct AnthropicProvider {
     api_key: String,
     client: Client,
+    base_url: String,
+    /// Extended-thinking budget; thinking is off when `None`
+    thinking_budget: Option<u32>,
 }
 
 impl AnthropicProvider {
+    /// Reads `ANTHROPIC_API_KEY`, plus optional `ANTHROPIC_BASE_URL` and
+    /// `ANTHROPIC_THINKING_BUDGET` (token budget that enables extended thinking).
     pub fn new() -> Result<Self> {
         let api_key = std::env::var("ANTHROPIC_API_KEY")
             .map_err(|_| anyhow::anyhow!("ANTHROPIC_API_KEY not set in environment"))?;
+        let base_url = std::env::var("ANTHROPIC_BASE_URL")
+            .unwrap_or_else(|_| "https://api.anthropic.com".to_string());
+        let thinking_budget = match std::env::var("ANTHROPIC_THINKING_BUDGET") {
+            Ok(v) => Some(v.parse().map_err(|_| {
+                anyhow::anyhow!(
+                    "ANTHROPIC_THINKING_BUDGET must be a token count, got '{}'",
+                    v
+                )
+            })?),
+            Err(_) => None,
+        };
         Ok(Self {
             api_key,
-            client: Client::new(),
+            client: http_client(),
+            base_url,
+            thinking_budget,
         })
     }
+
+    /// Create a provider with a custom base URL (useful for testing with mock servers).
+    pub fn with_base_url(api_key: String, base_url: String) -> Self {
+        Self {
+            api_key,
+            client: http_client(),
+            base_url,
+            thinking_budget: None,
+        }
+    }
+
+    /// Enable extended thinking with the given token budget.
+    pub fn with_thinking_budget(mut self, budget_tokens: u32) -> Self {
+        self.thinking_budget = Some(budget_tokens);
+        self
+    }
 }
 
 #[async_trait]
@@ -134,53 +492,533 @@ impl LlmProvider for AnthropicProvider {
         model: &str,
         temperature: f64,
     ) -> Result<CompletionResult> {
-        let body = json!({
+        self.complete_with_images(prompt, &[], model, temperature)
+            .await
+    }
+
+    async fn complete_with_images(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+    ) -> Result<CompletionResult> {
+        self.complete_with_params(
+            prompt,
+            images,
+            model,
+            temperature,
+            1,
+            &CompletionParams::default(),
+        )
+        .await
+    }
+
+    async fn complete_with_params(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+        n: u32,
+        params: &CompletionParams,
+    ) -> Result<CompletionResult> {
+        single_choice_only(n)?;
+        // Images go before the text, as the API docs recommend
+        let content = if images.is_empty() {
+            json!(prompt)
+        } else {
+            let mut blocks: Vec<serde_json::Value> = images
+                .iter()
+                .map(|image| match ImageSource::parse(image) {
+                    ImageSource::Url(url) => json!({
+                        "type": "image",
+                        "source": {"type": "url", "url": url},
+                    }),
+                    ImageSource::Base64 { media_type, data } => json!({
+                        "type": "image",
+                        "source": {"type": "base64", "media_type": media_type, "data": data},
+                    }),
+                })
+                .collect();
+            blocks.push(json!({"type": "text", "text": prompt}));
+            json!(blocks)
+        };
+        let max_tokens = params.max_tokens.unwrap_or(DEFAULT_ANTHROPIC_MAX_TOKENS);
+        let mut body = json!({
             "model": model,
-            "max_tokens": 1024,
-            "messages": [{"role": "user", "content": prompt}],
-            "temperature": temperature,
+            "max_tokens": max_tokens,
+            "messages": [{"role": "user", "content": content}],
         });
+        match self.thinking_budget {
+            // Thinking requires the default temperature and room for the
+            // answer on top of the budget.
+            Some(budget) => {
+                body["max_tokens"] = json!(budget + max_tokens);
+                body["thinking"] = json!({"type": "enabled", "budget_tokens": budget});
+            }
+            None if !params.omit_temperature => body["temperature"] = json!(temperature),
+            None => {}
+        }
 
-        let resp = self
+        let request = self
             .client
-            .post("https://api.anthropic.com/v1/messages")
+            .post(format!("{}/v1/messages", self.base_url))
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&body)
-            .send()
-            .await?;
+            .header("content-type", "application/json");
+        let resp = with_request_id(request, params).json(&body).send().await?;
 
         let status = resp.status();
         let text = resp.text().await?;
 
         if !status.is_success() {
-            return Err(anyhow::anyhow!(
-                "Anthropic API error ({}): {}",
+            return Err(ApiError::new(
                 status,
-                text
-            ));
+                format!("Anthropic API error ({}): {}", status, text),
+            )
+            .into());
         }
 
         let json: serde_json::Value = serde_json::from_str(&text)?;
-        let content = json["content"][0]["text"]
-            .as_str()
+        let blocks = json["content"]
+            .as_array()
             .ok_or_else(|| anyhow::anyhow!("Unexpected Anthropic response format: {}", text))?;
 
+        // The answer is the text blocks; thinking blocks are kept apart so
+        // assertions don't see them by default.
+        let mut answer = Vec::new();
+        let mut thinking = Vec::new();
+        for block in blocks {
+            match block["type"].as_str() {
+                Some("text") => answer.extend(block["text"].as_str()),
+                Some("thinking") => thinking.extend(block["thinking"].as_str()),
+                _ => {}
+            }
+        }
+        if answer.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Unexpected Anthropic response format: {}",
+                text
+            ));
+        }
+
         let usage = TokenUsage {
             prompt_tokens: json["usage"]["input_tokens"].as_u64().unwrap_or(0) as u32,
             completion_tokens: json["usage"]["output_tokens"].as_u64().unwrap_or(0) as u32,
             total_tokens: json["usage"]["input_tokens"].as_u64().unwrap_or(0) as u32
                 + json["usage"]["output_tokens"].as_u64().unwrap_or(0) as u32,
+            // Thinking is billed as output tokens without a separate count
+            reasoning_tokens: 0,
         };
 
         Ok(CompletionResult {
-            text: content.to_string(),
+            text: answer.join(""),
+            usage,
+            thinking: (!thinking.is_empty()).then(|| thinking.join("\n\n")),
+            choices: Vec::new(),
+            finish_reason: json["stop_reason"].as_str().map(str::to_string),
+        })
+    }
+}
+
+// ─── Vertex AI (Google Cloud) ────────────────────────────────────────────────
+
+/// OAuth scope Vertex AI access tokens are requested for.
+const VERTEX_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+/// Google's OAuth token endpoint, for credentials that don't name their own.
+const GOOGLE_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Region used when `VERTEX_REGION` isn't set.
+pub const DEFAULT_VERTEX_REGION: &str = "us-central1";
+
+/// Fetch a new access token this long before the current one expires.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Gemini models on Vertex AI, called at
+/// `https://{region}-aiplatform.googleapis.com/v1/projects/{project}/locations/{region}/publishers/google/models/{model}:generateContent`.
+///
+/// Requests carry an OAuth access token: a fixed `VERTEX_ACCESS_TOKEN`
+/// (e.g. from `gcloud auth print-access-token`), or one obtained with the
+/// `GOOGLE_APPLICATION_CREDENTIALS` file, either a service-account key (a
+/// signed JWT is exchanged at its `token_uri`) or the credentials written by
+/// `gcloud auth application-default login`. Obtained tokens are reused until
+/// shortly before they expire.
+pub struct VertexProvider {
+    client: Client,
+    base_url: String,
+    project: String,
+    region: String,
+    auth: VertexAuth,
+    /// Last obtained token and when to stop using it
+    token: tokio::sync::Mutex<Option<(String, Instant)>>,
+}
+
+enum VertexAuth {
+    Token(String),
+    ServiceAccount {
+        client_email: String,
+        key: jsonwebtoken::EncodingKey,
+        token_uri: String,
+    },
+    AuthorizedUser {
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+    },
+}
+
+/// A `GOOGLE_APPLICATION_CREDENTIALS` file, as far as getting a token goes.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GoogleCredentials {
+    ServiceAccount {
+        client_email: String,
+        private_key: String,
+        token_uri: Option<String>,
+    },
+    AuthorizedUser {
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+    },
+}
+
+impl VertexProvider {
+    /// Reads `VERTEX_PROJECT`, `VERTEX_REGION` (default `us-central1`) and
+    /// optional `VERTEX_BASE_URL`, then `VERTEX_ACCESS_TOKEN` or, failing
+    /// that, `GOOGLE_APPLICATION_CREDENTIALS`.
+    pub fn new() -> Result<Self> {
+        let project = std::env::var("VERTEX_PROJECT")
+            .map_err(|_| anyhow::anyhow!("VERTEX_PROJECT not set in environment"))?;
+        let region =
+            std::env::var("VERTEX_REGION").unwrap_or_else(|_| DEFAULT_VERTEX_REGION.to_string());
+        let base_url = std::env::var("VERTEX_BASE_URL").unwrap_or_else(|_| match region.as_str() {
+            "global" => "https://aiplatform.googleapis.com".to_string(),
+            region => format!("https://{}-aiplatform.googleapis.com", region),
+        });
+        if let Ok(token) = std::env::var("VERTEX_ACCESS_TOKEN") {
+            return Ok(Self::with_base_url(base_url, project, region, token));
+        }
+        let credentials = std::env::var("GOOGLE_APPLICATION_CREDENTIALS").map_err(|_| {
+            anyhow::anyhow!(
+                "Provider 'vertex' needs GOOGLE_APPLICATION_CREDENTIALS (a service-account key file) or VERTEX_ACCESS_TOKEN"
+            )
+        })?;
+        Self::with_base_url(base_url, project, region, String::new())
+            .with_credentials_file(Path::new(&credentials))
+    }
+
+    /// Create a provider with a custom base URL and a fixed access token
+    /// (useful for testing with mock servers).
+    pub fn with_base_url(base_url: String, project: String, region: String, token: String) -> Self {
+        Self {
+            client: http_client(),
+            base_url,
+            project,
+            region,
+            auth: VertexAuth::Token(token),
+            token: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Obtain access tokens with a service-account key or `gcloud`
+    /// application-default credentials instead of a fixed token.
+    pub fn with_credentials_file(mut self, path: &Path) -> Result<Self> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            anyhow::anyhow!("Invalid Google credentials file {}: {}", path.display(), e)
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        let credentials: GoogleCredentials =
+            serde_json::from_str(&text).map_err(|e| invalid(&e))?;
+        self.auth = match credentials {
+            GoogleCredentials::ServiceAccount {
+                client_email,
+                private_key,
+                token_uri,
+            } => VertexAuth::ServiceAccount {
+                client_email,
+                key: jsonwebtoken::EncodingKey::from_rsa_pem(private_key.as_bytes())
+                    .map_err(|e| invalid(&e))?,
+                token_uri: token_uri.unwrap_or_else(|| GOOGLE_TOKEN_URI.to_string()),
+            },
+            GoogleCredentials::AuthorizedUser {
+                client_id,
+                client_secret,
+                refresh_token,
+            } => VertexAuth::AuthorizedUser {
+                client_id,
+                client_secret,
+                refresh_token,
+            },
+        };
+        Ok(self)
+    }
+
+    /// A valid access token, fetching a new one when the last has (nearly)
+    /// expired. The lock keeps concurrent cases from all fetching at once.
+    async fn access_token(&self) -> Result<String> {
+        if let VertexAuth::Token(token) = &self.auth {
+            return Ok(token.clone());
+        }
+        let mut cached = self.token.lock().await;
+        if let Some((token, until)) = cached.as_ref() {
+            if Instant::now() < *until {
+                return Ok(token.clone());
+            }
+        }
+
+        let (token_uri, form) = match &self.auth {
+            VertexAuth::Token(_) => unreachable!("fixed tokens are returned above"),
+            VertexAuth::ServiceAccount {
+                client_email,
+                key,
+                token_uri,
+            } => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                let claims = json!({
+                    "iss": client_email,
+                    "scope": VERTEX_SCOPE,
+                    "aud": token_uri,
+                    "iat": now,
+                    "exp": now + 3600,
+                });
+                let assertion = jsonwebtoken::encode(
+                    &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+                    &claims,
+                    key,
+                )?;
+                (
+                    token_uri.as_str(),
+                    vec![
+                        (
+                            "grant_type",
+                            "urn:ietf:params:oauth:grant-type:jwt-bearer".to_string(),
+                        ),
+                        ("assertion", assertion),
+                    ],
+                )
+            }
+            VertexAuth::AuthorizedUser {
+                client_id,
+                client_secret,
+                refresh_token,
+            } => (
+                GOOGLE_TOKEN_URI,
+                vec![
+                    ("grant_type", "refresh_token".to_string()),
+                    ("client_id", client_id.clone()),
+                    ("client_secret", client_secret.clone()),
+                    ("refresh_token", refresh_token.clone()),
+                ],
+            ),
+        };
+
+        let resp = self.client.post(token_uri).form(&form).send().await?;
+        let status = resp.status();
+        let text = resp.text().await?;
+        if !status.is_success() {
+            return Err(ApiError::new(
+                status,
+                format!("Vertex AI token request failed ({}): {}", status, text),
+            )
+            .into());
+        }
+        let json: serde_json::Value = serde_json::from_str(&text)?;
+        let token = json["access_token"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected Google token response: {}", text))?
+            .to_string();
+        let lifetime = Duration::from_secs(json["expires_in"].as_u64().unwrap_or(3600));
+        *cached = Some((
+            token.clone(),
+            Instant::now() + lifetime.saturating_sub(TOKEN_REFRESH_MARGIN),
+        ));
+        Ok(token)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for VertexProvider {
+    async fn complete(
+        &self,
+        prompt: &str,
+        model: &str,
+        temperature: f64,
+    ) -> Result<CompletionResult> {
+        self.complete_choices(prompt, &[], model, temperature, 1)
+            .await
+    }
+
+    async fn complete_with_images(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+    ) -> Result<CompletionResult> {
+        self.complete_choices(prompt, images, model, temperature, 1)
+            .await
+    }
+
+    async fn complete_choices(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+        n: u32,
+    ) -> Result<CompletionResult> {
+        self.complete_with_params(
+            prompt,
+            images,
+            model,
+            temperature,
+            n,
+            &CompletionParams::default(),
+        )
+        .await
+    }
+
+    async fn complete_with_params(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+        n: u32,
+        params: &CompletionParams,
+    ) -> Result<CompletionResult> {
+        let mut parts = vec![json!({"text": prompt})];
+        parts.extend(images.iter().map(|image| match ImageSource::parse(image) {
+            ImageSource::Url(url) => json!({
+                "fileData": {"mimeType": image_type_from_url(url), "fileUri": url},
+            }),
+            ImageSource::Base64 { media_type, data } => json!({
+                "inlineData": {"mimeType": media_type, "data": data},
+            }),
+        }));
+        let mut config = json!({});
+        if !params.omit_temperature {
+            config["temperature"] = json!(temperature);
+        }
+        if n > 1 {
+            config["candidateCount"] = json!(n);
+        }
+        if !params.stop.is_empty() {
+            config["stopSequences"] = json!(params.stop);
+        }
+        if let Some(penalty) = params.frequency_penalty {
+            config["frequencyPenalty"] = json!(penalty);
+        }
+        if let Some(penalty) = params.presence_penalty {
+            config["presencePenalty"] = json!(penalty);
+        }
+        if let Some(max_tokens) = params.max_tokens {
+            config["maxOutputTokens"] = json!(max_tokens);
+        }
+        let body = json!({
+            "contents": [{"role": "user", "parts": parts}],
+            "generationConfig": config,
+        });
+
+        let token = self.access_token().await?;
+        let request = self
+            .client
+            .post(format!(
+                "{}/v1/projects/{}/locations/{}/publishers/google/models/{}:generateContent",
+                self.base_url, self.project, self.region, model
+            ))
+            .header("Authorization", format!("Bearer {}", token));
+        let resp = with_request_id(request, params).json(&body).send().await?;
+
+        let status = resp.status();
+        let text = resp.text().await?;
+
+        if !status.is_success() {
+            return Err(ApiError::new(
+                status,
+                format!("Vertex AI API error ({}): {}", status, text),
+            )
+            .into());
+        }
+
+        let json: serde_json::Value = serde_json::from_str(&text)?;
+        let candidates = json["candidates"].as_array().filter(|c| !c.is_empty());
+        let Some(candidates) = candidates else {
+            return Err(match json["promptFeedback"]["blockReason"].as_str() {
+                Some(reason) => anyhow::anyhow!("Vertex AI blocked the prompt ({})", reason),
+                None => anyhow::anyhow!("Unexpected Vertex AI response format: {}", text),
+            });
+        };
+
+        // Thought summaries (`"thought": true`) are kept apart from the answer
+        let candidate_text = |candidate: &serde_json::Value, thought: bool| -> String {
+            candidate["content"]["parts"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|part| part["thought"].as_bool().unwrap_or(false) == thought)
+                .filter_map(|part| part["text"].as_str())
+                .collect()
+        };
+        // Gemini's reasons are upper case (`MAX_TOKENS`); lower them to
+        // match the other providers
+        let finish_reason = candidates[0]["finishReason"]
+            .as_str()
+            .map(str::to_ascii_lowercase);
+        let answer = candidate_text(&candidates[0], false);
+        if answer.is_empty() && !is_truncation(finish_reason.as_deref()) {
+            return Err(anyhow::anyhow!(
+                "Vertex AI returned no text (finishReason: {})",
+                finish_reason.as_deref().unwrap_or("none")
+            ));
+        }
+        let thinking = candidate_text(&candidates[0], true);
+
+        let count = |field: &str| json["usageMetadata"][field].as_u64().unwrap_or(0) as u32;
+        let reasoning_tokens = count("thoughtsTokenCount");
+        let usage = TokenUsage {
+            prompt_tokens: count("promptTokenCount"),
+            // Thoughts are billed as output, as in OpenAI's count
+            completion_tokens: count("candidatesTokenCount") + reasoning_tokens,
+            total_tokens: count("totalTokenCount"),
+            reasoning_tokens,
+        };
+
+        Ok(CompletionResult {
+            text: answer,
             usage,
+            thinking: (!thinking.is_empty()).then_some(thinking),
+            choices: if n > 1 {
+                candidates
+                    .iter()
+                    .map(|candidate| candidate_text(candidate, false))
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            finish_reason,
         })
     }
 }
 
+/// MIME type Vertex AI needs for an image passed by URL, from its extension.
+fn image_type_from_url(url: &str) -> &'static str {
+    let path = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(url)
+        .to_ascii_lowercase();
+    match path.rsplit('.').next() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
 // ─── Webhook (Custom) ────────────────────────────────────────────────────────
 
 /// A custom provider that sends prompts to any HTTP endpoint.
@@ -188,21 +1026,110 @@ impl LlmProvider for AnthropicProvider {
 /// The webhook server must accept POST with JSON body:
 ///   `{"prompt": "...", "model": "...", "temperature": 0.7}`
 ///
+/// Cases with images add `"images": [...]`, passed through as configured.
+///
 /// And return JSON:
 ///   `{"text": "...", "usage": {"prompt_tokens": 10, "completion_tokens": 20, "total_tokens": 30}}`
 ///
-/// The `usage` field is optional.
+/// The `usage` and `finish_reason` fields are optional.
+///
+/// With polling enabled (`with_polling`, or `WEBHOOK_POLL_INTERVAL_MS`), a
+/// `202 Accepted` response carrying a `status_url` (or a `Location` header)
+/// is treated as a queued job: the URL is polled with GET until it returns
+/// the completion. A poll answering 202, or 200 with a `status` of
+/// `pending`, `queued`, `running` or `in_progress`, means "not done yet".
+/// Polling is bounded by the run's per-request timeout, and gives up after
+/// `max_polls` polls (`with_max_polls`, or `WEBHOOK_MAX_POLLS`).
 pub struct WebhookProvider {
     url: String,
     client: Client,
+    poll_interval: Option<Duration>,
+    max_polls: u32,
 }
 
+/// Polls of a queued webhook job before giving up, unless set otherwise.
+pub const DEFAULT_MAX_WEBHOOK_POLLS: u32 = 600;
+
 impl WebhookProvider {
     pub fn new(url: String) -> Self {
         Self {
             url,
-            client: Client::new(),
+            client: http_client(),
+            poll_interval: None,
+            max_polls: DEFAULT_MAX_WEBHOOK_POLLS,
+        }
+    }
+
+    /// Follow `202 Accepted` responses by polling their status URL.
+    pub fn with_polling(mut self, interval: Duration) -> Self {
+        self.poll_interval = Some(interval);
+        self
+    }
+
+    /// Give up on a queued job after this many polls.
+    pub fn with_max_polls(mut self, max_polls: u32) -> Self {
+        self.max_polls = max_polls;
+        self
+    }
+
+    async fn poll(&self, status_url: &str, interval: Duration) -> Result<CompletionResult> {
+        let mut last_status = StatusCode::ACCEPTED;
+        for _ in 0..self.max_polls {
+            tokio::time::sleep(interval).await;
+            let resp = self.client.get(status_url).send().await?;
+            let status = resp.status();
+            let text = resp.text().await?;
+
+            if status == StatusCode::ACCEPTED {
+                continue;
+            }
+            if !status.is_success() {
+                return Err(ApiError::new(
+                    status,
+                    format!(
+                        "Webhook error ({}) polling {}: {}",
+                        status, status_url, text
+                    ),
+                )
+                .into());
+            }
+            let json: serde_json::Value = serde_json::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("Webhook returned invalid JSON: {}", e))?;
+            let pending = matches!(
+                json["status"].as_str(),
+                Some("pending" | "queued" | "running" | "in_progress")
+            );
+            if pending && json["text"].is_null() {
+                last_status = status;
+                continue;
+            }
+            return parse_webhook_completion(&json, &text);
         }
+        Err(ApiError::new(
+            last_status,
+            format!(
+                "Webhook job at {} still pending after {} polls (WEBHOOK_MAX_POLLS)",
+                status_url, self.max_polls
+            ),
+        )
+        .into())
+    }
+
+    /// The URL to poll for a `202 Accepted` response, resolved against the
+    /// webhook URL when relative.
+    fn status_url(&self, location: Option<&str>, body: &str) -> Result<String> {
+        let from_body = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json["status_url"].as_str().map(str::to_string));
+        let url = from_body
+            .or_else(|| location.map(str::to_string))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Webhook returned 202 Accepted without a 'status_url' or Location header"
+                )
+            })?;
+        let base = reqwest::Url::parse(&self.url)?;
+        Ok(base.join(&url)?.to_string())
     }
 }
 
@@ -214,81 +1141,493 @@ impl LlmProvider for WebhookProvider {
         model: &str,
         temperature: f64,
     ) -> Result<CompletionResult> {
-        let body = json!({
+        self.complete_with_images(prompt, &[], model, temperature)
+            .await
+    }
+
+    async fn complete_with_images(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+    ) -> Result<CompletionResult> {
+        self.complete_with_params(
+            prompt,
+            images,
+            model,
+            temperature,
+            1,
+            &CompletionParams::default(),
+        )
+        .await
+    }
+
+    async fn complete_with_params(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+        n: u32,
+        params: &CompletionParams,
+    ) -> Result<CompletionResult> {
+        single_choice_only(n)?;
+        let mut body = json!({
             "prompt": prompt,
             "model": model,
-            "temperature": temperature,
         });
+        if !params.omit_temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if !images.is_empty() {
+            body["images"] = json!(images);
+        }
 
-        let resp = self
+        let request = self
             .client
             .post(&self.url)
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await?;
+            .header("Content-Type", "application/json");
+        let resp = with_request_id(request, params).json(&body).send().await?;
 
         let status = resp.status();
+        let location = resp
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
         let text = resp.text().await?;
 
         if !status.is_success() {
-            return Err(anyhow::anyhow!("Webhook error ({}): {}", status, text));
+            return Err(
+                ApiError::new(status, format!("Webhook error ({}): {}", status, text)).into(),
+            );
+        }
+        if let (StatusCode::ACCEPTED, Some(interval)) = (status, self.poll_interval) {
+            let status_url = self.status_url(location.as_deref(), &text)?;
+            return self.poll(&status_url, interval).await;
         }
 
         let json: serde_json::Value = serde_json::from_str(&text)
             .map_err(|e| anyhow::anyhow!("Webhook returned invalid JSON: {}", e))?;
+        parse_webhook_completion(&json, &text)
+    }
+}
+
+/// Read a completion from a webhook (or poll) response body.
+fn parse_webhook_completion(json: &serde_json::Value, text: &str) -> Result<CompletionResult> {
+    // Primary: {"text": "..."}
+    // Fallback: {"choices": [{"message": {"content": "..."}}]} (OpenAI-compatible)
+    let content = json["text"]
+        .as_str()
+        .or_else(|| json["choices"][0]["message"]["content"].as_str())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Webhook response must contain 'text' or 'choices[0].message.content': {}",
+                text
+            )
+        })?;
 
-        // Primary: {"text": "..."}
-        // Fallback: {"choices": [{"message": {"content": "..."}}]} (OpenAI-compatible)
-        let content = json["text"]
+    let usage = TokenUsage {
+        prompt_tokens: json["usage"]["prompt_tokens"].as_u64().unwrap_or(0) as u32,
+        completion_tokens: json["usage"]["completion_tokens"].as_u64().unwrap_or(0) as u32,
+        total_tokens: json["usage"]["total_tokens"].as_u64().unwrap_or(0) as u32,
+        reasoning_tokens: 0,
+    };
+
+    Ok(CompletionResult {
+        text: content.to_string(),
+        usage,
+        thinking: None,
+        choices: Vec::new(),
+        finish_reason: json["finish_reason"]
             .as_str()
-            .or_else(|| json["choices"][0]["message"]["content"].as_str())
-            .ok_or_else(|| {
-                anyhow::anyhow!(
-                    "Webhook response must contain 'text' or 'choices[0].message.content': {}",
-                    text
-                )
-            })?;
+            .or_else(|| json["choices"][0]["finish_reason"].as_str())
+            .map(str::to_string),
+    })
+}
 
-        let usage = TokenUsage {
-            prompt_tokens: json["usage"]["prompt_tokens"].as_u64().unwrap_or(0) as u32,
-            completion_tokens: json["usage"]["completion_tokens"].as_u64().unwrap_or(0) as u32,
-            total_tokens: json["usage"]["total_tokens"].as_u64().unwrap_or(0) as u32,
-        };
+// ─── Plugin (shared library) ────────────────────────────────────────────────
 
-        Ok(CompletionResult {
-            text: content.to_string(),
-            usage,
+/// Plugin ABI version this build of sentinel speaks. A plugin's
+/// `register_provider` must report it in `PluginVTable::abi_version`.
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+
+/// `PluginVTable::capabilities` flags, one per `ProviderCapabilities` field.
+pub const PLUGIN_SUPPORTS_TEMPERATURE: u32 = 1 << 0;
+pub const PLUGIN_RETURNS_USAGE: u32 = 1 << 1;
+pub const PLUGIN_SUPPORTS_SAMPLING: u32 = 1 << 2;
+
+/// What a provider plugin's `register_provider` symbol returns:
+///
+/// ```text
+/// #[no_mangle]
+/// pub extern "C" fn register_provider() -> PluginVTable
+/// ```
+///
+/// Only C types cross the boundary, so the plugin may be built with a
+/// different compiler version than sentinel. Requests and responses are
+/// NUL-terminated JSON in the webhook formats (see `WebhookProvider`), with
+/// `request_id` added when the case has one; a response of
+/// `{"error": "..."}` fails the call.
+///
+/// `name` is what the provider goes by in output and in a test's
+/// `provider:`, and `capabilities` is a set of `PLUGIN_*` flags saying which
+/// request parameters it accepts and whether it reports usage.
+///
+/// `complete` is called from several threads at once and must be
+/// thread-safe. A null return is an error. Every returned string goes back
+/// through `free_string`, and `destroy` is called once, after the last call.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginVTable {
+    pub abi_version: u32,
+    /// Opaque plugin state, passed back to every call
+    pub state: *mut c_void,
+    /// NUL-terminated, valid until `destroy`
+    pub name: *const c_char,
+    pub capabilities: u32,
+    pub complete: unsafe extern "C" fn(state: *mut c_void, request: *const c_char) -> *mut c_char,
+    pub free_string: unsafe extern "C" fn(response: *mut c_char),
+    pub destroy: unsafe extern "C" fn(state: *mut c_void),
+}
+
+/// A loaded plugin. Kept alive by every in-flight call, so a timed-out
+/// request can't outlive the library it's running in.
+struct LoadedPlugin {
+    vtable: PluginVTable,
+    // Dropped after `destroy` has run (fields drop after `Drop::drop`)
+    _library: libloading::Library,
+}
+
+// SAFETY: the plugin contract requires `complete` to be thread-safe.
+unsafe impl Send for LoadedPlugin {}
+unsafe impl Sync for LoadedPlugin {}
+
+impl LoadedPlugin {
+    fn call(&self, request: &str) -> Result<String> {
+        let request = CString::new(request)
+            .map_err(|_| anyhow::anyhow!("Plugin request contains a NUL byte"))?;
+        // SAFETY: per the plugin contract, `complete` returns null or a
+        // NUL-terminated string we own until it's passed to `free_string`.
+        unsafe {
+            let response = (self.vtable.complete)(self.vtable.state, request.as_ptr());
+            if response.is_null() {
+                return Err(anyhow::anyhow!("Plugin returned no response"));
+            }
+            let text = CStr::from_ptr(response).to_string_lossy().into_owned();
+            (self.vtable.free_string)(response);
+            Ok(text)
+        }
+    }
+}
+
+impl Drop for LoadedPlugin {
+    fn drop(&mut self) {
+        // SAFETY: nothing else holds the plugin once the last reference goes.
+        unsafe { (self.vtable.destroy)(self.vtable.state) }
+    }
+}
+
+/// A provider implemented in a shared library (`--provider-plugin`); see
+/// `PluginVTable` for the ABI.
+pub struct PluginProvider {
+    plugin: Arc<LoadedPlugin>,
+    name: String,
+    capabilities: ProviderCapabilities,
+}
+
+impl PluginProvider {
+    /// Load a plugin and call its `register_provider`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let describe = |e: &dyn std::fmt::Display| {
+            anyhow::anyhow!("Failed to load provider plugin {}: {}", path.display(), e)
+        };
+        // SAFETY: loading runs the library's initializers and trusts its
+        // `register_provider` signature; both are on whoever supplies the
+        // plugin, as with any native code.
+        let (library, vtable) = unsafe {
+            let library = libloading::Library::new(path).map_err(|e| describe(&e))?;
+            let register: libloading::Symbol<unsafe extern "C" fn() -> PluginVTable> = library
+                .get(b"register_provider")
+                .map_err(|e| describe(&e))?;
+            let vtable = register();
+            (library, vtable)
+        };
+        if vtable.abi_version != PLUGIN_ABI_VERSION {
+            return Err(describe(&format!(
+                "built for plugin ABI v{}, but this sentinel speaks v{}",
+                vtable.abi_version, PLUGIN_ABI_VERSION
+            )));
+        }
+        let plugin = Arc::new(LoadedPlugin {
+            vtable,
+            _library: library,
+        });
+        let name = if vtable.name.is_null() {
+            String::new()
+        } else {
+            // SAFETY: per the plugin contract, a non-null `name` is
+            // NUL-terminated and lives until `destroy`.
+            unsafe { CStr::from_ptr(vtable.name) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        if name.trim().is_empty() {
+            return Err(describe(&"register_provider returned no name"));
+        }
+        Ok(Self {
+            plugin,
+            name,
+            capabilities: ProviderCapabilities::from_plugin_flags(vtable.capabilities),
         })
     }
 }
 
+#[async_trait]
+impl LlmProvider for PluginProvider {
+    async fn complete(
+        &self,
+        prompt: &str,
+        model: &str,
+        temperature: f64,
+    ) -> Result<CompletionResult> {
+        self.complete_with_images(prompt, &[], model, temperature)
+            .await
+    }
+
+    async fn complete_with_images(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+    ) -> Result<CompletionResult> {
+        self.complete_with_params(
+            prompt,
+            images,
+            model,
+            temperature,
+            1,
+            &CompletionParams::default(),
+        )
+        .await
+    }
+
+    async fn complete_with_params(
+        &self,
+        prompt: &str,
+        images: &[String],
+        model: &str,
+        temperature: f64,
+        n: u32,
+        params: &CompletionParams,
+    ) -> Result<CompletionResult> {
+        single_choice_only(n)?;
+        let mut body = json!({
+            "prompt": prompt,
+            "model": model,
+        });
+        if !params.omit_temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if !images.is_empty() {
+            body["images"] = json!(images);
+        }
+        if let Some(ref id) = params.request_id {
+            body["request_id"] = json!(id);
+        }
+
+        // The plugin call blocks, so keep it off the async workers
+        let plugin = Arc::clone(&self.plugin);
+        let request = body.to_string();
+        let text = tokio::task::spawn_blocking(move || plugin.call(&request)).await??;
+
+        let json: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Plugin returned invalid JSON: {}", e))?;
+        if let Some(error) = json["error"].as_str() {
+            return Err(anyhow::anyhow!("Plugin error: {}", error));
+        }
+        parse_webhook_completion(&json, &text)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn capabilities(&self) -> Option<ProviderCapabilities> {
+        Some(self.capabilities)
+    }
+}
+
 // ─── Factory ─────────────────────────────────────────────────────────────────
 
+/// A provider `create_provider` knows how to build, for `sentinel list-providers`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ProviderSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Env vars that must be set before the provider can be created
+    pub required_env: &'static [&'static str],
+    /// Env vars of which at least one must be set as well (none if empty)
+    pub required_any: &'static [&'static str],
+    pub optional_env: &'static [&'static str],
+    pub capabilities: ProviderCapabilities,
+}
+
+/// What a provider accepts and reports. `validate_config` warns when a
+/// config sets something the provider can't use, and the runner leaves
+/// unsupported parameters out of its requests.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ProviderCapabilities {
+    /// Accepts `temperature` (narrowed per model by `provider_capabilities`)
+    pub supports_temperature: bool,
+    /// Reports token usage, which costs and budgets are computed from
+    pub returns_usage: bool,
+    /// Accepts stop sequences and frequency/presence penalties
+    pub supports_sampling: bool,
+}
+
+impl ProviderCapabilities {
+    /// Assumed for a provider not in `PROVIDERS`: nothing is stripped.
+    pub const ALL: Self = Self {
+        supports_temperature: true,
+        returns_usage: true,
+        supports_sampling: true,
+    };
+
+    /// Capabilities from `PluginVTable::capabilities` flags.
+    pub fn from_plugin_flags(flags: u32) -> Self {
+        Self {
+            supports_temperature: flags & PLUGIN_SUPPORTS_TEMPERATURE != 0,
+            returns_usage: flags & PLUGIN_RETURNS_USAGE != 0,
+            supports_sampling: flags & PLUGIN_SUPPORTS_SAMPLING != 0,
+        }
+    }
+}
+
+/// Capabilities of `provider` when serving `model`: the provider's own,
+/// minus temperature for models that reject it.
+pub fn provider_capabilities(provider: &str, model: &str) -> ProviderCapabilities {
+    let capabilities = PROVIDERS
+        .iter()
+        .find(|spec| spec.name == provider)
+        .map_or(ProviderCapabilities::ALL, |spec| spec.capabilities);
+    for_model(capabilities, model)
+}
+
+/// Capabilities of a created provider serving `model`: what it reports
+/// itself if it does (a plugin), else those of `name`.
+pub fn capabilities_of(
+    provider: &dyn LlmProvider,
+    name: &str,
+    model: &str,
+) -> ProviderCapabilities {
+    match provider.capabilities() {
+        Some(capabilities) => for_model(capabilities, model),
+        None => provider_capabilities(name, model),
+    }
+}
+
+/// `capabilities` minus temperature when `model` rejects it.
+fn for_model(mut capabilities: ProviderCapabilities, model: &str) -> ProviderCapabilities {
+    if model_info(model).is_some_and(|info| !info.supports_temperature) {
+        capabilities.supports_temperature = false;
+    }
+    capabilities
+}
+
+/// Every provider, in the same order as `config::KNOWN_PROVIDERS`.
+pub const PROVIDERS: &[ProviderSpec] = &[
+    ProviderSpec {
+        name: "openai",
+        description: "OpenAI chat completions (or any compatible endpoint)",
+        required_env: &["OPENAI_API_KEY"],
+        required_any: &[],
+        optional_env: &["OPENAI_BASE_URL"],
+        capabilities: ProviderCapabilities::ALL,
+    },
+    ProviderSpec {
+        name: "anthropic",
+        description: "Anthropic messages API",
+        required_env: &["ANTHROPIC_API_KEY"],
+        required_any: &[],
+        optional_env: &["ANTHROPIC_BASE_URL", "ANTHROPIC_THINKING_BUDGET"],
+        capabilities: ProviderCapabilities {
+            supports_temperature: true,
+            returns_usage: true,
+            supports_sampling: false,
+        },
+    },
+    ProviderSpec {
+        name: "vertex",
+        description: "Gemini on Google Cloud Vertex AI",
+        required_env: &["VERTEX_PROJECT"],
+        // A fixed token, or credentials to get one with
+        required_any: &["VERTEX_ACCESS_TOKEN", "GOOGLE_APPLICATION_CREDENTIALS"],
+        optional_env: &["VERTEX_REGION", "VERTEX_BASE_URL"],
+        capabilities: ProviderCapabilities::ALL,
+    },
+    ProviderSpec {
+        name: "webhook",
+        description: "POSTs the prompt to your own HTTP endpoint",
+        required_env: &["WEBHOOK_URL"],
+        required_any: &[],
+        optional_env: &["WEBHOOK_POLL_INTERVAL_MS", "WEBHOOK_MAX_POLLS"],
+        // Usage is read when the endpoint sends it, but nothing requires it
+        capabilities: ProviderCapabilities {
+            supports_temperature: true,
+            returns_usage: false,
+            supports_sampling: false,
+        },
+    },
+];
+
 /// Create a provider instance by name.
 /// For "webhook", pass the URL via `WEBHOOK_URL` env var or via `provider_url` in config.
 pub fn create_provider(name: &str) -> Result<Box<dyn LlmProvider>> {
     match name {
         "openai" => Ok(Box::new(OpenAiProvider::new()?)),
         "anthropic" => Ok(Box::new(AnthropicProvider::new()?)),
+        "vertex" => Ok(Box::new(VertexProvider::new()?)),
         "webhook" => {
             let url = std::env::var("WEBHOOK_URL").map_err(|_| {
                 anyhow::anyhow!(
                     "Provider 'webhook' requires WEBHOOK_URL env var (e.g. http://localhost:8080/complete)"
                 )
             })?;
-            Ok(Box::new(WebhookProvider::new(url)))
+            let mut provider = WebhookProvider::new(url);
+            if let Ok(ms) = std::env::var("WEBHOOK_POLL_INTERVAL_MS") {
+                let ms: u64 = ms.parse().map_err(|_| {
+                    anyhow::anyhow!("WEBHOOK_POLL_INTERVAL_MS must be a number of milliseconds")
+                })?;
+                provider = provider.with_polling(Duration::from_millis(ms));
+            }
+            if let Ok(max_polls) = std::env::var("WEBHOOK_MAX_POLLS") {
+                let max_polls: u32 = max_polls
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("WEBHOOK_MAX_POLLS must be a number"))?;
+                provider = provider.with_max_polls(max_polls);
+            }
+            Ok(Box::new(provider))
         }
         other => Err(anyhow::anyhow!(
-            "Unknown provider: '{}'. Known: openai, anthropic, webhook",
+            "Unknown provider: '{}'. Known: openai, anthropic, vertex, webhook",
             other
         )),
     }
 }
 
 /// Cost per 1M tokens for popular models (input, output) in USD.
+/// Unknown models cost nothing; see `model_pricing` to tell them apart.
 pub fn cost_per_million_tokens(model: &str) -> (f64, f64) {
-    match model {
+    model_pricing(model).unwrap_or((0.0, 0.0))
+}
+
+/// Cost per 1M tokens (input, output) in USD, or `None` for a model without
+/// pricing data.
+pub fn model_pricing(model: &str) -> Option<(f64, f64)> {
+    let rates = match model {
         // OpenAI
         "gpt-4o" => (2.50, 10.00),
         "gpt-4o-mini" => (0.15, 0.60),
@@ -302,8 +1641,68 @@ pub fn cost_per_million_tokens(model: &str) -> (f64, f64) {
         "claude-3-5-sonnet-20241022" | "claude-3-5-sonnet-latest" => (3.00, 15.00),
         "claude-3-5-haiku-20241022" | "claude-3-5-haiku-latest" => (0.80, 4.00),
         "claude-3-opus-20240229" | "claude-3-opus-latest" => (15.00, 75.00),
-        _ => (0.0, 0.0),
-    }
+        _ => return None,
+    };
+    Some(rates)
+}
+
+/// Request constraints for a known model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelInfo {
+    /// `false` for reasoning models that only accept the API default (1.0).
+    pub supports_temperature: bool,
+    /// Highest temperature the API accepts.
+    pub max_temperature: f64,
+    /// Maximum number of completion tokens the model can produce.
+    pub max_output_tokens: u32,
+    /// Whether the model accepts image inputs.
+    pub supports_vision: bool,
+}
+
+/// Temperature every provider uses when none is sent.
+pub const API_DEFAULT_TEMPERATURE: f64 = 1.0;
+
+/// Look up request constraints for popular models. Unknown models return `None`.
+pub fn model_info(model: &str) -> Option<ModelInfo> {
+    let (supports_temperature, max_temperature, max_output_tokens, supports_vision) = match model {
+        // OpenAI
+        "gpt-4o" | "gpt-4o-mini" => (true, 2.0, 16_384, true),
+        "gpt-4-turbo" | "gpt-4-turbo-preview" => (true, 2.0, 4_096, true),
+        "gpt-4" => (true, 2.0, 8_192, false),
+        "gpt-3.5-turbo" => (true, 2.0, 4_096, false),
+        "o1" => (false, 1.0, 100_000, true),
+        "o3-mini" => (false, 1.0, 100_000, false),
+        "o1-mini" => (false, 1.0, 65_536, false),
+        // Anthropic
+        "claude-3-5-sonnet-20241022" | "claude-3-5-sonnet-latest" => (true, 1.0, 8_192, true),
+        "claude-3-5-haiku-20241022" | "claude-3-5-haiku-latest" => (true, 1.0, 8_192, true),
+        "claude-3-opus-20240229" | "claude-3-opus-latest" => (true, 1.0, 4_096, true),
+        _ => return None,
+    };
+    Some(ModelInfo {
+        supports_temperature,
+        max_temperature,
+        max_output_tokens,
+        supports_vision,
+    })
+}
+
+/// Infer the provider serving a model from its name (`gpt-*` → openai,
+/// `claude-*` → anthropic). Returns `None` when the name doesn't say.
+pub fn provider_for_model(model: &str) -> Option<&'static str> {
+    const PREFIXES: &[(&str, &str)] = &[
+        ("gpt-", "openai"),
+        ("chatgpt-", "openai"),
+        ("o1", "openai"),
+        ("o3", "openai"),
+        ("o4", "openai"),
+        ("claude-", "anthropic"),
+        ("gemini-", "vertex"),
+    ];
+    PREFIXES
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, provider)| *provider)
 }
 
 /// Calculate cost in USD for a given model and token usage.
@@ -1,8 +1,60 @@
-use crate::runner::CaseResult;
+use crate::config::{self, Config};
+use crate::runner::{self, CaseResult, CostLevel, CostThresholds};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::io::Write;
+use std::net::SocketAddr;
 use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Knobs for `generate_report`.
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    pub cost_thresholds: CostThresholds,
+    /// Add a table of the N most expensive cases
+    pub top_cost: Option<usize>,
+    /// Have the page reload itself every N seconds (for `watch --serve`)
+    pub auto_refresh: Option<u32>,
+    /// Show the run's manifest in the footer
+    pub manifest: Option<RunManifest>,
+}
 
 /// Generate a self-contained HTML report file from test results.
-pub fn generate_report(results: &[CaseResult], output_path: &Path) -> anyhow::Result<String> {
+///
+/// Rows are streamed to the file as they're rendered, so a suite with tens
+/// of thousands of cases never holds the whole page in memory.
+pub fn generate_report(
+    results: &[CaseResult],
+    output_path: &Path,
+    options: &ReportOptions,
+) -> anyhow::Result<String> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output_path)?);
+    write_report(results, options, &mut out)?;
+    out.flush()?;
+
+    Ok(output_path.display().to_string())
+}
+
+/// Render the HTML report for `results` without writing it anywhere.
+pub fn render_report(results: &[CaseResult], options: &ReportOptions) -> String {
+    let mut html = Vec::new();
+    write_report(results, options, &mut html).expect("writing to a Vec can't fail");
+    String::from_utf8(html).expect("report HTML is UTF-8")
+}
+
+/// Rows rendered (in parallel) per write, bounding memory on huge suites.
+const REPORT_ROW_BATCH: usize = 1024;
+
+/// Write the HTML report for `results` to `out`. Rows are rendered in
+/// parallel batches (on rayon's pool, sized by `RAYON_NUM_THREADS`) and
+/// written in order.
+fn write_report(
+    results: &[CaseResult],
+    options: &ReportOptions,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
     let total = results.len();
     let passed = results.iter().filter(|r| r.passed).count();
     let failed = total - passed;
@@ -13,68 +65,65 @@ pub fn generate_report(results: &[CaseResult], output_path: &Path) -> anyhow::Re
     };
     let total_cost: f64 = results.iter().map(|r| r.cost_usd).sum();
     let total_tokens: u32 = results.iter().map(|r| r.tokens.total_tokens).sum();
+    let total_retries: u32 = results.iter().map(|r| r.retries).sum();
     let avg_latency: u64 = if total > 0 {
         results.iter().map(|r| r.latency_ms).sum::<u64>() / total as u64
     } else {
         0
     };
 
-    let mut rows = String::new();
-    for r in results {
-        let status_class = if r.passed { "pass" } else { "fail" };
-        let status_text = if r.passed { "PASS" } else { "FAIL" };
-
-        let mut assertion_html = String::new();
-        for a in &r.assertions {
-            let icon = if a.passed { "✓" } else { "✗" };
-            let cls = if a.passed { "pass" } else { "fail" };
-            assertion_html.push_str(&format!(
-                "<div class=\"assertion {}\"><span class=\"icon\">{}</span> <strong>{}</strong> — {}</div>",
-                cls, icon, html_escape(&a.label), html_escape(&a.detail)
-            ));
-        }
-
-        if let Some(ref err) = r.error {
-            assertion_html.push_str(&format!(
-                "<div class=\"assertion fail\"><span class=\"icon\">✗</span> <strong>error</strong> — {}</div>",
-                html_escape(err)
-            ));
+    let mut top_cost_html = String::new();
+    if let Some(n) = options.top_cost {
+        let top = runner::top_cost(results, n);
+        if !top.is_empty() {
+            top_cost_html.push_str(
+                "<h2>Most Expensive Cases</h2>\n  <table class=\"top-cost\">\n    <thead><tr><th>#</th><th>Test ID</th><th>Input</th><th>Cost</th><th>Share</th></tr></thead>\n    <tbody>\n",
+            );
+            for (i, r) in top.iter().enumerate() {
+                let share = if total_cost > 0.0 {
+                    r.cost_usd / total_cost * 100.0
+                } else {
+                    0.0
+                };
+                top_cost_html.push_str(&format!(
+                    "      <tr><td>{}</td><td class=\"test-id\">{}</td><td class=\"input\">{}</td><td class=\"num\">${:.6}</td><td class=\"num\">{:.0}%</td></tr>\n",
+                    i + 1,
+                    html_escape(&r.test_id),
+                    html_escape(&r.input_label),
+                    r.cost_usd,
+                    share
+                ));
+            }
+            top_cost_html.push_str("    </tbody>\n  </table>\n");
         }
-
-        let cost_str = if r.cost_usd > 0.0 {
-            format!("${:.6}", r.cost_usd)
-        } else {
-            "—".to_string()
-        };
-
-        rows.push_str(&format!(
-            r#"<tr class="{}">
-  <td><span class="badge {}">{}</span></td>
-  <td class="test-id">{}</td>
-  <td class="input">{}</td>
-  <td class="num">{}</td>
-  <td class="num">{}</td>
-  <td class="num">{}</td>
-  <td class="assertions">{}</td>
-</tr>"#,
-            status_class,
-            status_class,
-            status_text,
-            html_escape(&r.test_id),
-            html_escape(&r.input_label),
-            r.latency_ms,
-            r.tokens.total_tokens,
-            cost_str,
-            assertion_html,
-        ));
     }
 
-    let html = format!(
+    let manifest_html = options
+        .manifest
+        .as_ref()
+        .map(|m| {
+            let mut parts = vec![
+                format!("config {}", m.config_hash),
+                format!("{}: {}", m.provider, m.models.join(", ")),
+            ];
+            if let Some(ref commit) = m.git_commit {
+                parts.push(format!("commit {}", &commit[..commit.len().min(12)]));
+            }
+            format!("<br>{}", html_escape(&parts.join(" · ")))
+        })
+        .unwrap_or_default();
+    let refresh_meta = options
+        .auto_refresh
+        .map(|secs| format!("<meta http-equiv=\"refresh\" content=\"{}\">\n", secs))
+        .unwrap_or_default();
+
+    write!(
+        out,
         r##"<!DOCTYPE html>
 <html lang="en">
 <head>
 <meta charset="UTF-8">
-<meta name="viewport" content="width=device-width, initial-scale=1.0">
+{refresh_meta}<meta name="viewport" content="width=device-width, initial-scale=1.0">
 <title>Prompt Sentinel — Test Report</title>
 <style>
   :root {{
@@ -170,10 +219,17 @@ pub fn generate_report(results: &[CaseResult], output_path: &Path) -> anyhow::Re
   .test-id {{ font-weight: 600; }}
   .input {{ color: var(--text-dim); font-size: 0.82rem; }}
   .num {{ text-align: right; font-variant-numeric: tabular-nums; }}
+  .cost-warn {{ color: var(--yellow); font-weight: 600; }}
+  .cost-crit {{ color: var(--fail); font-weight: 700; }}
+  tr.retried td.retries {{ color: var(--yellow); font-weight: 700; }}
+  tr.retried td:first-child {{ box-shadow: inset 3px 0 var(--yellow); }}
+  h2 {{ font-size: 1rem; margin-bottom: 0.8rem; color: var(--text-dim); }}
+  table.top-cost {{ margin-bottom: 2rem; }}
   .assertions {{ font-size: 0.82rem; }}
   .assertion {{ margin: 0.15rem 0; }}
   .assertion.pass .icon {{ color: var(--pass); }}
   .assertion.fail .icon {{ color: var(--fail); }}
+  .assertion.skip {{ color: var(--text-dim); }}
   footer {{
     margin-top: 2rem; padding-top: 1rem;
     border-top: 1px solid var(--border);
@@ -196,10 +252,13 @@ pub fn generate_report(results: &[CaseResult], output_path: &Path) -> anyhow::Re
     <div class="stat accent"><div class="value">{avg_latency}ms</div><div class="label">Avg Latency</div></div>
     <div class="stat yellow"><div class="value">{total_tokens}</div><div class="label">Total Tokens</div></div>
     <div class="stat accent"><div class="value">${total_cost:.6}</div><div class="label">Total Cost</div></div>
+    <div class="stat yellow"><div class="value">{total_retries}</div><div class="label">Retries</div></div>
   </div>
 
   <div class="bar-track"><div class="bar-fill" style="width:{pass_pct}%"></div></div>
 
+  {top_cost_html}
+
   <table>
     <thead>
       <tr>
@@ -209,34 +268,444 @@ pub fn generate_report(results: &[CaseResult], output_path: &Path) -> anyhow::Re
         <th>Latency</th>
         <th>Tokens</th>
         <th>Cost</th>
+        <th>Retries</th>
         <th>Assertions</th>
       </tr>
     </thead>
     <tbody>
-      {rows}
+      "##,
+        timestamp = chrono_now(),
+        passed = passed,
+        failed = failed,
+        avg_latency = avg_latency,
+        total_tokens = total_tokens,
+        total_cost = total_cost,
+        total_retries = total_retries,
+        pass_pct = pass_pct,
+        top_cost_html = top_cost_html,
+        refresh_meta = refresh_meta,
+    )?;
+
+    for batch in results.chunks(REPORT_ROW_BATCH) {
+        let rows: Vec<String> = batch.par_iter().map(|r| render_row(r, options)).collect();
+        for row in rows {
+            out.write_all(row.as_bytes())?;
+        }
+    }
+
+    write!(
+        out,
+        r##"
     </tbody>
   </table>
 
   <footer>
-    Prompt Sentinel v0.1.0 · {total} test(s) · {pass_pct}% pass rate
+    Prompt Sentinel v{version} · {total} test(s) · {pass_pct}% pass rate{manifest_html}
   </footer>
 </div>
 </body>
 </html>"##,
-        timestamp = chrono_now(),
-        passed = passed,
-        failed = failed,
-        avg_latency = avg_latency,
-        total_tokens = total_tokens,
-        total_cost = total_cost,
         pass_pct = pass_pct,
-        rows = rows,
         total = total,
-    );
+        version = env!("CARGO_PKG_VERSION"),
+        manifest_html = manifest_html,
+    )
+}
 
-    std::fs::write(output_path, &html)?;
+/// One `<tr>` of the results table.
+fn render_row(r: &CaseResult, options: &ReportOptions) -> String {
+    let status_class = if r.passed { "pass" } else { "fail" };
+    let row_class = if r.retries > 0 {
+        format!("{} retried", status_class)
+    } else {
+        status_class.to_string()
+    };
+    let status_text = if r.passed { "PASS" } else { "FAIL" };
 
-    Ok(output_path.display().to_string())
+    let mut assertion_html = String::new();
+    for a in &r.assertions {
+        let (icon, cls) = if a.skipped {
+            ("–", "skip")
+        } else if a.passed {
+            ("✓", "pass")
+        } else {
+            ("✗", "fail")
+        };
+        assertion_html.push_str(&format!(
+            "<div class=\"assertion {}\"><span class=\"icon\">{}</span> <strong>{}</strong> — {}</div>",
+            cls, icon, html_escape(&a.label), html_escape(&a.detail)
+        ));
+    }
+
+    if let Some(ref err) = r.error {
+        assertion_html.push_str(&format!(
+            "<div class=\"assertion fail\"><span class=\"icon\">✗</span> <strong>error</strong> — {}</div>",
+            html_escape(err)
+        ));
+    }
+
+    let cost_str = if r.cost_usd > 0.0 {
+        format!("${:.6}", r.cost_usd)
+    } else {
+        "—".to_string()
+    };
+    let cost_class = match options.cost_thresholds.level(r.cost_usd) {
+        CostLevel::Normal => "num",
+        CostLevel::Warn => "num cost-warn",
+        CostLevel::Crit => "num cost-crit",
+    };
+    // Hovering the count lists why each retry happened
+    let retry_title = r
+        .retry_log
+        .iter()
+        .map(|e| format!("retry {} after {}ms ({})", e.attempt, e.delay_ms, e.reason))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"<tr class="{}">
+  <td><span class="badge {}">{}</span></td>
+  <td class="test-id">{}</td>
+  <td class="input" title="{}">{}</td>
+  <td class="num">{}</td>
+  <td class="num">{}</td>
+  <td class="{}">{}</td>
+  <td class="num retries" title="{}">{}</td>
+  <td class="assertions">{}</td>
+</tr>"#,
+        row_class,
+        status_class,
+        status_text,
+        html_escape(&r.test_id),
+        html_escape(r.prompt.as_deref().unwrap_or_default()),
+        html_escape(&r.input_label),
+        r.latency_ms,
+        r.tokens.total_tokens,
+        cost_class,
+        cost_str,
+        html_escape(&retry_title),
+        r.retries,
+        assertion_html,
+    )
+}
+
+// ─── Run Manifest ────────────────────────────────────────────────────────────
+
+/// Default path for `sentinel run --manifest`.
+pub const DEFAULT_MANIFEST_PATH: &str = ".sentinel-manifest.json";
+
+/// What a run was made from: enough to trace archived results back to the
+/// exact config, binary and commit.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunManifest {
+    pub sentinel_version: String,
+    pub config_file: String,
+    /// `config::config_hash` of the resolved config (after profile and CLI
+    /// overrides)
+    pub config_hash: String,
+    pub provider: String,
+    /// Every model the tests use, sorted
+    pub models: Vec<String>,
+    /// Run start, RFC 3339 UTC
+    pub timestamp: String,
+    /// `HEAD` of the git checkout holding the config, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_commit: Option<String>,
+}
+
+impl RunManifest {
+    pub fn new(config: &Config, config_file: &str) -> Self {
+        let mut models: Vec<String> = config
+            .tests
+            .iter()
+            .map(|t| {
+                t.model
+                    .clone()
+                    .unwrap_or_else(|| config.defaults.model.clone())
+            })
+            .collect();
+        models.sort();
+        models.dedup();
+
+        let dir = match Path::new(config_file).parent() {
+            Some(dir) if config_file != "-" && !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        Self {
+            sentinel_version: env!("CARGO_PKG_VERSION").to_string(),
+            config_file: config_file.to_string(),
+            config_hash: config::config_hash(config),
+            provider: config.defaults.provider.clone(),
+            models,
+            timestamp: iso_now(),
+            git_commit: git_commit(dir),
+        }
+    }
+
+    /// Write the manifest as pretty JSON.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+fn git_commit(dir: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(dir)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let commit = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!commit.is_empty()).then_some(commit)
+}
+
+// ─── JSON Report ─────────────────────────────────────────────────────────────
+
+/// Version of the `RunReport` JSON layout, bumped on breaking changes.
+pub const JSON_REPORT_VERSION: u32 = 1;
+
+/// The JSON written by `sentinel run --json` / `--json-out`: the results
+/// plus enough context to make an archived file self-describing.
+#[derive(Debug, Serialize)]
+pub struct RunReport<'a> {
+    pub version: u32,
+    /// Unique per run, for correlating archived reports and logs
+    pub run_id: String,
+    #[serde(flatten)]
+    pub manifest: &'a RunManifest,
+    pub summary: RunSummary,
+    pub results: &'a [CaseResult],
+}
+
+/// Aggregate counts for a `RunReport`.
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub total_tokens: u32,
+    pub cost_usd: f64,
+    /// Pass/fail counts per assertion type
+    pub assertion_stats: Vec<runner::AssertionTypeStats>,
+}
+
+impl<'a> RunReport<'a> {
+    pub fn new(manifest: &'a RunManifest, results: &'a [CaseResult]) -> Self {
+        let stats = runner::RunStats::new(results);
+        Self {
+            version: JSON_REPORT_VERSION,
+            run_id: new_run_id(),
+            manifest,
+            summary: RunSummary {
+                total: stats.total,
+                passed: stats.passed,
+                failed: stats.failed,
+                total_tokens: stats.tokens.total_tokens,
+                cost_usd: stats.cost_usd,
+                assertion_stats: runner::assertion_pass_rates(results),
+            },
+            results,
+        }
+    }
+}
+
+/// Serialize a report for `--json` / `--json-out`: pretty-printed, or on a
+/// single line with `compact`. Keys come out in struct declaration order
+/// (and sorted for maps), so two runs diff cleanly either way.
+pub fn to_json<T: Serialize + ?Sized>(value: &T, compact: bool) -> serde_json::Result<String> {
+    if compact {
+        serde_json::to_string(value)
+    } else {
+        serde_json::to_string_pretty(value)
+    }
+}
+
+/// One line of `sentinel run --format jsonl`: a `result` line per case as it
+/// completes, then a single `summary` line once the run is over.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum JsonLine<'a> {
+    Result(&'a CaseResult),
+    /// A `RunReport` without its `results`, which were already streamed
+    Summary {
+        version: u32,
+        run_id: String,
+        #[serde(flatten)]
+        manifest: &'a RunManifest,
+        summary: RunSummary,
+    },
+}
+
+impl<'a> JsonLine<'a> {
+    pub fn summary(manifest: &'a RunManifest, results: &'a [CaseResult]) -> Self {
+        let report = RunReport::new(manifest, results);
+        JsonLine::Summary {
+            version: report.version,
+            run_id: report.run_id,
+            manifest: report.manifest,
+            summary: report.summary,
+        }
+    }
+
+    /// Compact single-line JSON, ready to print.
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Read the results back from a saved run: a `--json`/`--json-out` report,
+/// the bare array of `--json-legacy`, or the lines of `--format jsonl`.
+pub fn load_results(path: &Path) -> anyhow::Result<Vec<CaseResult>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let invalid = |e: serde_json::Error| {
+        anyhow::anyhow!("{} is not a sentinel results file: {}", path.display(), e)
+    };
+
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(&text) {
+        let results = match value {
+            serde_json::Value::Array(_) => value,
+            serde_json::Value::Object(mut report) if report.contains_key("results") => {
+                report.remove("results").unwrap_or_default()
+            }
+            // A single JSONL line parses as an object too
+            serde_json::Value::Object(_) => return load_json_lines(&text).map_err(invalid),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "{} is not a sentinel results file: expected a report object or an array of results",
+                    path.display()
+                ))
+            }
+        };
+        return serde_json::from_value(results).map_err(invalid);
+    }
+    load_json_lines(&text).map_err(invalid)
+}
+
+/// The `result` lines of `--format jsonl` output; other lines are skipped.
+fn load_json_lines(text: &str) -> serde_json::Result<Vec<CaseResult>> {
+    let mut results = Vec::new();
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let value: serde_json::Value = serde_json::from_str(line)?;
+        if value["type"] == "result" {
+            results.push(serde_json::from_value(value)?);
+        }
+    }
+    Ok(results)
+}
+
+/// Time-based ID plus the process ID, so concurrent runs don't collide.
+pub(crate) fn new_run_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("{:x}-{:x}", nanos, std::process::id())
+}
+
+// ─── Live Report Server ──────────────────────────────────────────────────────
+
+/// The latest rendered report, shared between the watch loop and the server.
+#[derive(Debug, Clone)]
+pub struct LiveReport {
+    html: Arc<RwLock<String>>,
+}
+
+impl Default for LiveReport {
+    fn default() -> Self {
+        Self {
+            html: Arc::new(RwLock::new(
+                "<!DOCTYPE html><html><head><meta http-equiv=\"refresh\" content=\"2\"></head>\
+                 <body>Waiting for the first run...</body></html>"
+                    .to_string(),
+            )),
+        }
+    }
+}
+
+impl LiveReport {
+    /// Replace the page served to new requests.
+    pub fn update(&self, html: String) {
+        *self.html.write().unwrap_or_else(|e| e.into_inner()) = html;
+    }
+
+    fn current(&self) -> String {
+        self.html.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// A minimal HTTP server that serves a `LiveReport` at `/`. It stops when
+/// dropped.
+pub struct ReportServer {
+    addr: SocketAddr,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl ReportServer {
+    /// Listen on `127.0.0.1:port` (0 picks a free port).
+    pub async fn bind(port: u16, live: LiveReport) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .await
+            .map_err(|e| anyhow::anyhow!("Could not listen on port {}: {}", port, e))?;
+        let addr = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                let live = live.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, &live).await;
+                });
+            }
+        });
+        Ok(Self { addr, handle })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop accepting connections.
+    pub fn shutdown(&self) {
+        self.handle.abort();
+    }
+}
+
+impl Drop for ReportServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+async fn handle_connection(
+    mut stream: tokio::net::TcpStream,
+    live: &LiveReport,
+) -> std::io::Result<()> {
+    // Only the request line matters; headers and body are ignored
+    let mut buf = [0u8; 4096];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, body) = if path == "/" || path == "/index.html" {
+        ("200 OK", live.current())
+    } else {
+        ("404 Not Found", "Not found".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
 }
 
 fn html_escape(s: &str) -> String {
@@ -248,16 +717,31 @@ fn html_escape(s: &str) -> String {
 
 fn chrono_now() -> String {
     // Simple ISO-ish timestamp without chrono dependency
+    let (y, m, d, hours, minutes, _) = utc_now();
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", y, m, d, hours, minutes)
+}
+
+/// RFC 3339 timestamp (UTC, whole seconds) for machine-readable output.
+fn iso_now() -> String {
+    let (y, m, d, hours, minutes, seconds) = utc_now();
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        y, m, d, hours, minutes, seconds
+    )
+}
+
+/// Current UTC time as (year, month, day, hour, minute, second).
+fn utc_now() -> (i64, i64, i64, u64, u64, u64) {
     let now = std::time::SystemTime::now();
     let secs = now
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
-    // Format as "YYYY-MM-DD HH:MM UTC" approximation
     let days = secs / 86400;
     let time_of_day = secs % 86400;
     let hours = time_of_day / 3600;
     let minutes = (time_of_day % 3600) / 60;
+    let seconds = time_of_day % 60;
 
     // Approximate year/month/day from epoch days
     let mut y = 1970i64;
@@ -285,7 +769,7 @@ fn chrono_now() -> String {
     }
     let d = remaining + 1;
 
-    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", y, m, d, hours, minutes)
+    (y, m, d, hours, minutes, seconds)
 }
 
 fn is_leap(y: i64) -> bool {
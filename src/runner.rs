@@ -1,32 +1,48 @@
-use crate::assertions::{check_assertion, AssertionResult};
-use crate::config::{render_prompt, AssertionKind, Config};
-use crate::providers::{self, LlmProvider, TokenUsage};
+use crate::assertions::{
+    check_assertion, compare_snapshot, diff_summary, AssertionContext, AssertionResult,
+    SnapshotBase, SnapshotComparison, SnapshotMode,
+};
+use crate::config::{
+    case_rows, eval_condition, render_prompt, resolve_temperature, AssertionKind, ChoiceMode,
+    Config, Defaults, Extract, NormalizeOutput, TestCase, TestDef,
+};
+use crate::providers::{self, CompletionParams, LlmProvider, ProviderCapabilities, TokenUsage};
 
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
-use serde::Serialize;
-use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::sync::Semaphore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
 use tokio::task::JoinHandle;
 use tokio::time::{self, Duration, Instant};
 
 /// Output verbosity level.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum Verbosity {
     /// Only show summary line (pass/fail counts)
     Quiet,
     /// Default: per-test status + assertions
+    #[default]
     Normal,
     /// Show everything including full LLM output
     Verbose,
 }
 
-/// The result of running a single test case.
-#[derive(Debug, Serialize)]
+/// The result of running a single test case. Deserializes from the JSON
+/// reports for `sentinel stats`; fields left out of the JSON stay empty.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CaseResult {
     pub test_id: String,
     pub input_label: String,
+    /// The case's input variables (JSON only)
+    #[serde(serialize_with = "crate::config::sorted")]
+    pub input: HashMap<String, String>,
     pub passed: bool,
     pub latency_ms: u64,
     pub assertions: Vec<AssertionDetail>,
@@ -35,271 +51,2937 @@ pub struct CaseResult {
     pub retries: u32,
     pub tokens: TokenUsage,
     pub cost_usd: f64,
-    #[serde(skip)]
-    #[allow(dead_code)]
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub model: String,
     /// Full LLM output (included in JSON, shown in --verbose)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub output: Option<String>,
+    /// Every choice's output when the test asks for `n > 1` (`output` is
+    /// the first)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    /// Prompt as sent: rendered, then wrapped by `--prompt-prefix`/`--prompt-suffix`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// `X-Request-Id` sent with the case's provider call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Why the provider stopped generating; `length` (OpenAI) or
+    /// `max_tokens` (Anthropic) means the output was cut off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    /// Snapshot file key, set only for cases with a snapshot assertion
+    #[serde(skip)]
+    pub snapshot_key: Option<String>,
+    /// 1-based run number when cases are repeated (`--repeat`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<u32>,
+    /// Each backed-off retry, in order (shown in --verbose)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub retry_log: Vec<RetryEvent>,
+    /// Reused from an earlier run because nothing about the case changed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Hash of everything that determines the case's result
+    #[serde(skip)]
+    pub fingerprint: Option<u64>,
+}
+
+/// One retry of a provider call after a transient failure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryEvent {
+    /// 1-based retry number
+    pub attempt: u32,
+    /// Total wait before the retry: the exponential backoff plus `jitter_ms`
+    pub delay_ms: u64,
+    /// Random part of the delay, so concurrent cases don't retry in lockstep
+    #[serde(default)]
+    pub jitter_ms: u64,
+    /// HTTP status or failure class that triggered the retry, e.g. `429`
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssertionDetail {
+    /// Assertion type (`contains`, `snapshot`, ...) or the check that
+    /// produced the entry (`extract`, `expect_error`, `deterministic`)
+    pub kind: String,
+    pub label: String,
+    pub passed: bool,
+    pub detail: String,
+    /// Skipped by `skip_if`; counts as passed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+}
+
+impl From<AssertionResult> for AssertionDetail {
+    fn from(r: AssertionResult) -> Self {
+        Self {
+            kind: String::new(),
+            label: r.label,
+            passed: r.passed,
+            detail: r.detail,
+            skipped: false,
+        }
+    }
+}
+
+/// Memoizes pure assertion results within a single run, keyed on the
+/// assertion (kind + value) and a hash of the output it was checked against.
+#[derive(Default)]
+pub struct AssertionCache {
+    entries: Mutex<HashMap<(String, u64), AssertionDetail>>,
+}
+
+impl AssertionCache {
+    /// Return the cached result for `kind` on `output`, or run `eval` and
+    /// remember it. Non-cacheable kinds always run `eval`.
+    pub fn evaluate(
+        &self,
+        kind: &AssertionKind,
+        output: &str,
+        eval: impl FnOnce() -> AssertionDetail,
+    ) -> AssertionDetail {
+        if let Some(hit) = self.lookup(kind, output) {
+            return hit;
+        }
+
+        // Evaluate outside the lock; a racing duplicate evaluation is harmless.
+        let detail = eval();
+        self.store(kind, output, &detail);
+        detail
+    }
+
+    /// The cached result for `kind` on `output`, if there is one.
+    pub fn lookup(&self, kind: &AssertionKind, output: &str) -> Option<AssertionDetail> {
+        let key = Self::key(kind, output)?;
+        self.entries
+            .lock()
+            .expect("cache poisoned")
+            .get(&key)
+            .cloned()
+    }
+
+    /// Remember `detail` as the result of `kind` on `output`, unless the kind
+    /// isn't cacheable.
+    pub fn store(&self, kind: &AssertionKind, output: &str, detail: &AssertionDetail) {
+        if let Some(key) = Self::key(kind, output) {
+            self.entries
+                .lock()
+                .expect("cache poisoned")
+                .insert(key, detail.clone());
+        }
+    }
+
+    fn key(kind: &AssertionKind, output: &str) -> Option<(String, u64)> {
+        if !kind.is_cacheable() {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        output.hash(&mut hasher);
+        Some((format!("{:?}", kind), hasher.finish()))
+    }
+}
+
+/// Concurrency used when neither the CLI nor a profile sets one.
+pub const DEFAULT_CONCURRENCY: usize = 5;
+
+/// Directory where snapshot files are stored.
+pub const DEFAULT_SNAPSHOT_DIR: &str = ".snapshots";
+
+/// The snapshot directory for a run: `flag` (`--snapshot-dir`) if given,
+/// else `defaults.snapshot_dir`, else `DEFAULT_SNAPSHOT_DIR`.
+pub fn snapshot_dir(config: &Config, flag: Option<&Path>) -> PathBuf {
+    match (flag, &config.defaults.snapshot_dir) {
+        (Some(dir), _) => dir.to_path_buf(),
+        (None, Some(dir)) => PathBuf::from(dir),
+        (None, None) => PathBuf::from(DEFAULT_SNAPSHOT_DIR),
+    }
+}
+
+/// Max retry attempts for transient API errors.
+const MAX_RETRIES: u32 = 3;
+/// Base delay for exponential backoff (doubles each retry: 500ms → 1s → 2s).
+const BASE_RETRY_DELAY_MS: u64 = 500;
+
+/// Jitter added to a retry's backoff: up to half of it, derived from the
+/// case's `jitter_key` and the attempt so a seeded run repeats its delays.
+fn retry_jitter_ms(jitter_key: u64, attempt: u32, backoff_ms: u64) -> u64 {
+    splitmix64(jitter_key ^ u64::from(attempt)) % (backoff_ms / 2 + 1)
+}
+
+/// One SplitMix64 step. Seeded jitter is derived with this rather than
+/// `DefaultHasher`, whose algorithm may change between Rust releases, so a
+/// `--retry-jitter-seed` gives the same delays on every toolchain.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Per-attempt timeout: `base_ms` for the first attempt, multiplied by
+/// `multiplier` on each retry so a slow endpoint gets more time instead of
+/// timing out the same way again.
+#[derive(Debug, Clone, Copy)]
+struct AttemptTimeout {
+    base_ms: u64,
+    multiplier: f64,
+}
+
+impl AttemptTimeout {
+    fn for_retry(&self, retries: u32) -> u64 {
+        (self.base_ms as f64 * self.multiplier.powi(retries as i32)).round() as u64
+    }
+}
+
+/// Limit on provider calls in flight that adapts to rate limiting (AIMD):
+/// a 429 halves it, and every `limit` successes in a row raise it by one,
+/// never above the `--concurrency` it started at.
+#[derive(Debug)]
+pub struct AdaptiveLimit {
+    max: usize,
+    state: Mutex<LimitState>,
+    freed: tokio::sync::Notify,
+}
+
+#[derive(Debug)]
+struct LimitState {
+    limit: usize,
+    in_flight: usize,
+    successes: usize,
+    /// Bumped on every cut, so one burst of 429s only halves the limit once
+    generation: u64,
+}
+
+/// A slot taken from an `AdaptiveLimit`; released on drop.
+#[derive(Debug)]
+pub struct LimitPermit<'a> {
+    owner: &'a AdaptiveLimit,
+    generation: u64,
+}
+
+impl AdaptiveLimit {
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        AdaptiveLimit {
+            max,
+            state: Mutex::new(LimitState {
+                limit: max,
+                in_flight: 0,
+                successes: 0,
+                generation: 0,
+            }),
+            freed: tokio::sync::Notify::new(),
+        }
+    }
+
+    /// Current number of calls allowed in flight.
+    pub fn limit(&self) -> usize {
+        self.state.lock().expect("limit poisoned").limit
+    }
+
+    /// Wait for a free slot under the current limit.
+    pub async fn acquire(&self) -> LimitPermit<'_> {
+        loop {
+            // Registered before checking, so a release in between isn't missed
+            let freed = self.freed.notified();
+            {
+                let mut state = self.state.lock().expect("limit poisoned");
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    return LimitPermit {
+                        owner: self,
+                        generation: state.generation,
+                    };
+                }
+            }
+            freed.await;
+        }
+    }
+
+    /// The call holding `permit` was rate limited. Calls started before the
+    /// last cut already count toward it and don't cut again.
+    pub fn rate_limited(&self, permit: &LimitPermit) {
+        let mut state = self.state.lock().expect("limit poisoned");
+        if permit.generation == state.generation {
+            state.limit = (state.limit / 2).max(1);
+            state.successes = 0;
+            state.generation += 1;
+        }
+    }
+
+    /// A call went through; after a full window of successes, allow one more.
+    pub fn succeeded(&self) {
+        let mut state = self.state.lock().expect("limit poisoned");
+        state.successes += 1;
+        if state.successes >= state.limit && state.limit < self.max {
+            state.limit += 1;
+            state.successes = 0;
+            drop(state);
+            self.freed.notify_waiters();
+        }
+    }
+}
+
+impl Drop for LimitPermit<'_> {
+    fn drop(&mut self) {
+        self.owner.state.lock().expect("limit poisoned").in_flight -= 1;
+        self.owner.freed.notify_waiters();
+    }
+}
+
+/// Attempt an LLM completion with retry + exponential backoff + timeout.
+/// Each attempt holds a slot of `limit` and reports rate limiting to it.
+#[allow(clippy::too_many_arguments)]
+async fn complete_with_retry(
+    provider: &dyn LlmProvider,
+    limit: &AdaptiveLimit,
+    prompt: &str,
+    images: &[String],
+    model: &str,
+    temperature: f64,
+    n: u32,
+    params: &CompletionParams,
+    timeout: AttemptTimeout,
+    jitter_key: u64,
+    mut on_retry: impl FnMut(RetryEvent),
+) -> (Result<providers::CompletionResult, anyhow::Error>, u32) {
+    let mut retries = 0;
+
+    loop {
+        let timeout_ms = timeout.for_retry(retries);
+        let permit = limit.acquire().await;
+        let attempt = time::timeout(
+            Duration::from_millis(timeout_ms),
+            provider.complete_with_params(prompt, images, model, temperature, n, params),
+        )
+        .await;
+
+        let result = match attempt {
+            Ok(inner) => inner,
+            Err(_) => Err(anyhow::anyhow!("request timed out after {}ms", timeout_ms)),
+        };
+
+        match result {
+            Ok(output) => {
+                limit.succeeded();
+                return (Ok(output), retries);
+            }
+            Err(e) => {
+                let err_msg = e.to_string();
+                if err_msg.contains("429") {
+                    limit.rate_limited(&permit);
+                }
+                drop(permit);
+                let is_transient = err_msg.contains("429")
+                    || err_msg.contains("500")
+                    || err_msg.contains("502")
+                    || err_msg.contains("503")
+                    || err_msg.contains("timeout")
+                    || err_msg.contains("timed out")
+                    || err_msg.contains("connection");
+
+                if is_transient && retries < MAX_RETRIES {
+                    retries += 1;
+                    let backoff = BASE_RETRY_DELAY_MS * 2u64.pow(retries - 1);
+                    let jitter = retry_jitter_ms(jitter_key, retries, backoff);
+                    let delay = backoff + jitter;
+                    on_retry(RetryEvent {
+                        attempt: retries,
+                        delay_ms: delay,
+                        jitter_ms: jitter,
+                        reason: retry_reason(&err_msg),
+                    });
+                    time::sleep(Duration::from_millis(delay)).await;
+                    continue;
+                }
+
+                return (Err(e), retries);
+            }
+        }
+    }
+}
+
+/// Short label for why a transient error is being retried.
+fn retry_reason(err_msg: &str) -> String {
+    if let Some(code) = ["429", "500", "502", "503"]
+        .iter()
+        .find(|code| err_msg.contains(*code))
+    {
+        code.to_string()
+    } else if err_msg.contains("timeout") || err_msg.contains("timed out") {
+        "timeout".to_string()
+    } else {
+        "connection".to_string()
+    }
+}
+
+/// Outcome of a provider preflight probe.
+#[derive(Debug)]
+pub enum PreflightStatus {
+    /// The provider answered a tiny completion.
+    Ok { latency_ms: u64 },
+    /// The provider rejected our credentials (401/403).
+    AuthFailure(String),
+    /// The endpoint could not be reached or did not answer in time.
+    NetworkFailure(String),
+    /// Any other error (rate limits, 5xx, malformed responses, ...).
+    Failure(String),
+}
+
+/// Throwaway prompt used for preflight probes; kept tiny to cost next to nothing.
+const PREFLIGHT_PROMPT: &str = "Reply with the single word: ok";
+
+/// The temperature to send `model` through a provider with `capabilities`
+/// (for that model): the `temperature` setting resolved for the model, or
+/// none when it is `null` or the provider or model doesn't take one.
+pub fn request_temperature(
+    setting: Option<Option<f64>>,
+    capabilities: &ProviderCapabilities,
+    model: &str,
+) -> Option<f64> {
+    resolve_temperature(setting, model).filter(|_| capabilities.supports_temperature)
+}
+
+/// Send one tiny completion to check that the provider is reachable and the
+/// credentials are valid. No retries: a misconfigured setup should fail fast.
+/// The temperature is sent as a case's would be (`request_temperature`).
+pub async fn preflight_check(
+    provider: &dyn LlmProvider,
+    provider_name: &str,
+    model: &str,
+    temperature: Option<Option<f64>>,
+    timeout_ms: u64,
+) -> PreflightStatus {
+    let capabilities = providers::capabilities_of(provider, provider_name, model);
+    let temperature = request_temperature(temperature, &capabilities, model);
+    let params = CompletionParams {
+        omit_temperature: temperature.is_none(),
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let attempt = time::timeout(
+        Duration::from_millis(timeout_ms),
+        provider.complete_with_params(
+            PREFLIGHT_PROMPT,
+            &[],
+            model,
+            // Ignored when `params.omit_temperature` is set
+            temperature.unwrap_or(providers::API_DEFAULT_TEMPERATURE),
+            1,
+            &params,
+        ),
+    )
+    .await;
+
+    let err = match attempt {
+        Ok(Ok(_)) => {
+            return PreflightStatus::Ok {
+                latency_ms: start.elapsed().as_millis() as u64,
+            }
+        }
+        Ok(Err(e)) => e,
+        Err(_) => {
+            return PreflightStatus::NetworkFailure(format!("no response within {}ms", timeout_ms))
+        }
+    };
+
+    if let Some(req_err) = err.downcast_ref::<reqwest::Error>() {
+        if req_err.is_connect() || req_err.is_timeout() {
+            return PreflightStatus::NetworkFailure(err.to_string());
+        }
+    }
+
+    let msg = err.to_string();
+    match err.downcast_ref::<providers::ApiError>() {
+        Some(api) if api.is_auth_failure() => PreflightStatus::AuthFailure(msg),
+        _ => PreflightStatus::Failure(msg),
+    }
+}
+
+/// How case progress is shown on stderr (`--progress`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProgressMode {
+    /// An animated bar on a terminal, dots otherwise
+    #[default]
+    Auto,
+    Bar,
+    /// A `.` per passing case and an `F` per failure, pytest-style, so CI
+    /// logs and pipes stay free of control sequences
+    Dots,
+}
+
+/// Dots printed per line before wrapping.
+const DOTS_PER_LINE: usize = 60;
+
+/// Case progress on stderr, as an animated bar or a line of dots.
+struct Progress {
+    bar: ProgressBar,
+    /// Dots printed on the current line; `None` when drawing a bar
+    dots: Option<Mutex<usize>>,
+}
+
+impl Progress {
+    fn new(total: u64, mode: ProgressMode) -> Self {
+        let dots = match mode {
+            ProgressMode::Auto => !std::io::stderr().is_terminal(),
+            ProgressMode::Bar => false,
+            ProgressMode::Dots => true,
+        };
+        if dots {
+            let bar = ProgressBar::hidden();
+            bar.set_length(total);
+            return Progress {
+                bar,
+                dots: Some(Mutex::new(0)),
+            };
+        }
+
+        let bar = ProgressBar::new(total);
+        // A bad template only loses the styling, never the run
+        if let Ok(style) = ProgressStyle::with_template(
+            "  {spinner:.cyan} [{bar:30.green/dim}] {pos}/{len} tests ({eta} remaining)",
+        ) {
+            bar.set_style(style.progress_chars("█▓░"));
+        }
+        bar.enable_steady_tick(Duration::from_millis(120));
+        Progress { bar, dots: None }
+    }
+
+    fn inc_length(&self, delta: u64) {
+        self.bar.inc_length(delta);
+    }
+
+    fn inc(&self, passed: bool) {
+        self.bar.inc(1);
+        let Some(dots) = &self.dots else {
+            return;
+        };
+        let mut on_line = dots.lock().expect("progress poisoned");
+        let mut stderr = std::io::stderr().lock();
+        if *on_line == 0 {
+            let _ = write!(stderr, "  ");
+        }
+        let _ = write!(stderr, "{}", if passed { "." } else { "F" });
+        *on_line += 1;
+        if *on_line == DOTS_PER_LINE {
+            let _ = writeln!(
+                stderr,
+                " {}/{}",
+                self.bar.position(),
+                self.bar.length().unwrap_or(0)
+            );
+            *on_line = 0;
+        }
+        let _ = stderr.flush();
+    }
+
+    fn finish(&self) {
+        self.bar.finish_and_clear();
+        if let Some(dots) = &self.dots {
+            if *dots.lock().expect("progress poisoned") > 0 {
+                eprintln!();
+            }
+        }
+    }
+}
+
+/// State shared by every case task in a run.
+struct RunShared {
+    provider: Arc<dyn LlmProvider>,
+    provider_name: String,
+    /// Providers a test or case names besides the run's, created on first use
+    other_providers: Mutex<HashMap<String, Arc<dyn LlmProvider>>>,
+    prior: Option<Arc<PriorResults>>,
+    resume: Option<Arc<CompletedResults>>,
+    cache: AssertionCache,
+    progress: Option<Arc<Progress>>,
+    snapshot_dir: PathBuf,
+    snapshot_base: Option<SnapshotBase>,
+    snapshot_mode: SnapshotMode,
+    /// `defaults.temperature` as configured, resolved per model
+    temperature: Option<Option<f64>>,
+    timeout: AttemptTimeout,
+    limit: AdaptiveLimit,
+    normalize_output: NormalizeOutput,
+    /// Limit on each assertion's evaluation
+    assertion_timeout: Duration,
+    /// `defaults.case_sensitive_contains`
+    case_sensitive_contains: bool,
+    prompt_prefix: Option<String>,
+    prompt_suffix: Option<String>,
+    /// `--strip-control`: on for every test
+    strip_control: bool,
+    /// Unique per run, so request IDs don't repeat across runs
+    run_nonce: String,
+    /// Seeds retry jitter (`--retry-jitter-seed`, else random per run)
+    jitter_seed: u64,
+}
+
+impl RunShared {
+    /// The provider to call for `name`: the run's own, or one created from
+    /// the environment the first time a test asks for it.
+    fn provider_for(&self, name: &str) -> anyhow::Result<Arc<dyn LlmProvider>> {
+        if name == self.provider_name {
+            return Ok(Arc::clone(&self.provider));
+        }
+        let mut others = self.other_providers.lock().expect("providers poisoned");
+        if let Some(provider) = others.get(name) {
+            return Ok(Arc::clone(provider));
+        }
+        let provider: Arc<dyn LlmProvider> = Arc::from(providers::create_provider(name)?);
+        others.insert(name.to_string(), Arc::clone(&provider));
+        Ok(provider)
+    }
+
+    /// Capabilities of the provider `name` resolves to, serving `model`.
+    fn capabilities(&self, name: &str, model: &str) -> ProviderCapabilities {
+        if name == self.provider_name {
+            providers::capabilities_of(&*self.provider, name, model)
+        } else {
+            providers::provider_capabilities(name, model)
+        }
+    }
+
+    /// `X-Request-Id` for one case run: the run nonce, snapshot key (test ID
+    /// and case index) and run number, reduced to header-safe characters.
+    fn request_id(&self, snapshot_key: &str, run: u32) -> String {
+        format!("sentinel-{}-{}-{}", self.run_nonce, snapshot_key, run)
+            .chars()
+            .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+            .collect()
+    }
+
+    /// Per-case input to `retry_jitter_ms`: fixed for a given seed, case and
+    /// repeat, whatever order the cases run in.
+    fn jitter_key(&self, snapshot_key: &str, run: u32) -> u64 {
+        let key = snapshot_key
+            .bytes()
+            .fold(splitmix64(self.jitter_seed), |key, b| {
+                splitmix64(key ^ u64::from(b))
+            });
+        splitmix64(key ^ u64::from(run))
+    }
+
+    /// `prompt` with the run's prefix and suffix, if any, around it.
+    fn wrap_prompt(&self, prompt: String) -> String {
+        wrap_prompt(
+            &prompt,
+            self.prompt_prefix.as_deref(),
+            self.prompt_suffix.as_deref(),
+        )
+    }
+}
+
+/// Put `prefix` before and `suffix` after `prompt`, each separated from it
+/// by a blank line.
+pub fn wrap_prompt(prompt: &str, prefix: Option<&str>, suffix: Option<&str>) -> String {
+    let mut wrapped = String::new();
+    if let Some(prefix) = prefix {
+        wrapped.push_str(prefix);
+        wrapped.push_str("\n\n");
+    }
+    wrapped.push_str(prompt);
+    if let Some(suffix) = suffix {
+        wrapped.push_str("\n\n");
+        wrapped.push_str(suffix);
+    }
+    wrapped
+}
+
+/// A single case ready to execute.
+struct CaseJob {
+    test_id: String,
+    prompt_template: String,
+    /// Model, possibly a template like `{{model}}` until `run_case` renders it
+    model: String,
+    /// Test-level provider override, templated like `model`; `None` uses the
+    /// run's provider
+    provider: Option<String>,
+    case: TestCase,
+    extract: Option<Extract>,
+    include_thinking: bool,
+    /// Strip ANSI and control characters before assertions
+    strip_control: bool,
+    /// Completions requested per call (`n`), and how their results combine
+    n: u32,
+    choices: ChoiceMode,
+    /// Stop sequences and penalties
+    params: CompletionParams,
+    /// `Some` for negative tests: the error substring to require, if any
+    expect_error: Option<Option<String>>,
+    snapshot_key: String,
+    /// 1-based run number under `--repeat`
+    run: u32,
+}
+
+/// Results of an earlier run keyed by case fingerprint, so cases whose
+/// prompt and parameters haven't changed can be reused instead of re-run.
+#[derive(Debug, Default)]
+pub struct PriorResults {
+    by_fingerprint: HashMap<u64, CaseResult>,
+}
+
+impl PriorResults {
+    /// Remember every case that completed without a provider error.
+    pub fn from_results(results: &[CaseResult]) -> Self {
+        let by_fingerprint = results
+            .iter()
+            .filter(|r| r.error.is_none())
+            .filter_map(|r| Some((r.fingerprint?, r.clone())))
+            .collect();
+        Self { by_fingerprint }
+    }
+
+    fn get(&self, fingerprint: u64) -> Option<CaseResult> {
+        let mut result = self.by_fingerprint.get(&fingerprint)?.clone();
+        result.cached = true;
+        Some(result)
+    }
+}
+
+/// Cases an interrupted run already finished (`--resume`), keyed by test,
+/// input, model and repeat run, so only the rest are run. Inputs are compared
+/// as maps, not by their display label.
+#[derive(Debug, Default)]
+pub struct CompletedResults {
+    by_case: HashMap<CompletedKey, CaseResult>,
+}
+
+type CompletedKey = (String, BTreeMap<String, String>, String, u32);
+
+impl CompletedResults {
+    /// Remember every case that finished; one that ended in a provider error
+    /// (or never got an answer) is run again.
+    pub fn from_results(results: Vec<CaseResult>) -> Self {
+        let by_case = results
+            .into_iter()
+            .filter(|r| r.error.is_none())
+            .map(|r| {
+                let key = Self::key(&r.test_id, &r.input, &r.model, r.repeat.unwrap_or(1));
+                (key, r)
+            })
+            .collect();
+        Self { by_case }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_case.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_case.is_empty()
+    }
+
+    fn key(test_id: &str, input: &HashMap<String, String>, model: &str, run: u32) -> CompletedKey {
+        (
+            test_id.to_string(),
+            input.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            model.to_string(),
+            run,
+        )
+    }
+
+    fn get(
+        &self,
+        test_id: &str,
+        input: &HashMap<String, String>,
+        model: &str,
+        run: u32,
+    ) -> Option<CaseResult> {
+        let mut result = self
+            .by_case
+            .get(&Self::key(test_id, input, model, run))?
+            .clone();
+        result.cached = true;
+        Some(result)
+    }
+}
+
+/// Knobs for a test run.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Max number of cases in flight at once
+    pub concurrency: usize,
+    /// Per-request timeout in milliseconds
+    pub timeout_ms: u64,
+    /// Scale the timeout by this much on each retry (1.0 keeps it fixed)
+    pub timeout_multiplier: f64,
+    pub snapshot_mode: SnapshotMode,
+    /// Only run tests whose ID contains this pattern
+    pub filter: Option<String>,
+    /// Only run cases whose input has every one of these key/value pairs
+    /// (`--input-filter`), including rows read from a `cases_file`
+    pub input_filter: Vec<(String, String)>,
+    /// Show case progress on stderr
+    pub show_progress: bool,
+    /// How progress is drawn when shown
+    pub progress: ProgressMode,
+    /// Run every case this many times
+    pub repeat: u32,
+    /// Fail repeated cases whose outputs aren't all identical
+    /// (applied by `run_all_tests`, which sees every run)
+    pub assert_deterministic: bool,
+    /// Reuse these results for cases that haven't changed since
+    pub prior: Option<Arc<PriorResults>>,
+    /// Skip cases an interrupted run already finished (`--resume`)
+    pub resume: Option<Arc<CompletedResults>>,
+    /// Run each test's cases one after another (in config order), while
+    /// different tests still run in parallel
+    pub serial_per_test: bool,
+    /// Order in which cases from different tests are started
+    pub schedule: Schedule,
+    /// Where snapshots live (`--snapshot-dir`); overrides `defaults.snapshot_dir`
+    pub snapshot_dir: Option<PathBuf>,
+    /// Baseline snapshots compared before the snapshot dir's (`--snapshot-base`)
+    pub snapshot_base: Option<SnapshotBase>,
+    /// Text added before every rendered prompt, separated by a blank line
+    pub prompt_prefix: Option<String>,
+    /// Text added after every rendered prompt, separated by a blank line
+    pub prompt_suffix: Option<String>,
+    /// Strip ANSI and control characters before assertions for every test,
+    /// whatever `strip_control` says
+    pub strip_control: bool,
+    /// Seed for retry jitter, so a run's retry delays can be reproduced;
+    /// `None` picks a random one
+    pub retry_jitter_seed: Option<u64>,
+}
+
+/// Order in which cases are handed concurrency slots. Results are reported
+/// in config order either way.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Schedule {
+    /// One case from each test in turn, so a test with a large data file
+    /// doesn't hold every slot while the tests after it wait
+    #[default]
+    RoundRobin,
+    /// Every case of a test before any case of the next
+    InOrder,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            timeout_ms: 30_000,
+            timeout_multiplier: 1.0,
+            snapshot_mode: SnapshotMode::Check,
+            filter: None,
+            input_filter: Vec::new(),
+            show_progress: false,
+            progress: ProgressMode::Auto,
+            repeat: 1,
+            assert_deterministic: false,
+            prior: None,
+            resume: None,
+            serial_per_test: false,
+            schedule: Schedule::default(),
+            snapshot_dir: None,
+            snapshot_base: None,
+            prompt_prefix: None,
+            prompt_suffix: None,
+            strip_control: false,
+            retry_jitter_seed: None,
+        }
+    }
+}
+
+/// Live aggregate counters for an in-flight run, safe to read from any thread.
+#[derive(Debug, Default)]
+pub struct RunCounters {
+    completed: AtomicUsize,
+    passed: AtomicUsize,
+    failed: AtomicUsize,
+    /// Total spend in USD, stored as `f64` bits
+    spent_usd: AtomicU64,
+}
+
+impl RunCounters {
+    /// Cases finished so far. Read this before the other counters: they
+    /// then include at least every case it counts.
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::Acquire)
+    }
+
+    pub fn passed(&self) -> usize {
+        self.passed.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn spent_usd(&self) -> f64 {
+        f64::from_bits(self.spent_usd.load(Ordering::Relaxed))
+    }
+
+    fn record(&self, result: &CaseResult) {
+        if result.passed {
+            self.passed.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        let _ = self
+            .spent_usd
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + result.cost_usd).to_bits())
+            });
+        // Bump last, with Release, so a reader that sees this count (via
+        // the Acquire load in `completed`) also sees the updates above.
+        self.completed.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// Handle to a run started with [`run_tests_streaming`].
+pub struct RunHandle {
+    counters: Arc<RunCounters>,
+    results: mpsc::UnboundedReceiver<(ResultKey, CaseResult)>,
+}
+
+impl RunHandle {
+    /// Shared counters, updated as each case completes.
+    pub fn counters(&self) -> Arc<RunCounters> {
+        Arc::clone(&self.counters)
+    }
+
+    /// Next finished case in completion order; `None` once the run is over.
+    pub async fn next(&mut self) -> Option<CaseResult> {
+        self.results.recv().await.map(|(_, result)| result)
+    }
+}
+
+/// Start a run in the background and stream results as cases complete.
+pub fn run_tests_streaming(
+    config: Arc<Config>,
+    provider: Arc<dyn LlmProvider>,
+    options: RunOptions,
+) -> RunHandle {
+    let counters = Arc::new(RunCounters::default());
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let driver_counters = Arc::clone(&counters);
+    tokio::spawn(async move {
+        drive_run(&config, provider, &options, driver_counters, tx).await;
+    });
+
+    RunHandle {
+        counters,
+        results: rx,
+    }
+}
+
+/// Run all tests from the config in parallel (bounded by concurrency limit).
+/// Results are returned in config order.
+pub async fn run_all_tests(
+    config: &Config,
+    provider: Arc<dyn LlmProvider>,
+    options: &RunOptions,
+) -> Vec<CaseResult> {
+    run_all_tests_with(config, provider, options, |_| {}).await
+}
+
+/// Like `run_all_tests`, but also hands each result to `on_result` as soon as
+/// it completes (in completion order), before the determinism check runs.
+pub async fn run_all_tests_with(
+    config: &Config,
+    provider: Arc<dyn LlmProvider>,
+    options: &RunOptions,
+    mut on_result: impl FnMut(&CaseResult),
+) -> Vec<CaseResult> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let drive = drive_run(
+        config,
+        provider,
+        options,
+        Arc::new(RunCounters::default()),
+        tx,
+    );
+    let collect = async {
+        let mut indexed = Vec::new();
+        while let Some((index, result)) = rx.recv().await {
+            on_result(&result);
+            indexed.push((index, result));
+        }
+        indexed
+    };
+    let ((), mut indexed) = tokio::join!(drive, collect);
+
+    indexed.sort_by_key(|(index, _)| *index);
+    let mut results: Vec<CaseResult> = indexed.into_iter().map(|(_, result)| result).collect();
+
+    if options.assert_deterministic {
+        check_determinism(&mut results);
+    }
+    results
+}
+
+/// Compare the outputs of each repeated case (its runs are adjacent, numbered
+/// from 1) and add a `deterministic` assertion to every run, failing them all
+/// when the outputs differ.
+fn check_determinism(results: &mut [CaseResult]) {
+    for range in repeat_groups(results) {
+        let runs = &mut results[range];
+        let mut distinct: Vec<&str> = Vec::new();
+        for output in runs.iter().filter_map(|r| r.output.as_deref()) {
+            if !distinct.contains(&output) {
+                distinct.push(output);
+            }
+        }
+
+        let detail = match distinct.as_slice() {
+            [] => None,
+            [_] => Some(AssertionDetail {
+                kind: "deterministic".to_string(),
+                label: "deterministic".to_string(),
+                passed: true,
+                detail: format!("{} identical outputs", runs.len()),
+                skipped: false,
+            }),
+            [first, second, ..] => Some(AssertionDetail {
+                kind: "deterministic".to_string(),
+                label: "deterministic".to_string(),
+                passed: false,
+                detail: format!(
+                    "{} distinct outputs across {} runs; {}",
+                    distinct.len(),
+                    runs.len(),
+                    diff_summary(first, second)
+                ),
+                skipped: false,
+            }),
+        };
+
+        if let Some(detail) = detail {
+            for run in runs.iter_mut() {
+                run.passed &= detail.passed;
+                run.assertions.push(detail.clone());
+            }
+        }
+    }
+}
+
+/// Index ranges of the repeated cases in `results`: each case's runs, when
+/// there is more than one, are adjacent and numbered from 1.
+fn repeat_groups(results: &[CaseResult]) -> Vec<std::ops::Range<usize>> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    while start < results.len() {
+        let mut end = start + 1;
+        if results[start].repeat == Some(1) {
+            while end < results.len()
+                && results[end].repeat.is_some_and(|run| run > 1)
+                && results[end].test_id == results[start].test_id
+            {
+                end += 1;
+            }
+        }
+        if end - start > 1 {
+            groups.push(start..end);
+        }
+        start = end;
+    }
+    groups
+}
+
+/// The different outputs among a repeated case's runs, most frequent first,
+/// with how many runs produced each. Outputs that differ only in whitespace
+/// count as one, shown as the first run's text; runs without output are left
+/// out.
+pub fn distinct_outputs(runs: &[CaseResult]) -> Vec<(String, usize)> {
+    let mut distinct: Vec<(String, String, usize)> = Vec::new();
+    for output in runs.iter().filter_map(|r| r.output.as_deref()) {
+        let normalized = output.split_whitespace().collect::<Vec<_>>().join(" ");
+        match distinct.iter_mut().find(|(key, _, _)| *key == normalized) {
+            Some((_, _, count)) => *count += 1,
+            None => distinct.push((normalized, output.to_string(), 1)),
+        }
+    }
+    // Stable, so ties keep the order the outputs first appeared in
+    distinct.sort_by_key(|(_, _, count)| std::cmp::Reverse(*count));
+    distinct
+        .into_iter()
+        .map(|(_, output, count)| (output, count))
+        .collect()
+}
+
+/// Spawn every case and send `(config order key, result)` pairs as they
+/// finish. Returns once all cases are done.
+///
+/// Cases are produced lazily and a task is only spawned once a concurrency
+/// slot is free, so streamed case files are never materialized up front.
+async fn drive_run(
+    config: &Config,
+    provider: Arc<dyn LlmProvider>,
+    options: &RunOptions,
+    counters: Arc<RunCounters>,
+    tx: mpsc::UnboundedSender<(ResultKey, CaseResult)>,
+) {
+    // Filter tests by ID if --filter is specified
+    let tests: Vec<_> = config
+        .tests
+        .iter()
+        .filter(|t| match options.filter.as_deref() {
+            Some(pattern) => t.id.contains(pattern),
+            None => true,
+        })
+        .collect();
+
+    let repeat = options.repeat.max(1);
+
+    // Streamed rows aren't counted yet; they grow the bar as they are read.
+    let total_cases: usize = tests
+        .iter()
+        .flat_map(|t| &t.cases)
+        .filter(|c| input_matches(c, &options.input_filter))
+        .count()
+        * repeat as usize;
+    let has_streams = tests.iter().any(|t| t.cases_path.is_some());
+
+    let pb = if options.show_progress && (total_cases > 0 || has_streams) {
+        Some(Arc::new(Progress::new(
+            total_cases as u64,
+            options.progress,
+        )))
+    } else {
+        None
+    };
+
+    let run_nonce = crate::report::new_run_id();
+    // A plugin goes by the name it registered, whatever `defaults.provider` says
+    let provider_name = provider
+        .name()
+        .map_or_else(|| config.defaults.provider.clone(), str::to_string);
+    let shared = Arc::new(RunShared {
+        provider,
+        provider_name,
+        other_providers: Mutex::default(),
+        prior: options.prior.clone(),
+        resume: options.resume.clone(),
+        cache: AssertionCache::default(),
+        progress: pb.clone(),
+        snapshot_dir: snapshot_dir(config, options.snapshot_dir.as_deref()),
+        snapshot_base: options.snapshot_base.clone(),
+        snapshot_mode: options.snapshot_mode,
+        temperature: config.defaults.temperature,
+        timeout: AttemptTimeout {
+            base_ms: options.timeout_ms,
+            multiplier: options.timeout_multiplier,
+        },
+        limit: AdaptiveLimit::new(options.concurrency),
+        normalize_output: config.defaults.normalize_output,
+        assertion_timeout: Duration::from_millis(
+            config
+                .defaults
+                .assertion_timeout_ms
+                .unwrap_or(crate::config::DEFAULT_ASSERTION_TIMEOUT_MS),
+        ),
+        case_sensitive_contains: config.defaults.case_sensitive_contains,
+        prompt_prefix: options.prompt_prefix.clone(),
+        prompt_suffix: options.prompt_suffix.clone(),
+        strip_control: options.strip_control,
+        run_nonce: run_nonce.clone(),
+        jitter_seed: options.retry_jitter_seed.unwrap_or_else(|| {
+            let mut hasher = DefaultHasher::new();
+            run_nonce.hash(&mut hasher);
+            hasher.finish()
+        }),
+    });
+
+    let mut handles: Vec<(ResultKey, JoinHandle<()>)> = Vec::new();
+    let semaphore = Arc::new(Semaphore::new(options.concurrency));
+
+    // Report a result produced outside a case task (e.g. a broken data file).
+    let emit = |key: ResultKey, result: CaseResult| {
+        counters.record(&result);
+        let _ = tx.send((key, result));
+    };
+
+    let input_filter: Arc<[(String, String)]> = options.input_filter.clone().into();
+    let mut queues: Vec<TestQueue> = tests
+        .iter()
+        .enumerate()
+        .map(|(position, test)| {
+            TestQueue::new(
+                position,
+                Arc::new((*test).clone()),
+                &config.defaults,
+                Arc::clone(&input_filter),
+            )
+        })
+        .collect();
+
+    let mut turn = 0;
+    while !queues.is_empty() {
+        let current = match options.schedule {
+            Schedule::RoundRobin => turn % queues.len(),
+            Schedule::InOrder => 0,
+        };
+
+        // A test with hooks runs as with `--serial-per-test`, so its cases
+        // stay between its `before` and `after` commands. The whole test
+        // takes one concurrency slot when its turn comes.
+        if options.serial_per_test || queues[current].test.has_hooks() {
+            let queue = queues.remove(current);
+            turn = current;
+            let permit = Arc::clone(&semaphore)
+                .acquire_owned()
+                .await
+                .expect("semaphore closed");
+            let first_key = (queue.position, 0);
+            let handle = tokio::spawn(run_serial_test(
+                queue,
+                Arc::clone(&shared),
+                Arc::clone(&counters),
+                tx.clone(),
+                repeat,
+                permit,
+            ));
+            handles.push((first_key, handle));
+            continue;
+        }
+
+        let Some(case_jobs) = queues[current].next_case(repeat, pb.as_deref(), &emit) else {
+            queues.remove(current);
+            turn = current;
+            continue;
+        };
+        turn = current + 1;
+
+        for (key, job) in case_jobs {
+            let permit = Arc::clone(&semaphore)
+                .acquire_owned()
+                .await
+                .expect("semaphore closed");
+            let shared = Arc::clone(&shared);
+            let counters = Arc::clone(&counters);
+            let tx = tx.clone();
+            let run = job.run;
+
+            let handle = tokio::spawn(async move {
+                let result = run_case(Arc::clone(&shared), job).await;
+                drop(permit);
+                deliver(&shared, &counters, &tx, key, result, run, repeat);
+            });
+            handles.push((key, handle));
+        }
+    }
+
+    for (case_index, handle) in handles {
+        if let Err(e) = handle.await {
+            emit(
+                case_index,
+                failed_result(
+                    "unknown",
+                    "unknown",
+                    "unknown",
+                    format!("Task join error: {}", e),
+                ),
+            );
+        }
+    }
+
+    if let Some(pb) = pb {
+        pb.finish();
+    }
+}
+
+/// Run a serial test on the concurrency slot `permit` holds: its `before`
+/// hook, then its cases one after another, then its `after` hook. Cases are
+/// read from the queue as they are reached, so a streamed data file is
+/// never collected up front. A test with no cases runs no hooks. The last
+/// result is held back until `after` finishes, so a failing `after` fails it.
+async fn run_serial_test(
+    mut queue: TestQueue,
+    shared: Arc<RunShared>,
+    counters: Arc<RunCounters>,
+    tx: mpsc::UnboundedSender<(ResultKey, CaseResult)>,
+    repeat: u32,
+    permit: OwnedSemaphorePermit,
+) {
+    let emit = |key: ResultKey, result: CaseResult| {
+        counters.record(&result);
+        let _ = tx.send((key, result));
+    };
+    let pb = shared.progress.clone();
+    let Some(mut case_jobs) = queue.next_case(repeat, pb.as_deref(), &emit) else {
+        return;
+    };
+
+    let test = Arc::clone(&queue.test);
+    let before_error = match &test.before {
+        Some(cmd) => run_test_hook(cmd, SUITE_COMMAND_TIMEOUT).await.err(),
+        None => None,
+    };
+    let mut last = None;
+    loop {
+        for (key, job) in case_jobs {
+            let run = job.run;
+            let result = match &before_error {
+                Some(e) => CaseResult {
+                    input: job.case.input.clone(),
+                    ..failed_result(
+                        &job.test_id,
+                        &input_label(&job.case.input),
+                        &job.model,
+                        format!("before hook failed: {}", e),
+                    )
+                },
+                None => run_case(Arc::clone(&shared), job).await,
+            };
+            if let Some((key, result, run)) = last.replace((key, result, run)) {
+                deliver(&shared, &counters, &tx, key, result, run, repeat);
+            }
+        }
+        match queue.next_case(repeat, pb.as_deref(), &emit) {
+            Some(next) => case_jobs = next,
+            None => break,
+        }
+    }
+
+    let after_error = match &test.after {
+        Some(cmd) => run_test_hook(cmd, SUITE_COMMAND_TIMEOUT).await.err(),
+        None => None,
+    };
+    if let Some((key, mut result, run)) = last {
+        if let Some(e) = after_error {
+            let hook_error = format!("after hook failed: {}", e);
+            result.passed = false;
+            result.error = Some(match result.error {
+                Some(error) => format!("{}; {}", error, hook_error),
+                None => hook_error,
+            });
+        }
+        deliver(&shared, &counters, &tx, key, result, run, repeat);
+    }
+    drop(permit);
+}
+
+/// Whether a case's input has every `--input-filter` pair. An empty filter
+/// matches every case.
+pub fn input_matches(case: &TestCase, filter: &[(String, String)]) -> bool {
+    filter
+        .iter()
+        .all(|(key, value)| case.input.get(key) == Some(value))
+}
+
+/// Position of a result in config order: (test, case run within the test).
+/// Streamed tests don't know their row count up front, so a flat index
+/// can't be assigned before the run.
+type ResultKey = (usize, usize);
+
+/// One test's not-yet-started cases, inline ones first, then streamed rows.
+/// Each case keeps its position in the unfiltered list, so snapshot keys
+/// don't shift when `--input-filter` skips cases.
+struct TestQueue {
+    position: usize,
+    test: Arc<TestDef>,
+    model: String,
+    params: CompletionParams,
+    strip_control: bool,
+    cases: Box<dyn Iterator<Item = (usize, anyhow::Result<TestCase>)> + Send>,
+    next_key: usize,
+}
+
+impl TestQueue {
+    fn new(
+        position: usize,
+        test: Arc<TestDef>,
+        defaults: &Defaults,
+        input_filter: Arc<[(String, String)]>,
+    ) -> Self {
+        let streamed: Box<dyn Iterator<Item = anyhow::Result<TestCase>> + Send> =
+            match &test.cases_path {
+                Some(path) => match case_rows(path, &test.cases_file_options) {
+                    Ok(rows) => {
+                        let test = Arc::clone(&test);
+                        Box::new(rows.map(move |row| row.map(|input| test.case_from_row(input))))
+                    }
+                    Err(e) => Box::new(std::iter::once(Err(e))),
+                },
+                None => Box::new(std::iter::empty()),
+            };
+        let inline = {
+            let test = Arc::clone(&test);
+            (0..test.cases.len()).map(move |i| Ok(test.cases[i].clone()))
+        };
+        let cases = inline
+            .chain(streamed)
+            .enumerate()
+            .filter(move |(_, case)| match case {
+                Ok(case) => input_matches(case, &input_filter),
+                Err(_) => true,
+            });
+        Self {
+            position,
+            model: test.model.clone().unwrap_or_else(|| defaults.model.clone()),
+            params: test.completion_params(defaults),
+            strip_control: test.strip_control.unwrap_or(defaults.strip_control),
+            test,
+            cases: Box::new(cases),
+            next_key: 0,
+        }
+    }
+
+    /// Jobs for the next case, one per repeat run (kept adjacent in order).
+    /// `None` once the test has no cases left; a broken data file is
+    /// reported once through `emit` and ends the test.
+    fn next_case(
+        &mut self,
+        repeat: u32,
+        pb: Option<&Progress>,
+        emit: &dyn Fn(ResultKey, CaseResult),
+    ) -> Option<Vec<(ResultKey, CaseJob)>> {
+        let test = Arc::clone(&self.test);
+        let (ci, case) = self.cases.next()?;
+        let case = match case {
+            Ok(case) => case,
+            Err(e) => {
+                emit(
+                    (self.position, self.next_key),
+                    failed_result(&test.id, "cases_file", &self.model, e.to_string()),
+                );
+                self.cases = Box::new(std::iter::empty());
+                return None;
+            }
+        };
+
+        if test.cases_path.is_some() && ci >= test.cases.len() {
+            if let Some(pb) = pb {
+                pb.inc_length(repeat as u64);
+            }
+        }
+
+        let jobs = (1..=repeat)
+            .map(|run| {
+                let key = (self.position, self.next_key);
+                self.next_key += 1;
+                let job = CaseJob {
+                    test_id: test.id.clone(),
+                    prompt_template: test.prompt.clone(),
+                    model: self.model.clone(),
+                    provider: test.provider.clone(),
+                    extract: case.extract.clone().or_else(|| test.extract.clone()),
+                    include_thinking: test.include_thinking,
+                    strip_control: self.strip_control,
+                    n: test.n.unwrap_or(1),
+                    choices: test.choices,
+                    params: self.params.clone(),
+                    expect_error: expected_error(&test, &case),
+                    snapshot_key: format!("{}_case{}", test.id, ci),
+                    case: case.clone(),
+                    run,
+                };
+                (key, job)
+            })
+            .collect();
+        Some(jobs)
+    }
+}
+
+/// Record a finished case and send it to the collector.
+fn deliver(
+    shared: &RunShared,
+    counters: &RunCounters,
+    tx: &mpsc::UnboundedSender<(ResultKey, CaseResult)>,
+    case_index: ResultKey,
+    mut result: CaseResult,
+    run: u32,
+    repeat: u32,
+) {
+    if repeat > 1 {
+        result.repeat = Some(run);
+    }
+    if let Some(ref pb) = shared.progress {
+        pb.inc(result.passed);
+    }
+    counters.record(&result);
+    let _ = tx.send((case_index, result));
+}
+
+/// Execute one case: render, call the provider (with retries), evaluate.
+async fn run_case(shared: Arc<RunShared>, job: CaseJob) -> CaseResult {
+    let CaseJob {
+        test_id,
+        prompt_template,
+        model,
+        provider,
+        case,
+        extract,
+        include_thinking,
+        strip_control,
+        n,
+        choices,
+        params,
+        expect_error,
+        snapshot_key,
+        run,
+    } = job;
+    let strip_control = strip_control || shared.strip_control;
+    // Wrapped after templating, so a prefix's own `{{braces}}` are left alone
+    let rendered_prompt = shared.wrap_prompt(render_prompt(&prompt_template, &case.input));
+
+    // A data file can pick the model (or provider) per row
+    let model = render_prompt(&model, &case.input);
+    let provider = render_prompt(
+        provider.as_deref().unwrap_or(&shared.provider_name),
+        &case.input,
+    );
+    for (field, value) in [("model", &model), ("provider", &provider)] {
+        if value.trim().is_empty() || value.contains("{{") {
+            let mut failed = failed_result(
+                &test_id,
+                &input_label(&case.input),
+                &model,
+                format!("{} resolved to '{}' for this case", field, value),
+            );
+            failed.input = case.input;
+            return failed;
+        }
+    }
+
+    if let Some(done) = shared
+        .resume
+        .as_ref()
+        .and_then(|r| r.get(&test_id, &case.input, &model, run))
+    {
+        return done;
+    }
+
+    // Parameters the provider can't take are left out, rather than sent
+    // and answered with a 400 that looks like a transient failure
+    let capabilities = shared.capabilities(&provider, &model);
+    let temperature = request_temperature(shared.temperature, &capabilities, &model);
+    let params = if capabilities.supports_sampling {
+        params
+    } else {
+        CompletionParams {
+            stop: Vec::new(),
+            frequency_penalty: None,
+            presence_penalty: None,
+            ..params
+        }
+    };
+
+    // Everything that can change the outcome: prompt, call parameters,
+    // assertions, and the case's position (snapshots, repeats).
+    let fingerprint = {
+        let mut hasher = DefaultHasher::new();
+        test_id.hash(&mut hasher);
+        snapshot_key.hash(&mut hasher);
+        run.hash(&mut hasher);
+        rendered_prompt.hash(&mut hasher);
+        case.images.hash(&mut hasher);
+        provider.hash(&mut hasher);
+        model.hash(&mut hasher);
+        temperature.map(f64::to_bits).hash(&mut hasher);
+        serde_json::to_string(&case.assertions)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        serde_json::to_string(&extract)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        include_thinking.hash(&mut hasher);
+        strip_control.hash(&mut hasher);
+        n.hash(&mut hasher);
+        choices.hash(&mut hasher);
+        serde_json::to_string(&params)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        expect_error.hash(&mut hasher);
+        hasher.finish()
+    };
+    if let Some(prior) = shared.prior.as_ref().and_then(|p| p.get(fingerprint)) {
+        return prior;
+    }
+
+    let request_id = shared.request_id(&snapshot_key, run);
+    let params = CompletionParams {
+        request_id: Some(request_id.clone()),
+        omit_temperature: temperature.is_none(),
+        ..params
+    };
+    let mut result = evaluate_case(
+        Arc::clone(&shared),
+        CaseJob {
+            test_id,
+            prompt_template: rendered_prompt.clone(),
+            model,
+            provider: Some(provider),
+            case,
+            extract,
+            include_thinking,
+            strip_control,
+            n,
+            choices,
+            params,
+            expect_error,
+            snapshot_key,
+            run,
+        },
+    )
+    .await;
+    result.fingerprint = Some(fingerprint);
+    result.prompt = Some(rendered_prompt);
+    result.request_id = Some(request_id);
+    result
+}
+
+/// Call the provider for an already-rendered case and evaluate its assertions.
+async fn evaluate_case(shared: Arc<RunShared>, job: CaseJob) -> CaseResult {
+    let CaseJob {
+        test_id,
+        prompt_template: rendered_prompt,
+        model,
+        provider,
+        case,
+        extract,
+        include_thinking,
+        strip_control,
+        n,
+        choices,
+        params,
+        expect_error,
+        snapshot_key,
+        run,
+        ..
+    } = job;
+    let input = case.input;
+    let input_label = input_label(&input);
+
+    // (raw type, parsed kind, skip_if) per assertion. Values that don't parse
+    // here (e.g. a regex deferred by --no-validate-regex) fail on their own
+    // instead of aborting the case.
+    let parsed_assertions: Vec<(String, Result<AssertionKind, String>, Option<String>)> = case
+        .assertions
+        .iter()
+        .map(|a| {
+            let kind = a
+                .parse()
+                .map(|mut kind| {
+                    kind.default_case_sensitive(shared.case_sensitive_contains);
+                    kind
+                })
+                .map_err(|e| e.to_string());
+            (a.name().to_string(), kind, a.skip_if.clone())
+        })
+        .collect();
+    let has_snapshot = parsed_assertions
+        .iter()
+        .any(|(_, kind, _)| matches!(kind, Ok(AssertionKind::Snapshot { .. })));
+
+    let provider = match shared.provider_for(provider.as_deref().unwrap_or(&shared.provider_name)) {
+        Ok(provider) => provider,
+        Err(e) => {
+            let mut failed = failed_result(&test_id, &input_label, &model, e.to_string());
+            failed.input = input;
+            return failed;
+        }
+    };
+
+    let start = Instant::now();
+    let mut retry_log = Vec::new();
+    let (result, retries) = complete_with_retry(
+        &*provider,
+        &shared.limit,
+        &rendered_prompt,
+        &case.images,
+        &model,
+        // Ignored when `params.omit_temperature` is set
+        resolve_temperature(shared.temperature, &model)
+            .unwrap_or(providers::API_DEFAULT_TEMPERATURE),
+        n,
+        &params,
+        shared.timeout,
+        shared.jitter_key(&snapshot_key, run),
+        |event| retry_log.push(event),
+    )
+    .await;
+    let latency_ms = start.elapsed().as_millis() as u64;
+    let result = result.map(|mut completion| {
+        completion.text = shared.normalize_output.apply(&completion.text);
+        completion.thinking = completion
+            .thinking
+            .map(|thinking| shared.normalize_output.apply(&thinking));
+        for choice in &mut completion.choices {
+            *choice = shared.normalize_output.apply(choice);
+        }
+        completion
+    });
+
+    // Negative tests invert the outcome: the error is what's being checked.
+    if let Some(error_contains) = expect_error {
+        let (detail, output, tokens) = match result {
+            Err(e) => (
+                expected_error_detail(&e.to_string(), error_contains.as_deref()),
+                None,
+                TokenUsage::default(),
+            ),
+            Ok(completion) => (
+                AssertionDetail {
+                    kind: "expect_error".to_string(),
+                    label: "expect_error".to_string(),
+                    passed: false,
+                    detail: "expected an error, but the provider returned a completion".to_string(),
+                    skipped: false,
+                },
+                Some(completion.text),
+                completion.usage,
+            ),
+        };
+        return CaseResult {
+            test_id,
+            input_label,
+            input,
+            passed: detail.passed,
+            latency_ms,
+            assertions: vec![detail],
+            retries,
+            retry_log,
+            cost_usd: providers::calculate_cost(&model, &tokens),
+            tokens,
+            model,
+            output,
+            ..Default::default()
+        };
+    }
+
+    let completion = match result {
+        Ok(completion) => completion,
+        Err(e) => {
+            let mut failed = failed_result(&test_id, &input_label, &model, e.to_string());
+            failed.input = input;
+            failed.latency_ms = latency_ms;
+            failed.retries = retries;
+            failed.retry_log = retry_log;
+            return failed;
+        }
+    };
+
+    let cost = providers::calculate_cost(&model, &completion.usage);
+    let case_snapshot_key = has_snapshot.then(|| snapshot_key.clone());
+
+    // Assertions see the final answer unless the test opts into thinking.
+    // With `n > 1` they run against every choice.
+    let answers: Vec<&str> = if completion.choices.is_empty() {
+        vec![&completion.text]
+    } else {
+        completion.choices.iter().map(String::as_str).collect()
+    };
+    let texts: Vec<Result<String, String>> = answers
+        .into_iter()
+        .map(|answer| {
+            let answer = match completion.thinking {
+                Some(ref thinking) if include_thinking => format!("{}\n\n{}", thinking, answer),
+                _ => answer.to_string(),
+            };
+            // `output` keeps the raw text; only the assertions see it cleaned
+            let answer = if strip_control {
+                crate::config::strip_control(&answer)
+            } else {
+                answer
+            };
+            match extract.as_ref() {
+                None => Ok(answer),
+                Some(extract) => extract.apply(&answer),
+            }
+        })
+        .collect();
+
+    let case_ctx = Arc::new(CaseContext {
+        latency_ms,
+        snapshot_key: snapshot_key.clone(),
+        input: input.clone(),
+        usage: completion.usage.clone(),
+    });
+    let mut per_choice: Vec<Vec<AssertionDetail>> = Vec::with_capacity(texts.len());
+    for text in texts {
+        per_choice.push(match text {
+            Err(e) => vec![AssertionDetail {
+                kind: "extract".to_string(),
+                label: "extract".to_string(),
+                passed: false,
+                detail: e,
+                skipped: false,
+            }],
+            Ok(text) => {
+                evaluate_assertions(&shared, &case_ctx, &parsed_assertions, &Arc::from(text)).await
+            }
+        });
+    }
+    let assertion_results = combine_choices(per_choice, choices);
+
+    let all_passed = assertion_results.iter().all(|a| a.passed);
+    let finish_reason = completion.finish_reason;
+
+    CaseResult {
+        test_id,
+        input_label,
+        input,
+        passed: all_passed,
+        latency_ms,
+        assertions: assertion_results,
+        error: None,
+        retries,
+        tokens: completion.usage,
+        cost_usd: cost,
+        model,
+        output: Some(completion.text),
+        choices: completion.choices,
+        prompt: None,
+        request_id: None,
+        finish_reason,
+        snapshot_key: case_snapshot_key,
+        repeat: None,
+        retry_log,
+        cached: false,
+        fingerprint: None,
+    }
+}
+
+/// What an assertion check needs to know about its case, owned so the
+/// check can run on a blocking thread.
+struct CaseContext {
+    latency_ms: u64,
+    snapshot_key: String,
+    input: HashMap<String, String>,
+    usage: TokenUsage,
+}
+
+/// Evaluate a case's parsed assertions against one output text, honoring
+/// `skip_if` and reusing cached results for identical text.
+async fn evaluate_assertions(
+    shared: &Arc<RunShared>,
+    case: &Arc<CaseContext>,
+    parsed: &[(String, Result<AssertionKind, String>, Option<String>)],
+    text: &Arc<str>,
+) -> Vec<AssertionDetail> {
+    let mut details = Vec::with_capacity(parsed.len());
+    for (raw_kind, kind, skip_if) in parsed {
+        let failed = |detail: String| AssertionDetail {
+            kind: raw_kind.clone(),
+            label: raw_kind.clone(),
+            passed: false,
+            detail,
+            skipped: false,
+        };
+
+        if let Some(cond) = skip_if {
+            match eval_condition(cond, &case.input) {
+                Ok(true) => {
+                    details.push(AssertionDetail {
+                        kind: raw_kind.clone(),
+                        label: raw_kind.clone(),
+                        passed: true,
+                        detail: format!("skipped ({})", cond),
+                        skipped: true,
+                    });
+                    continue;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    details.push(failed(format!("invalid skip_if: {}", e)));
+                    continue;
+                }
+            }
+        }
+
+        let kind = match kind {
+            Ok(kind) => kind,
+            Err(e) => {
+                details.push(failed(e.clone()));
+                continue;
+            }
+        };
+        if let Some(hit) = shared.cache.lookup(kind, text) {
+            details.push(hit);
+            continue;
+        }
+
+        // Command assertions block on a child process, so every check runs
+        // off the async worker threads, and none may outlast the timeout.
+        // Commands enforce it themselves, killing whatever they started;
+        // anything else is cut off here.
+        let runs_command = matches!(
+            kind,
+            AssertionKind::Command(_) | AssertionKind::External { .. }
+        );
+        let check = {
+            let (shared, case) = (Arc::clone(shared), Arc::clone(case));
+            let (kind, text) = (kind.clone(), Arc::clone(text));
+            tokio::task::spawn_blocking(move || {
+                let ctx = AssertionContext {
+                    latency_ms: case.latency_ms,
+                    snapshot_key: &case.snapshot_key,
+                    snapshot_dir: &shared.snapshot_dir,
+                    snapshot_base: shared.snapshot_base.as_ref(),
+                    snapshot_mode: shared.snapshot_mode,
+                    input: &case.input,
+                    usage: &case.usage,
+                    timeout: Some(shared.assertion_timeout),
+                };
+                AssertionDetail::from(check_assertion(&kind, &text, &ctx))
+            })
+        };
+        let timeout = shared.assertion_timeout;
+        let finished = if runs_command {
+            Ok(check.await)
+        } else {
+            time::timeout(timeout, check).await
+        };
+        details.push(match finished {
+            Ok(Ok(mut detail)) => {
+                detail.kind = raw_kind.clone();
+                shared.cache.store(kind, text, &detail);
+                detail
+            }
+            Ok(Err(e)) => failed(format!("evaluation task failed: {}", e)),
+            Err(_) => failed(format!("timed out after {}ms", timeout.as_millis())),
+        });
+    }
+    details
+}
+
+/// Reduce per-choice assertion results (`n > 1`) to one list for the case.
+/// Under `All` that is the first choice that failed, or the first choice if
+/// none did; under `Any`, the first choice that passed everything, or else
+/// the one that passed the most. Details say which choice is shown.
+pub fn combine_choices(
+    mut per_choice: Vec<Vec<AssertionDetail>>,
+    mode: ChoiceMode,
+) -> Vec<AssertionDetail> {
+    let n = per_choice.len();
+    if n <= 1 {
+        return per_choice.pop().unwrap_or_default();
+    }
+
+    let passes = |details: &Vec<AssertionDetail>| details.iter().all(|a| a.passed);
+    let (shown, prefix) = match mode {
+        ChoiceMode::All => match per_choice.iter().position(|d| !passes(d)) {
+            Some(i) => (i, format!("choice {}/{}", i + 1, n)),
+            None => (0, format!("all {} choices", n)),
+        },
+        ChoiceMode::Any => match per_choice.iter().position(passes) {
+            Some(i) => (i, format!("choice {}/{}", i + 1, n)),
+            None => {
+                let passed = |d: &Vec<AssertionDetail>| d.iter().filter(|a| a.passed).count();
+                // max_by_key keeps the last of equals; prefer the first
+                let best = (0..n)
+                    .rev()
+                    .max_by_key(|&i| passed(&per_choice[i]))
+                    .unwrap_or(0);
+                (best, format!("best of {} choices ({})", n, best + 1))
+            }
+        },
+    };
+
+    per_choice
+        .swap_remove(shown)
+        .into_iter()
+        .map(|mut a| {
+            a.detail = format!("{}: {}", prefix, a.detail);
+            a
+        })
+        .collect()
+}
+
+/// Whether a case expects a provider error (`Some`), and the substring the
+/// error must contain. Case settings override the test's; `error_contains`
+/// on its own implies `expect_error`.
+fn expected_error(test: &TestDef, case: &TestCase) -> Option<Option<String>> {
+    let error_contains = case
+        .error_contains
+        .clone()
+        .or_else(|| test.error_contains.clone());
+    let expect = case
+        .expect_error
+        .unwrap_or(test.expect_error || error_contains.is_some());
+    expect.then_some(error_contains)
+}
+
+/// Judge a provider error for a negative test.
+fn expected_error_detail(error: &str, error_contains: Option<&str>) -> AssertionDetail {
+    let (label, passed) = match error_contains {
+        Some(needle) => (
+            format!("expect_error contains \"{}\"", needle),
+            error.to_lowercase().contains(&needle.to_lowercase()),
+        ),
+        None => ("expect_error".to_string(), true),
+    };
+    AssertionDetail {
+        kind: "expect_error".to_string(),
+        label,
+        passed,
+        detail: format!("provider error: {}", error),
+        skipped: false,
+    }
+}
+
+/// `key=value` pairs describing a case's input, for display. Sorted by
+/// key, so the label is the same from run to run.
+fn input_label(input: &HashMap<String, String>) -> String {
+    input
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A result for a case that never produced output.
+fn failed_result(test_id: &str, input_label: &str, model: &str, error: String) -> CaseResult {
+    CaseResult {
+        test_id: test_id.to_string(),
+        input_label: input_label.to_string(),
+        input: HashMap::new(),
+        passed: false,
+        latency_ms: 0,
+        assertions: vec![],
+        error: Some(error),
+        retries: 0,
+        tokens: TokenUsage::default(),
+        cost_usd: 0.0,
+        model: model.to_string(),
+        output: None,
+        choices: Vec::new(),
+        prompt: None,
+        request_id: None,
+        finish_reason: None,
+        snapshot_key: None,
+        repeat: None,
+        retry_log: Vec::new(),
+        cached: false,
+        fingerprint: None,
+    }
+}
+
+// ─── Suite Setup/Teardown ───────────────────────────────────────────────────
+
+/// How long one `setup` or `teardown` command may run before it is killed.
+/// Long-running services should be started in the background (`./server &`).
+pub const SUITE_COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Run one `setup`/`teardown` command in the shell. Its stdout and stderr go
+/// to our stderr, so `--json` output stays clean. Fails if the command can't
+/// be started, exits non-zero or runs longer than `timeout`.
+pub async fn run_suite_command(cmd: &str, timeout: Duration) -> anyhow::Result<()> {
+    let mut command = tokio::process::Command::from(crate::assertions::shell_command(cmd));
+    command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::io::stderr())
+        .kill_on_drop(true);
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("`{}` failed to start: {}", cmd, e))?;
+
+    match time::timeout(timeout, child.wait()).await {
+        Ok(Ok(status)) if status.success() => Ok(()),
+        Ok(Ok(status)) => Err(anyhow::anyhow!(
+            "`{}` {}",
+            cmd,
+            crate::assertions::describe_exit(status)
+        )),
+        Ok(Err(e)) => Err(anyhow::anyhow!("`{}` failed: {}", cmd, e)),
+        Err(_) => {
+            let _ = child.kill().await;
+            Err(anyhow::anyhow!(
+                "`{}` timed out after {}s",
+                cmd,
+                timeout.as_secs_f64()
+            ))
+        }
+    }
+}
+
+/// Run a test's `before` or `after` command in the shell. Unlike suite
+/// commands, what it prints is captured, so a failure can be reported in the
+/// test's results rather than scrolling past on stderr.
+async fn run_test_hook(cmd: &str, timeout: Duration) -> anyhow::Result<()> {
+    let mut command = tokio::process::Command::from(crate::assertions::shell_command(cmd));
+    command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    let child = command
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("`{}` failed to start: {}", cmd, e))?;
+
+    // On timeout the child is dropped with the future, which kills it.
+    match time::timeout(timeout, child.wait_with_output()).await {
+        Ok(Ok(output)) if output.status.success() => Ok(()),
+        Ok(Ok(output)) => {
+            let status = crate::assertions::describe_exit(output.status);
+            let mut printed = String::from_utf8_lossy(&output.stdout).into_owned();
+            printed.push_str(&String::from_utf8_lossy(&output.stderr));
+            match printed.trim() {
+                "" => Err(anyhow::anyhow!("`{}` {}", cmd, status)),
+                printed => Err(anyhow::anyhow!("`{}` {}: {}", cmd, status, printed)),
+            }
+        }
+        Ok(Err(e)) => Err(anyhow::anyhow!("`{}` failed: {}", cmd, e)),
+        Err(_) => Err(anyhow::anyhow!(
+            "`{}` timed out after {}s",
+            cmd,
+            timeout.as_secs_f64()
+        )),
+    }
+}
+
+/// Run the config's `setup` commands in order, stopping at the first failure.
+pub async fn run_setup(config: &Config, timeout: Duration) -> anyhow::Result<()> {
+    for cmd in &config.setup {
+        run_suite_command(cmd, timeout)
+            .await
+            .map_err(|e| anyhow::anyhow!("setup failed: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Run every `teardown` command, even after one fails, and return the
+/// failures so the caller can report them.
+pub async fn run_teardown(config: &Config, timeout: Duration) -> Vec<anyhow::Error> {
+    let mut errors = Vec::new();
+    for cmd in &config.teardown {
+        if let Err(e) = run_suite_command(cmd, timeout).await {
+            errors.push(anyhow::anyhow!("teardown failed: {}", e));
+        }
+    }
+    errors
+}
+
+// ─── Snapshot Review ────────────────────────────────────────────────────────
+
+/// A snapshot whose saved content differs from (or is missing for) the
+/// current output, awaiting accept/reject.
+#[derive(Debug)]
+pub struct SnapshotDiff {
+    pub snapshot_key: String,
+    pub test_id: String,
+    pub input_label: String,
+    /// Saved snapshot content, `None` if no snapshot exists yet
+    pub existing: Option<String>,
+    pub output: String,
+    pub summary: String,
+}
+
+/// Compare every snapshot-bearing result against disk without writing anything.
+pub fn collect_snapshot_diffs(results: &[CaseResult], snapshot_dir: &Path) -> Vec<SnapshotDiff> {
+    let mut diffs = Vec::new();
+
+    for result in results {
+        let (Some(key), Some(output)) = (&result.snapshot_key, &result.output) else {
+            continue;
+        };
+
+        let (existing, summary) = match compare_snapshot(output, key, snapshot_dir) {
+            SnapshotComparison::Matches => continue,
+            SnapshotComparison::Missing => (None, "new snapshot".to_string()),
+            SnapshotComparison::Differs { existing, summary } => (Some(existing), summary),
+            SnapshotComparison::Error(e) => (None, e),
+        };
+
+        diffs.push(SnapshotDiff {
+            snapshot_key: key.clone(),
+            test_id: result.test_id.clone(),
+            input_label: result.input_label.clone(),
+            existing,
+            output: output.clone(),
+            summary,
+        });
+    }
+
+    diffs
+}
+
+// ─── Model Comparison ───────────────────────────────────────────────────────
+
+/// One case's outputs from the two models of `sentinel compare-models`.
+#[derive(Debug, Serialize)]
+pub struct CaseComparison {
+    pub test_id: String,
+    pub input_label: String,
+    /// Word-level similarity of the two outputs (0.0–1.0); `None` when
+    /// either side has no output
+    pub similarity: Option<f64>,
+    pub output_a: Option<String>,
+    pub output_b: Option<String>,
+    /// Why the case couldn't be compared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Side-by-side outcome of running the same suite against two models.
+#[derive(Debug, Serialize)]
+pub struct ModelComparison {
+    pub model_a: String,
+    pub model_b: String,
+    pub passed_a: usize,
+    pub passed_b: usize,
+    /// How many cases each model ran
+    pub total_a: usize,
+    pub total_b: usize,
+    /// Mean similarity over the cases both models answered
+    pub average_similarity: Option<f64>,
+    pub cases: Vec<CaseComparison>,
+}
+
+impl ModelComparison {
+    /// Pair up two runs of the same config, one per model, matching cases
+    /// by test and input (and by occurrence, for repeats). A case only one
+    /// run has is listed with no similarity and says so in `error`.
+    pub fn new(model_a: &str, a: &[CaseResult], model_b: &str, b: &[CaseResult]) -> Self {
+        let mut unmatched_b: HashMap<CaseKey, &CaseResult> = keyed(b).collect();
+        let mut cases: Vec<CaseComparison> = keyed(a)
+            .map(|(key, a)| match unmatched_b.remove(&key) {
+                Some(b) => {
+                    let error = match (&a.error, &b.error) {
+                        (Some(e), _) => Some(format!("{}: {}", model_a, e)),
+                        (None, Some(e)) => Some(format!("{}: {}", model_b, e)),
+                        (None, None) => None,
+                    };
+                    let similarity = match (&a.output, &b.output) {
+                        (Some(x), Some(y)) => Some(crate::assertions::similarity(x, y)),
+                        _ => None,
+                    };
+                    CaseComparison {
+                        test_id: a.test_id.clone(),
+                        input_label: a.input_label.clone(),
+                        similarity,
+                        output_a: a.output.clone(),
+                        output_b: b.output.clone(),
+                        error,
+                    }
+                }
+                None => CaseComparison {
+                    test_id: a.test_id.clone(),
+                    input_label: a.input_label.clone(),
+                    similarity: None,
+                    output_a: a.output.clone(),
+                    output_b: None,
+                    error: Some(format!("only run against {}", model_a)),
+                },
+            })
+            .collect();
+        cases.extend(
+            keyed(b)
+                .filter(|(key, _)| unmatched_b.contains_key(key))
+                .map(|(_, b)| CaseComparison {
+                    test_id: b.test_id.clone(),
+                    input_label: b.input_label.clone(),
+                    similarity: None,
+                    output_a: None,
+                    output_b: b.output.clone(),
+                    error: Some(format!("only run against {}", model_b)),
+                }),
+        );
+
+        let scores: Vec<f64> = cases.iter().filter_map(|c| c.similarity).collect();
+        let average_similarity =
+            (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64);
+
+        ModelComparison {
+            model_a: model_a.to_string(),
+            model_b: model_b.to_string(),
+            passed_a: a.iter().filter(|r| r.passed).count(),
+            passed_b: b.iter().filter(|r| r.passed).count(),
+            total_a: a.len(),
+            total_b: b.len(),
+            average_similarity,
+            cases,
+        }
+    }
+
+    /// How far the models drift apart on average: one minus the mean
+    /// similarity.
+    pub fn drift(&self) -> Option<f64> {
+        self.average_similarity.map(|s| 1.0 - s)
+    }
+
+    /// The `n` compared cases whose outputs differ most, least similar first.
+    pub fn most_divergent(&self, n: usize) -> Vec<&CaseComparison> {
+        let mut compared: Vec<&CaseComparison> = self
+            .cases
+            .iter()
+            .filter(|c| c.similarity.is_some())
+            .collect();
+        compared.sort_by(|x, y| {
+            x.similarity
+                .unwrap_or(0.0)
+                .total_cmp(&y.similarity.unwrap_or(0.0))
+        });
+        compared.truncate(n);
+        compared
+    }
+}
+
+/// Identifies a case across two runs: test ID, input, and how many earlier
+/// results had the same two (for `--repeat`).
+type CaseKey<'r> = (&'r str, BTreeMap<&'r str, &'r str>, usize);
+
+fn keyed(results: &[CaseResult]) -> impl Iterator<Item = (CaseKey<'_>, &CaseResult)> {
+    let mut seen: HashMap<(&str, BTreeMap<&str, &str>), usize> = HashMap::new();
+    results.iter().map(move |r| {
+        let input: BTreeMap<&str, &str> = r
+            .input
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let count = seen.entry((r.test_id.as_str(), input.clone())).or_default();
+        let key = (r.test_id.as_str(), input, *count);
+        *count += 1;
+        (key, r)
+    })
+}
+
+// ─── Printing Logic (moved from main.rs) ────────────────────────────────────
+
+/// Per-case cost levels (USD) above which a case's cost is highlighted.
+/// Purely visual; nothing fails because of them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CostThresholds {
+    pub warn: Option<f64>,
+    pub crit: Option<f64>,
+}
+
+/// How a case's cost compares to the `CostThresholds`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CostLevel {
+    Normal,
+    Warn,
+    Crit,
+}
+
+impl CostThresholds {
+    pub fn level(&self, cost_usd: f64) -> CostLevel {
+        if self.crit.is_some_and(|crit| cost_usd > crit) {
+            CostLevel::Crit
+        } else if self.warn.is_some_and(|warn| cost_usd > warn) {
+            CostLevel::Warn
+        } else {
+            CostLevel::Normal
+        }
+    }
+}
+
+/// Knobs for `print_results`.
+#[derive(Debug, Clone, Default)]
+pub struct PrintOptions {
+    pub verbosity: Verbosity,
+    pub cost_thresholds: CostThresholds,
+    /// Show the N most expensive cases after the summary
+    pub top_cost: Option<usize>,
+    /// Tests that went over their `budget_usd`, listed after the summary
+    pub budget_overages: Vec<BudgetOverage>,
+    /// Repeat up to N failing cases at the end of the output (0 = don't)
+    pub max_failures_shown: usize,
+    /// Collapse identical failures into one entry per `FailureGroup` instead
+    /// of listing each failing case
+    pub group_failures: bool,
+}
+
+/// How many failing cases the summary repeats unless told otherwise.
+pub const DEFAULT_MAX_FAILURES_SHOWN: usize = 10;
+
+/// The first `n` failing cases in run order, each with why it failed: its
+/// error, or else the first failing assertion's label and detail.
+pub fn top_failures(results: &[CaseResult], n: usize) -> Vec<(&CaseResult, String)> {
+    results
+        .iter()
+        .filter(|r| !r.passed)
+        .take(n)
+        .map(|r| {
+            let reason = match &r.error {
+                Some(err) => err.clone(),
+                None => r
+                    .assertions
+                    .iter()
+                    .find(|a| !a.passed && !a.skipped)
+                    .map(|a| format!("{} — {}", a.label, a.detail))
+                    .unwrap_or_default(),
+            };
+            (r, reason)
+        })
+        .collect()
+}
+
+/// Failing cases that failed the same way: the same assertion label and the
+/// same detail once numbers are masked, or the same error.
+#[derive(Debug, Clone)]
+pub struct FailureGroup<'a> {
+    /// The assertion label, or `error` for cases that never got an output
+    pub label: String,
+    /// The first case's detail (or error), as shown
+    pub detail: String,
+    pub count: usize,
+    /// The first few cases in run order
+    pub examples: Vec<&'a CaseResult>,
+}
+
+/// How many example cases each `FailureGroup` keeps.
+pub const FAILURE_GROUP_EXAMPLES: usize = 3;
+
+/// Group every failure across `results`, largest group first (ties keep the
+/// order they were first seen). A case failing two assertions lands in two
+/// groups. Runs of digits in details are masked so "actual: 5 lines" and
+/// "actual: 7 lines" count as one failure.
+pub fn group_failures(results: &[CaseResult]) -> Vec<FailureGroup<'_>> {
+    group_by_reason(results, true)
+}
+
+/// Group the cases that ended in an error (no output, e.g. a provider
+/// outage) by their error message, numbers masked, largest group first.
+pub fn group_errors(results: &[CaseResult]) -> Vec<FailureGroup<'_>> {
+    group_by_reason(results, false)
+}
+
+fn group_by_reason(results: &[CaseResult], assertions: bool) -> Vec<FailureGroup<'_>> {
+    let mask = |text: &str| {
+        let mut masked = String::with_capacity(text.len());
+        let mut in_number = false;
+        for c in text.chars() {
+            if c.is_ascii_digit() {
+                if !in_number {
+                    masked.push('#');
+                }
+                in_number = true;
+            } else {
+                masked.push(c);
+                in_number = false;
+            }
+        }
+        masked
+    };
+
+    let mut groups: Vec<FailureGroup> = Vec::new();
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+    for result in results.iter().filter(|r| !r.passed) {
+        let failures: Vec<(&str, &str)> = match &result.error {
+            Some(err) => vec![("error", err.as_str())],
+            None if !assertions => Vec::new(),
+            None => result
+                .assertions
+                .iter()
+                .filter(|a| !a.passed && !a.skipped)
+                .map(|a| (a.label.as_str(), a.detail.as_str()))
+                .collect(),
+        };
+        for (label, detail) in failures {
+            let key = (label.to_string(), mask(detail));
+            let i = *index.entry(key).or_insert_with(|| {
+                groups.push(FailureGroup {
+                    label: label.to_string(),
+                    detail: detail.to_string(),
+                    count: 0,
+                    examples: Vec::new(),
+                });
+                groups.len() - 1
+            });
+            let group = &mut groups[i];
+            group.count += 1;
+            if group.examples.len() < FAILURE_GROUP_EXAMPLES
+                && !group.examples.iter().any(|e| std::ptr::eq(*e, result))
+            {
+                group.examples.push(result);
+            }
+        }
+    }
+    groups.sort_by_key(|g| std::cmp::Reverse(g.count));
+    groups
+}
+
+/// The `n` most expensive cases, most expensive first. Cases with no cost
+/// are left out; ties keep run order.
+pub fn top_cost(results: &[CaseResult], n: usize) -> Vec<&CaseResult> {
+    let mut costly: Vec<&CaseResult> = results.iter().filter(|r| r.cost_usd > 0.0).collect();
+    costly.sort_by(|a, b| b.cost_usd.total_cmp(&a.cost_usd));
+    costly.truncate(n);
+    costly
+}
+
+/// Pass/fail counts for one assertion type across a run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AssertionTypeStats {
+    pub kind: String,
+    pub passed: usize,
+    pub failed: usize,
+}
+
+/// How many checks of each assertion type passed and failed across the
+/// whole run, sorted by type. Skipped assertions aren't counted.
+pub fn assertion_pass_rates(results: &[CaseResult]) -> Vec<AssertionTypeStats> {
+    let mut counts: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for assertion in results.iter().flat_map(|r| &r.assertions) {
+        if assertion.skipped || assertion.kind.is_empty() {
+            continue;
+        }
+        let entry = counts.entry(&assertion.kind).or_default();
+        if assertion.passed {
+            entry.0 += 1;
+        } else {
+            entry.1 += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .map(|(kind, (passed, failed))| AssertionTypeStats {
+            kind: kind.to_string(),
+            passed,
+            failed,
+        })
+        .collect()
+}
+
+/// Aggregates over a run's results, shared by the printed summary, the JSON
+/// report and `sentinel stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunStats {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub cost_usd: f64,
+    pub tokens: TokenUsage,
+    /// Over cases that got a response; all zero when none did
+    pub latency_ms: LatencyStats,
+    /// One entry per test, in the order tests first appear
+    pub tests: Vec<TestStats>,
+    /// Assertions that failed at least once, most failures first
+    pub failing_assertions: Vec<FailingAssertion>,
+}
+
+/// Nearest-rank latency percentiles.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LatencyStats {
+    pub p50: u64,
+    pub p90: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+/// Totals for one test's cases.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestStats {
+    pub test_id: String,
+    pub total: usize,
+    pub passed: usize,
+    pub cost_usd: f64,
+    pub mean_latency_ms: u64,
 }
 
-#[derive(Debug, Serialize)]
-pub struct AssertionDetail {
+/// How often one assertion (type and label) failed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailingAssertion {
+    pub kind: String,
     pub label: String,
-    pub passed: bool,
-    pub detail: String,
+    pub failed: usize,
 }
 
-impl From<AssertionResult> for AssertionDetail {
-    fn from(r: AssertionResult) -> Self {
+impl RunStats {
+    pub fn new(results: &[CaseResult]) -> Self {
+        let passed = results.iter().filter(|r| r.passed).count();
+        let mut tokens = TokenUsage::default();
+        for r in results {
+            tokens.prompt_tokens += r.tokens.prompt_tokens;
+            tokens.completion_tokens += r.tokens.completion_tokens;
+            tokens.total_tokens += r.tokens.total_tokens;
+            tokens.reasoning_tokens += r.tokens.reasoning_tokens;
+        }
+
+        let mut latencies: Vec<u64> = results
+            .iter()
+            .filter(|r| r.error.is_none())
+            .map(|r| r.latency_ms)
+            .collect();
+        latencies.sort_unstable();
+        let percentile = |p: usize| match latencies.len() {
+            0 => 0,
+            n => latencies[(p * n).div_ceil(100).max(1) - 1],
+        };
+        let latency_ms = LatencyStats {
+            p50: percentile(50),
+            p90: percentile(90),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: latencies.last().copied().unwrap_or(0),
+        };
+
+        let mut tests: Vec<TestStats> = Vec::new();
+        let mut latency_sums: Vec<u64> = Vec::new();
+        for r in results {
+            let i = match tests.iter().position(|t| t.test_id == r.test_id) {
+                Some(i) => i,
+                None => {
+                    tests.push(TestStats {
+                        test_id: r.test_id.clone(),
+                        total: 0,
+                        passed: 0,
+                        cost_usd: 0.0,
+                        mean_latency_ms: 0,
+                    });
+                    latency_sums.push(0);
+                    tests.len() - 1
+                }
+            };
+            tests[i].total += 1;
+            tests[i].passed += usize::from(r.passed);
+            tests[i].cost_usd += r.cost_usd;
+            latency_sums[i] += r.latency_ms;
+        }
+        for (test, sum) in tests.iter_mut().zip(latency_sums) {
+            test.mean_latency_ms = sum / test.total as u64;
+        }
+
+        let mut failing_assertions: Vec<FailingAssertion> = Vec::new();
+        for a in results.iter().flat_map(|r| &r.assertions) {
+            if a.passed || a.skipped {
+                continue;
+            }
+            match failing_assertions
+                .iter_mut()
+                .find(|f| f.kind == a.kind && f.label == a.label)
+            {
+                Some(f) => f.failed += 1,
+                None => failing_assertions.push(FailingAssertion {
+                    kind: a.kind.clone(),
+                    label: a.label.clone(),
+                    failed: 1,
+                }),
+            }
+        }
+        failing_assertions.sort_by_key(|f| std::cmp::Reverse(f.failed));
+
         Self {
-            label: r.label,
-            passed: r.passed,
-            detail: r.detail,
+            total: results.len(),
+            passed,
+            failed: results.len() - passed,
+            cost_usd: results.iter().map(|r| r.cost_usd).sum(),
+            tokens,
+            latency_ms,
+            tests,
+            failing_assertions,
+        }
+    }
+
+    /// Fraction of cases that passed, 0.0 to 1.0 (1.0 for an empty run).
+    pub fn pass_rate(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.passed as f64 / self.total as f64
         }
     }
 }
 
-/// Max retry attempts for transient API errors.
-const MAX_RETRIES: u32 = 3;
-/// Base delay for exponential backoff (doubles each retry: 500ms → 1s → 2s).
-const BASE_RETRY_DELAY_MS: u64 = 500;
+/// Total spend of the run if it is above `limit_usd` (`--fail-on-cost`).
+pub fn over_cost_limit(results: &[CaseResult], limit_usd: f64) -> Option<f64> {
+    let total: f64 = results.iter().map(|r| r.cost_usd).sum();
+    (total > limit_usd).then_some(total)
+}
 
-/// Attempt an LLM completion with retry + exponential backoff + timeout.
-async fn complete_with_retry(
-    provider: &dyn LlmProvider,
-    prompt: &str,
-    model: &str,
-    temperature: f64,
-    timeout_ms: u64,
-) -> (Result<providers::CompletionResult, anyhow::Error>, u32) {
-    let mut retries = 0;
-    let timeout_dur = Duration::from_millis(timeout_ms);
+/// Distinct models the run used that have no pricing data, so their cases
+/// reported $0 (`--require-pricing`). Sorted by name.
+pub fn unpriced_models(results: &[CaseResult]) -> Vec<String> {
+    let models: std::collections::BTreeSet<&str> = results
+        .iter()
+        .map(|r| r.model.as_str())
+        .filter(|m| !m.is_empty() && crate::providers::model_pricing(m).is_none())
+        .collect();
+    models.into_iter().map(str::to_string).collect()
+}
 
-    loop {
-        let attempt =
-            time::timeout(timeout_dur, provider.complete(prompt, model, temperature)).await;
+/// A test whose cases together cost more than its `budget_usd`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetOverage {
+    pub test_id: String,
+    pub budget_usd: f64,
+    pub spent_usd: f64,
+}
 
-        let result = match attempt {
-            Ok(inner) => inner,
-            Err(_) => Err(anyhow::anyhow!("request timed out after {}ms", timeout_ms)),
-        };
+/// Tests that spent more than their `budget_usd` in this run, in config
+/// order. Repeats count toward the budget like any other call.
+pub fn budget_overages(config: &Config, results: &[CaseResult]) -> Vec<BudgetOverage> {
+    config
+        .tests
+        .iter()
+        .filter_map(|test| {
+            let budget_usd = test.budget_usd?;
+            let spent_usd: f64 = results
+                .iter()
+                .filter(|r| r.test_id == test.id)
+                .map(|r| r.cost_usd)
+                .sum();
+            (spent_usd > budget_usd).then(|| BudgetOverage {
+                test_id: test.id.clone(),
+                budget_usd,
+                spent_usd,
+            })
+        })
+        .collect()
+}
 
-        match result {
-            Ok(output) => return (Ok(output), retries),
-            Err(e) => {
-                let err_msg = e.to_string();
-                let is_transient = err_msg.contains("429")
-                    || err_msg.contains("500")
-                    || err_msg.contains("502")
-                    || err_msg.contains("503")
-                    || err_msg.contains("timeout")
-                    || err_msg.contains("timed out")
-                    || err_msg.contains("connection");
+/// What `annotate_costs` made of the config text.
+#[derive(Debug)]
+pub struct CostAnnotations {
+    pub text: String,
+    /// How many tests were annotated
+    pub annotated: usize,
+    /// Tests that ran but are written in flow style (`- { id: ... }`), which
+    /// are left alone
+    pub skipped: Vec<String>,
+}
 
-                if is_transient && retries < MAX_RETRIES {
-                    retries += 1;
-                    let delay = BASE_RETRY_DELAY_MS * 2u64.pow(retries - 1);
-                    time::sleep(Duration::from_millis(delay)).await;
-                    continue;
-                }
+/// Write each test's observed average cost and latency into the config as a
+/// trailing comment on its `id:` line (`--annotate-costs`), e.g.
+/// `- id: "greet"  # ~$0.0003/case, ~820ms`. `tests` is the parsed config,
+/// whose entries are matched to the `tests:` list in the text by position.
+/// The file is edited line by line so its formatting, line endings and other
+/// comments survive; an annotation from an earlier run is replaced.
+pub fn annotate_costs(yaml: &str, tests: &[TestDef], results: &[CaseResult]) -> CostAnnotations {
+    let id_line = regex::Regex::new(r#"^(\s*(?:-\s+)?id:\s*)(["']?)([^"'#]+?)(["']?)(\s*#.*)?$"#)
+        .expect("valid regex");
+    let annotation = regex::Regex::new(r"\s*# ~\$[0-9.]+/case, ~\d+ms$").expect("valid regex");
 
-                return (Err(e), retries);
+    let (mut lines, endings): (Vec<String>, Vec<&str>) = yaml
+        .split_inclusive('\n')
+        .map(|line| {
+            let body = line.trim_end_matches(['\r', '\n']);
+            (body.to_string(), &line[body.len()..])
+        })
+        .unzip();
+
+    let mut annotated = 0;
+    let mut skipped = Vec::new();
+    for (test, entry) in tests.iter().zip(test_entries(&lines, tests.len())) {
+        let runs: Vec<&CaseResult> = results.iter().filter(|r| r.test_id == test.id).collect();
+        if runs.is_empty() {
+            continue;
+        }
+        let index = match entry {
+            TestEntry::Block {
+                id_line: Some(index),
+            } => index,
+            TestEntry::Block { id_line: None } => continue,
+            TestEntry::Flow => {
+                skipped.push(test.id.clone());
+                continue;
             }
+        };
+        let line = &lines[index];
+        if id_line.captures(line).is_none_or(|caps| caps[3] != test.id) {
+            continue;
         }
+
+        let n = runs.len() as f64;
+        let cost = runs.iter().map(|r| r.cost_usd).sum::<f64>() / n;
+        let latency = runs.iter().map(|r| r.latency_ms as f64).sum::<f64>() / n;
+        let base = annotation.replace(line, "");
+        let precision = if cost > 0.0 && cost < 0.0001 { 6 } else { 4 };
+        lines[index] = format!(
+            "{}  # ~${:.*}/case, ~{:.0}ms",
+            base, precision, cost, latency
+        );
+        annotated += 1;
     }
-}
 
-/// Run all tests from the config in parallel (bounded by concurrency limit).
-pub async fn run_all_tests(
-    config: &Config,
-    provider: Arc<dyn LlmProvider>,
-    concurrency: usize,
-    verbosity: Verbosity,
-    json_mode: bool,
-    update_snapshots: bool,
-    timeout_ms: u64,
-    filter: Option<&str>,
-) -> Vec<CaseResult> {
-    // Filter tests by ID if --filter is specified
-    let tests: Vec<_> = config
-        .tests
+    let text = lines
         .iter()
-        .filter(|t| match filter {
-            Some(pattern) => t.id.contains(pattern),
-            None => true,
-        })
+        .zip(endings)
+        .map(|(line, ending)| format!("{}{}", line, ending))
         .collect();
+    CostAnnotations {
+        text,
+        annotated,
+        skipped,
+    }
+}
 
-    let total_cases: usize = tests.iter().map(|t| t.cases.len()).sum();
+/// How one entry of the top-level `tests:` list is written.
+#[derive(Debug, Clone, Copy)]
+enum TestEntry {
+    /// A block mapping, with the line its `id:` key is on
+    Block { id_line: Option<usize> },
+    /// A flow mapping (`- { id: a, ... }`), or the whole list in flow style
+    Flow,
+}
 
-    // Show progress bar only in Normal/Verbose mode (not quiet, not json)
-    let show_progress = !json_mode && verbosity != Verbosity::Quiet;
-    let pb = if show_progress && total_cases > 0 {
-        let pb = ProgressBar::new(total_cases as u64);
-        pb.set_style(
-            ProgressStyle::with_template(
-                "  {spinner:.cyan} [{bar:30.green/dim}] {pos}/{len} tests ({eta} remaining)",
-            )
-            .unwrap()
-            .progress_chars("█▓░"),
-        );
-        pb.enable_steady_tick(Duration::from_millis(120));
-        Some(pb)
-    } else {
-        None
+/// The entries of the top-level `tests:` list in `lines`, in file order.
+/// `count` is how many the parsed config has, assumed all flow style when
+/// the list itself is (`tests: [...]`).
+fn test_entries(lines: &[String], count: usize) -> Vec<TestEntry> {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let is_content = |line: &str| {
+        let trimmed = line.trim_start();
+        !trimmed.is_empty() && !trimmed.starts_with('#')
     };
 
-    let pb_arc = pb.as_ref().map(|p| Arc::new(p.clone()));
-
-    let mut handles: Vec<JoinHandle<CaseResult>> = Vec::new();
-    let semaphore = Arc::new(Semaphore::new(concurrency));
-
-    let default_model = config.defaults.model.clone();
-    let default_temp = config.defaults.temperature;
-    let snapshot_dir = PathBuf::from(".snapshots");
-
-    for test in &tests {
-        let test_id = test.id.clone();
-        let prompt_template = test.prompt.clone();
-        let model = test.model.clone().unwrap_or_else(|| default_model.clone());
-
-        for (ci, case) in test.cases.iter().enumerate() {
-            let provider = Arc::clone(&provider);
-            let semaphore = Arc::clone(&semaphore);
-            let pb_arc = pb_arc.clone();
-            let test_id = test_id.clone();
-            let prompt_template = prompt_template.clone();
-            let model = model.clone();
-            let input = case.input.clone();
-            let raw_assertions = case.assertions.clone();
-            let temperature = default_temp;
-            let snapshot_dir = snapshot_dir.clone();
-            let snapshot_key = format!("{}_case{}", test_id, ci);
+    let Some(start) = lines.iter().position(|l| l.starts_with("tests:")) else {
+        return Vec::new();
+    };
+    if lines[start]["tests:".len()..].trim_start().starts_with('[') {
+        return vec![TestEntry::Flow; count];
+    }
 
-            let handle = tokio::spawn(async move {
-                let _permit = semaphore.acquire().await.expect("semaphore closed");
+    let mut entries = Vec::new();
+    let mut item_indent = None;
+    // Column of the current block entry's keys, once known
+    let mut key_indent = None;
+    for (index, line) in lines.iter().enumerate().skip(start + 1) {
+        if !is_content(line) {
+            continue;
+        }
+        let trimmed = line.trim_start();
+        let is_item = trimmed == "-" || trimmed.starts_with("- ");
+        let items = *item_indent.get_or_insert(indent(line));
+        if indent(line) < items || (indent(line) == items && !is_item) {
+            break;
+        }
 
-                let rendered_prompt = render_prompt(&prompt_template, &input);
-                let input_label = input
-                    .iter()
-                    .map(|(k, v)| format!("{}={}", k, v))
-                    .collect::<Vec<_>>()
-                    .join(", ");
+        if indent(line) == items && is_item {
+            let content = trimmed[1..].trim_start();
+            if content.starts_with('{') {
+                entries.push(TestEntry::Flow);
+                key_indent = None;
+                continue;
+            }
+            key_indent = (!content.is_empty()).then(|| line.len() - content.len());
+            entries.push(TestEntry::Block {
+                id_line: content.starts_with("id:").then_some(index),
+            });
+            continue;
+        }
 
-                let parsed_assertions: Vec<AssertionKind> = raw_assertions
-                    .iter()
-                    .filter_map(|a| AssertionKind::from_raw(&a.kind, &a.value).ok())
-                    .collect();
-
-                let start = Instant::now();
-                let (result, retries) = complete_with_retry(
-                    &*provider,
-                    &rendered_prompt,
-                    &model,
-                    temperature,
-                    timeout_ms,
-                )
-                .await;
-                let latency_ms = start.elapsed().as_millis() as u64;
-
-                let case_result = match result {
-                    Ok(completion) => {
-                        let cost = providers::calculate_cost(&model, &completion.usage);
-                        let output_text = completion.text.clone();
-
-                        let assertion_results: Vec<AssertionDetail> = parsed_assertions
-                            .iter()
-                            .map(|kind| {
-                                check_assertion(
-                                    kind,
-                                    &completion.text,
-                                    latency_ms,
-                                    &snapshot_key,
-                                    &snapshot_dir,
-                                    update_snapshots,
-                                )
-                                .into()
-                            })
-                            .collect();
-
-                        let all_passed = assertion_results.iter().all(|a| a.passed);
-
-                        CaseResult {
-                            test_id,
-                            input_label,
-                            passed: all_passed,
-                            latency_ms,
-                            assertions: assertion_results,
-                            error: None,
-                            retries,
-                            tokens: completion.usage,
-                            cost_usd: cost,
-                            model,
-                            output: Some(output_text),
-                        }
-                    }
-                    Err(e) => CaseResult {
-                        test_id,
-                        input_label,
-                        passed: false,
-                        latency_ms,
-                        assertions: vec![],
-                        error: Some(e.to_string()),
-                        retries,
-                        tokens: TokenUsage::default(),
-                        cost_usd: 0.0,
-                        model,
-                        output: None,
-                    },
-                };
+        let Some(TestEntry::Block { id_line }) = entries.last_mut() else {
+            continue;
+        };
+        let keys = *key_indent.get_or_insert(indent(line));
+        if id_line.is_none() && indent(line) == keys && trimmed.starts_with("id:") {
+            *id_line = Some(index);
+        }
+    }
+    entries
+}
 
-                if let Some(ref pb) = pb_arc {
-                    pb.inc(1);
-                }
+/// "3 distinct outputs across 10 runs: "A"×6, "B"×3, "C"×1" for one
+/// repeated case, each output cut to a one-line preview.
+fn print_distinct_outputs(runs: &[CaseResult]) {
+    let distinct = distinct_outputs(runs);
+    if distinct.is_empty() {
+        return;
+    }
+    let counts: Vec<String> = distinct
+        .iter()
+        .map(|(output, count)| {
+            let preview = output.split_whitespace().collect::<Vec<_>>().join(" ");
+            format!(
+                "\"{}\"×{}",
+                crate::assertions::truncate(&preview, 40),
+                count
+            )
+        })
+        .collect();
+    println!(
+        "  {} {} │ {} │ {} distinct output{} across {} runs: {}",
+        "≋".bright_cyan(),
+        runs[0].test_id.bold(),
+        runs[0].input_label.bright_black(),
+        distinct.len(),
+        if distinct.len() == 1 { "" } else { "s" },
+        runs.len(),
+        counts.join(", ")
+    );
+    println!();
+}
 
-                case_result
-            });
+/// Summary of `sentinel compare-models`: pass counts, average similarity
+/// and drift, then the `top` most divergent cases with both outputs.
+pub fn print_model_comparison(comparison: &ModelComparison, top: usize) {
+    let preview = |output: &Option<String>| {
+        let text = output.as_deref().unwrap_or("");
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        crate::assertions::truncate(&text, 80)
+    };
 
-            handles.push(handle);
+    println!();
+    println!(
+        "  {} {} vs {}",
+        "⇄".bright_cyan(),
+        comparison.model_a.bold(),
+        comparison.model_b.bold()
+    );
+    println!();
+    println!(
+        "  {:<24} {}/{}",
+        comparison.model_a, comparison.passed_a, comparison.total_a
+    );
+    println!(
+        "  {:<24} {}/{}",
+        comparison.model_b, comparison.passed_b, comparison.total_b
+    );
+    match (comparison.average_similarity, comparison.drift()) {
+        (Some(similarity), Some(drift)) => {
+            println!("  {:<24} {:.2}", "Average similarity", similarity);
+            println!("  {:<24} {:.2}", "Drift", drift);
         }
+        _ => println!(
+            "  {:<24} {}",
+            "Average similarity",
+            "n/a (no case answered by both)".bright_black()
+        ),
     }
 
-    let mut results = Vec::with_capacity(handles.len());
-    for handle in handles {
-        match handle.await {
-            Ok(case_result) => results.push(case_result),
-            Err(e) => results.push(CaseResult {
-                test_id: "unknown".to_string(),
-                input_label: "unknown".to_string(),
-                passed: false,
-                latency_ms: 0,
-                assertions: vec![],
-                error: Some(format!("Task join error: {}", e)),
-                retries: 0,
-                tokens: TokenUsage::default(),
-                cost_usd: 0.0,
-                model: "unknown".to_string(),
-                output: None,
-            }),
+    let errors: Vec<&CaseComparison> = comparison
+        .cases
+        .iter()
+        .filter(|c| c.error.is_some())
+        .collect();
+    if !errors.is_empty() {
+        println!();
+        for case in errors {
+            println!(
+                "  {} {} │ {} │ {}",
+                "✗".red(),
+                case.test_id.bold(),
+                case.input_label.bright_black(),
+                case.error.as_deref().unwrap_or("").red()
+            );
         }
     }
 
-    if let Some(pb) = pb {
-        pb.finish_and_clear();
+    let divergent = comparison.most_divergent(top);
+    if !divergent.is_empty() {
+        println!();
+        println!("  {}", "Most divergent".bold());
+        for case in divergent {
+            println!(
+                "  {} {} │ {} │ similarity {:.2}",
+                "≠".yellow(),
+                case.test_id.bold(),
+                case.input_label.bright_black(),
+                case.similarity.unwrap_or(0.0)
+            );
+            println!(
+                "       {} {}",
+                format!("{}:", comparison.model_a).bright_black(),
+                preview(&case.output_a)
+            );
+            println!(
+                "       {} {}",
+                format!("{}:", comparison.model_b).bright_black(),
+                preview(&case.output_b)
+            );
+        }
     }
-
-    results
+    println!();
 }
 
-// ─── Printing Logic (moved from main.rs) ────────────────────────────────────
-
-pub fn print_results(results: &[CaseResult], verbosity: Verbosity) {
-    let total = results.len();
-    let passed = results.iter().filter(|r| r.passed).count();
-    let failed = total - passed;
-    let total_cost: f64 = results.iter().map(|r| r.cost_usd).sum();
-    let total_tokens: u32 = results.iter().map(|r| r.tokens.total_tokens).sum();
+pub fn print_results(results: &[CaseResult], options: &PrintOptions) {
+    let verbosity = options.verbosity;
+    let stats = RunStats::new(results);
+    let (total, passed, failed) = (stats.total, stats.passed, stats.failed);
+    let total_cost = stats.cost_usd;
+    let total_tokens = stats.tokens.total_tokens;
 
     if verbosity == Verbosity::Quiet {
         // Quiet mode: one-liner summary only
@@ -326,7 +3008,23 @@ pub fn print_results(results: &[CaseResult], verbosity: Verbosity) {
     // because watch mode prints its own header.
     // Or we keep it. Let's keep it simple.
 
-    for result in results {
+    // Verbose mode sums up each repeated case after its last run
+    let repeated = if verbosity == Verbosity::Verbose {
+        repeat_groups(results)
+    } else {
+        Vec::new()
+    };
+    let print_variety = |i: usize| {
+        if let Some(range) = repeated.iter().find(|r| r.end == i + 1) {
+            print_distinct_outputs(&results[range.clone()]);
+        }
+    };
+
+    for (i, result) in results.iter().enumerate() {
+        if options.group_failures && !result.passed {
+            print_variety(i);
+            continue;
+        }
         let status = if result.passed {
             "PASS".green().bold()
         } else {
@@ -340,9 +3038,14 @@ pub fn print_results(results: &[CaseResult], verbosity: Verbosity) {
         };
 
         let cost_info = if result.cost_usd > 0.0 {
-            format!(" · ${:.5}", result.cost_usd)
+            let cost = format!(" · ${:.5}", result.cost_usd);
+            match options.cost_thresholds.level(result.cost_usd) {
+                CostLevel::Normal => cost.bright_black(),
+                CostLevel::Warn => cost.yellow(),
+                CostLevel::Crit => cost.red().bold(),
+            }
         } else {
-            String::new()
+            "".normal()
         };
 
         let token_info = if result.tokens.total_tokens > 0 {
@@ -351,23 +3054,96 @@ pub fn print_results(results: &[CaseResult], verbosity: Verbosity) {
             String::new()
         };
 
+        let mut run_info = result
+            .repeat
+            .map(|run| format!(" · run {}", run))
+            .unwrap_or_default();
+        if result.cached {
+            run_info.push_str(" · cached");
+        }
+
         println!(
-            "  {} │ {} │ {} │ {}ms{}{}{}",
+            "  {} │ {} │ {}{} │ {}ms{}{}{}",
             status,
             result.test_id.bold(),
             result.input_label.bright_black(),
+            run_info.bright_black(),
             result.latency_ms,
             retry_info.yellow(),
             token_info.bright_black(),
-            cost_info.bright_black()
+            cost_info
         );
 
+        if verbosity == Verbosity::Verbose {
+            for event in &result.retry_log {
+                println!(
+                    "       {} {}",
+                    "↻".yellow(),
+                    format!(
+                        "retry {}/{} after {}ms ({}ms backoff + {}ms jitter, reason: {})",
+                        event.attempt,
+                        MAX_RETRIES,
+                        event.delay_ms,
+                        event.delay_ms - event.jitter_ms,
+                        event.jitter_ms,
+                        event.reason
+                    )
+                    .yellow()
+                );
+            }
+        }
+
         if let Some(ref err) = result.error {
             println!("       {} {}", "error:".red(), err);
         }
+        if providers::is_truncation(result.finish_reason.as_deref()) {
+            println!(
+                "       {} {}",
+                "⚠".yellow(),
+                format!(
+                    "output was cut off by max_tokens (finish_reason: {}); later content is missing",
+                    result.finish_reason.as_deref().unwrap_or_default()
+                )
+                .yellow()
+            );
+        }
 
+        // Normal mode keeps long assertion lists short: the ratio, then
+        // only what failed. Verbose shows every assertion.
+        let checked = result.assertions.iter().filter(|a| !a.skipped).count();
+        if checked > 0 {
+            let passed = result
+                .assertions
+                .iter()
+                .filter(|a| a.passed && !a.skipped)
+                .count();
+            let skipped = result.assertions.len() - checked;
+            let ratio = format!(
+                "{}/{} assertions passed{}",
+                passed,
+                checked,
+                if skipped > 0 {
+                    format!(" ({} skipped)", skipped)
+                } else {
+                    String::new()
+                }
+            );
+            println!(
+                "       {}",
+                if passed == checked {
+                    ratio.bright_black()
+                } else {
+                    ratio.red()
+                }
+            );
+        }
         for assertion in &result.assertions {
-            let icon = if assertion.passed {
+            if verbosity != Verbosity::Verbose && (assertion.passed || assertion.skipped) {
+                continue;
+            }
+            let icon = if assertion.skipped {
+                "–".dimmed()
+            } else if assertion.passed {
                 "✓".green()
             } else {
                 "✗".red()
@@ -380,22 +3156,49 @@ pub fn print_results(results: &[CaseResult], verbosity: Verbosity) {
             );
         }
 
-        // Verbose mode: show full LLM output
+        // Verbose mode: show the prompt as sent and the full LLM output
         if verbosity == Verbosity::Verbose {
-            if let Some(ref output) = result.output {
+            if let Some(ref prompt) = result.prompt {
+                println!(
+                    "       {} {}",
+                    "prompt:".bright_cyan().bold(),
+                    "─".repeat(40).bright_black()
+                );
+                for line in prompt.lines() {
+                    println!("       │ {}", line.bright_black());
+                }
+            }
+            let outputs: Vec<(String, &String)> = if result.choices.is_empty() {
+                result
+                    .output
+                    .iter()
+                    .map(|o| ("output:".to_string(), o))
+                    .collect()
+            } else {
+                result
+                    .choices
+                    .iter()
+                    .enumerate()
+                    .map(|(i, o)| (format!("choice {}:", i + 1), o))
+                    .collect()
+            };
+            for (heading, output) in outputs {
                 println!(
                     "       {} {}",
-                    "output:".bright_cyan().bold(),
+                    heading.bright_cyan().bold(),
                     "─".repeat(40).bright_black()
                 );
                 for line in output.lines() {
                     println!("       │ {}", line.bright_black());
                 }
+            }
+            if result.output.is_some() {
                 println!("       {}", "─".repeat(48).bright_black());
             }
         }
 
         println!();
+        print_variety(i);
     }
 
     println!(
@@ -410,6 +3213,26 @@ pub fn print_results(results: &[CaseResult], verbosity: Verbosity) {
         failed,
         total
     );
+    let rates = assertion_pass_rates(results);
+    if !rates.is_empty() {
+        let parts: Vec<String> = rates
+            .iter()
+            .map(|stats| {
+                let part = format!(
+                    "{} {}/{}",
+                    stats.kind,
+                    stats.passed,
+                    stats.passed + stats.failed
+                );
+                if stats.failed > 0 {
+                    part.red().to_string()
+                } else {
+                    part
+                }
+            })
+            .collect();
+        println!("  {} {}", "📊".bright_cyan(), parts.join(" · "));
+    }
     if total_tokens > 0 || total_cost > 0.0 {
         println!(
             "  {} {} tokens · ${:.6} estimated cost",
@@ -418,6 +3241,131 @@ pub fn print_results(results: &[CaseResult], verbosity: Verbosity) {
             total_cost
         );
     }
+
+    let truncated = results
+        .iter()
+        .filter(|r| providers::is_truncation(r.finish_reason.as_deref()))
+        .count();
+    if truncated > 0 {
+        println!(
+            "  {} {} case(s) stopped at max_tokens; raise max_tokens if assertions look for later content",
+            "⚠".yellow(),
+            truncated
+        );
+    }
+
+    for overage in &options.budget_overages {
+        println!(
+            "  {} {} spent ${:.4}, over its ${:.4} budget by ${:.4} (+{:.0}%)",
+            "💸".red(),
+            overage.test_id.bold(),
+            overage.spent_usd,
+            overage.budget_usd,
+            overage.spent_usd - overage.budget_usd,
+            (overage.spent_usd / overage.budget_usd - 1.0) * 100.0
+        );
+    }
+
+    if let Some(n) = options.top_cost {
+        let top = top_cost(results, n);
+        if !top.is_empty() {
+            println!();
+            println!("  {} Most expensive cases:", "💸".bright_yellow());
+            for (i, r) in top.iter().enumerate() {
+                let share = if total_cost > 0.0 {
+                    r.cost_usd / total_cost * 100.0
+                } else {
+                    0.0
+                };
+                println!(
+                    "    {}. ${:.5} ({:.0}%) │ {} │ {}",
+                    i + 1,
+                    r.cost_usd,
+                    share,
+                    r.test_id.bold(),
+                    r.input_label.bright_black()
+                );
+            }
+        }
+    }
+
+    let groups = if options.group_failures {
+        group_failures(results)
+    } else {
+        Vec::new()
+    };
+    if !groups.is_empty() {
+        println!();
+        println!(
+            "  {} {} failure(s) in {} group(s):",
+            "✗".red().bold(),
+            groups.iter().map(|g| g.count).sum::<usize>(),
+            groups.len()
+        );
+        for group in &groups {
+            println!(
+                "    {} {} — {}",
+                format!("{}×", group.count).red().bold(),
+                group.label.dimmed(),
+                group.detail
+            );
+            for example in &group.examples {
+                println!(
+                    "        e.g. {} │ {}",
+                    example.test_id.bold(),
+                    example.input_label.bright_black()
+                );
+            }
+        }
+    }
+
+    // Already part of the failure groups when those are shown
+    let errors = if options.group_failures {
+        Vec::new()
+    } else {
+        group_errors(results)
+    };
+    if !errors.is_empty() {
+        println!();
+        println!(
+            "  {} {} error(s), {} distinct:",
+            "⚠".red().bold(),
+            errors.iter().map(|g| g.count).sum::<usize>(),
+            errors.len()
+        );
+        for group in &errors {
+            println!(
+                "    {} {}",
+                format!("{}×", group.count).red().bold(),
+                group.detail
+            );
+        }
+    }
+
+    let failures = if options.group_failures {
+        Vec::new()
+    } else {
+        top_failures(results, options.max_failures_shown)
+    };
+    if !failures.is_empty() {
+        println!();
+        println!("  {} Top failures:", "✗".red().bold());
+        for (r, reason) in &failures {
+            println!(
+                "    {} │ {} │ {}",
+                r.test_id.bold(),
+                r.input_label.bright_black(),
+                reason
+            );
+        }
+        if failed > failures.len() {
+            println!(
+                "    {}",
+                format!("… and {} more", failed - failures.len()).bright_black()
+            );
+        }
+    }
+
     println!(
         "{}",
         "━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━".bright_black()
@@ -1,26 +1,38 @@
-use crate::config;
-use crate::providers;
-use crate::report;
-use crate::runner::{self, Verbosity};
 use colored::*;
 use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use prompt_sentinel::assertions::SnapshotMode;
+use prompt_sentinel::config;
+use prompt_sentinel::providers;
+use prompt_sentinel::report;
+use prompt_sentinel::runner::{self, Verbosity};
 use std::path::Path;
 use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+/// How often a browser showing the `--serve` report polls for a new run.
+const LIVE_REFRESH_SECS: u32 = 2;
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run_watch_loop(
     file: &str,
     json: bool,
+    compact: bool,
     upload: bool,
     _token: Option<String>,
-    concurrency: usize,
+    concurrency: Option<usize>,
+    provider_plugin: Option<&str>,
     timeout: u64,
     update_snapshots: bool,
+    snapshot_dir: Option<String>,
     no_validate: bool,
+    validate: config::ValidateOptions,
     filter: Option<String>,
     report_path: Option<Option<String>>,
     verbosity: Verbosity,
+    profile: Option<String>,
+    serve: Option<u16>,
+    only_changed_cases: bool,
 ) -> anyhow::Result<()> {
     println!(
         "  {} {}",
@@ -28,19 +40,50 @@ pub async fn run_watch_loop(
         format!("Watching {} for changes...", file).bold()
     );
 
+    // Loaded once; every cycle calls the same library
+    let plugin: Option<Arc<dyn providers::LlmProvider>> = match provider_plugin {
+        Some(path) => Some(Arc::new(providers::PluginProvider::load(Path::new(path))?)),
+        None => None,
+    };
+
+    // Dropping the server at the end of the loop shuts it down
+    let live = serve.map(|_| report::LiveReport::default());
+    let _server = match (serve, &live) {
+        (Some(port), Some(live)) => {
+            let server = report::ReportServer::bind(port, live.clone()).await?;
+            println!(
+                "  {} {}",
+                "📊".bright_cyan(),
+                format!("Serving the live report at http://{}", server.addr()).bold()
+            );
+            Some(server)
+        }
+        _ => None,
+    };
+
+    // Results of the previous cycle, for --only-changed-cases
+    let mut prior = only_changed_cases.then(runner::PriorResults::default);
+
     // Initial run
     run_cycle(
         file,
         json,
+        compact,
         upload,
         _token.clone(),
         concurrency,
+        plugin.as_ref(),
         timeout,
         update_snapshots,
+        snapshot_dir.clone(),
         no_validate,
+        &validate,
         filter.clone(),
         report_path.clone(),
         verbosity,
+        profile.clone(),
+        live.as_ref(),
+        prior.as_mut(),
     )
     .await;
 
@@ -57,7 +100,9 @@ pub async fn run_watch_loop(
     let debounce_interval = Duration::from_millis(500);
 
     loop {
-        match rx.recv() {
+        // Hand this worker's queue to another thread while we wait, so
+        // spawned tasks (like the report server) keep running
+        match tokio::task::block_in_place(|| rx.recv()) {
             Ok(Ok(Event { .. })) => {
                 if last_run.elapsed() < debounce_interval {
                     continue;
@@ -76,15 +121,22 @@ pub async fn run_watch_loop(
                 run_cycle(
                     file,
                     json,
+                    compact,
                     upload,
                     _token.clone(),
                     concurrency,
+                    plugin.as_ref(),
                     timeout,
                     update_snapshots,
+                    snapshot_dir.clone(),
                     no_validate,
+                    &validate,
                     filter.clone(),
                     report_path.clone(),
                     verbosity,
+                    profile.clone(),
+                    live.as_ref(),
+                    prior.as_mut(),
                 )
                 .await;
             }
@@ -96,21 +148,29 @@ pub async fn run_watch_loop(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_cycle(
     file: &str,
     json: bool,
+    compact: bool,
     upload: bool,
     _token: Option<String>,
-    concurrency: usize,
+    concurrency: Option<usize>,
+    plugin: Option<&Arc<dyn providers::LlmProvider>>,
     timeout: u64,
     update_snapshots: bool,
+    snapshot_dir: Option<String>,
     no_validate: bool,
+    validate: &config::ValidateOptions,
     filter: Option<String>,
     report_path: Option<Option<String>>,
     verbosity: Verbosity,
+    profile: Option<String>,
+    live: Option<&report::LiveReport>,
+    mut prior: Option<&mut runner::PriorResults>,
 ) {
     // 1. Load config (hande errors gracefully so we don't crash watcher)
-    let cfg = match config::load_config(file) {
+    let mut cfg = match config::load_config(file) {
         Ok(cfg) => cfg,
         Err(e) => {
             println!("\n  {} Failed to load config:\n  {}", "✗".red().bold(), e);
@@ -118,30 +178,62 @@ async fn run_cycle(
         }
     };
 
+    let profile_concurrency = match profile {
+        Some(ref name) => match config::apply_profile(&mut cfg, name) {
+            Ok(c) => c,
+            Err(e) => {
+                println!("\n  {} {}", "✗".red().bold(), e);
+                return;
+            }
+        },
+        None => None,
+    };
+    let concurrency = concurrency
+        .or(profile_concurrency)
+        .unwrap_or(runner::DEFAULT_CONCURRENCY);
+    if let Err(e) = config::resolve_provider(&mut cfg) {
+        println!("\n  {} {}", "✗".red().bold(), e);
+        return;
+    }
+
     // 2. Validate
     if !no_validate {
-        let issues = config::validate_config(&cfg);
+        let issues = config::validate_config_with(&cfg, validate);
+        let has_errors = issues.iter().any(|i| i.is_error());
         if !issues.is_empty() {
-            println!("\n  {} Config issues:", "✗".red().bold());
+            let icon = if has_errors {
+                "✗".red().bold()
+            } else {
+                "⚠".yellow().bold()
+            };
+            println!("\n  {} Config issues:", icon);
             for issue in &issues {
-                println!("    {} {}", "•".red(), issue);
+                if issue.is_error() {
+                    println!("    {} {}", "•".red(), issue);
+                } else {
+                    println!("    {} {}", "⚠".yellow(), issue);
+                }
+            }
+            // Warnings are worth seeing on every save, but not worth stopping for
+            if has_errors {
+                return;
             }
-            return;
         }
     }
 
     // 3. Provider
-    let provider = match providers::create_provider(&cfg.defaults.provider) {
-        Ok(p) => Arc::from(p),
-        Err(e) => {
-            println!("\n  {} Provider error: {}", "✗".red().bold(), e);
-            return;
-        }
+    let provider = match plugin {
+        Some(plugin) => Arc::clone(plugin),
+        None => match providers::create_provider(&cfg.defaults.provider) {
+            Ok(p) => Arc::from(p),
+            Err(e) => {
+                println!("\n  {} Provider error: {}", "✗".red().bold(), e);
+                return;
+            }
+        },
     };
 
     // 4. Run
-    let filter_ref = filter.as_deref();
-
     // Header for watch mode clarity
     if !json && verbosity != Verbosity::Quiet {
         let all_tests: usize = cfg.tests.iter().map(|t| t.cases.len()).sum();
@@ -152,31 +244,50 @@ async fn run_cycle(
         );
     }
 
-    let results = runner::run_all_tests(
-        &cfg,
-        provider,
+    // Hand the previous cycle's results to the runner; they're replaced by
+    // this cycle's once it finishes
+    let prior_run = prior.as_mut().map(|p| Arc::new(std::mem::take(&mut **p)));
+    let options = runner::RunOptions {
         concurrency,
-        verbosity,
-        json,
-        update_snapshots,
-        timeout,
-        filter_ref,
-    )
-    .await;
+        timeout_ms: timeout,
+        snapshot_mode: if update_snapshots {
+            SnapshotMode::Update
+        } else {
+            SnapshotMode::Check
+        },
+        snapshot_dir: snapshot_dir.map(std::path::PathBuf::from),
+        filter: filter.clone(),
+        show_progress: !json && verbosity != Verbosity::Quiet,
+        prior: prior_run,
+        ..Default::default()
+    };
+    let results = runner::run_all_tests(&cfg, provider, &options).await;
+    if let Some(prior) = prior {
+        *prior = runner::PriorResults::from_results(&results);
+    }
 
     // 5. Print
     if json {
-        if let Ok(json_output) = serde_json::to_string_pretty(&results) {
-            println!("{}", json_output);
+        let manifest = report::RunManifest::new(&cfg, file);
+        match report::to_json(&report::RunReport::new(&manifest, &results), compact) {
+            Ok(json_output) => println!("{}", json_output),
+            Err(e) => println!("  {} JSON error: {}", "⚠".yellow(), e),
         }
     } else {
-        runner::print_results(&results, verbosity);
+        let print_options = runner::PrintOptions {
+            verbosity,
+            budget_overages: runner::budget_overages(&cfg, &results),
+            max_failures_shown: runner::DEFAULT_MAX_FAILURES_SHOWN,
+            group_failures: false,
+            ..Default::default()
+        };
+        runner::print_results(&results, &print_options);
     }
 
     // 6. Report
     if let Some(report_path) = report_path {
         let path = report_path.unwrap_or_else(|| "report.html".to_string());
-        match report::generate_report(&results, Path::new(&path)) {
+        match report::generate_report(&results, Path::new(&path), &Default::default()) {
             Ok(generated) => {
                 if !json {
                     println!(
@@ -189,6 +300,13 @@ async fn run_cycle(
             Err(e) => println!("  {} Report error: {}", "⚠".yellow(), e),
         }
     }
+    if let Some(live) = live {
+        let options = report::ReportOptions {
+            auto_refresh: Some(LIVE_REFRESH_SECS),
+            ..Default::default()
+        };
+        live.update(report::render_report(&results, &options));
+    }
 
     // 7. Upload
     // Should we upload on every watch cycle? Probably not, or only if requested.
@@ -1,4 +1,6 @@
-use prompt_sentinel::config::{load_config, validate_config};
+use prompt_sentinel::config::{
+    load_config, load_config_with, validate_config, AssertionType, LoadOptions,
+};
 use std::io::Write;
 use tempfile::NamedTempFile;
 
@@ -52,12 +54,8 @@ tests:
     // Templated assertion should be rendered
     // Wait, render_assertions renders AT LOAD TIME based on input vars.
     // So "value" should be "Hello Alice"
-    if let prompt_sentinel::config::AssertionKind::Contains(val) =
-        prompt_sentinel::config::AssertionKind::from_raw(
-            &case1.assertions[0].kind,
-            &case1.assertions[0].value,
-        )
-        .unwrap()
+    if let prompt_sentinel::config::AssertionKind::Contains { value: val, .. } =
+        case1.assertions[0].parse().unwrap()
     {
         assert_eq!(val, "Hello Alice");
     } else {
@@ -67,15 +65,488 @@ tests:
     // Row 2: Bob
     let case2 = &test.cases[1];
     assert_eq!(case2.input.get("name").map(|s| s.as_str()), Some("Bob"));
-    if let prompt_sentinel::config::AssertionKind::Contains(val) =
-        prompt_sentinel::config::AssertionKind::from_raw(
-            &case2.assertions[0].kind,
-            &case2.assertions[0].value,
-        )
-        .unwrap()
+    if let prompt_sentinel::config::AssertionKind::Contains { value: val, .. } =
+        case2.assertions[0].parse().unwrap()
     {
         assert_eq!(val, "Hello Bob");
     } else {
         panic!("Wrong assertion kind");
     }
 }
+
+#[test]
+fn test_jsonl_loading() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("cases.jsonl"),
+        "{\"name\": \"Alice\", \"age\": 30}\n\n{\"name\": \"Bob\", \"age\": 41}\n",
+    )
+    .unwrap();
+
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "jsonl-test"
+    prompt: "Say hello to {{name}}"
+    cases_file: "cases.jsonl"
+    assertions:
+      - type: "contains"
+        value: "{{name}}"
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+
+    let cfg = load_config(config_path.to_str().unwrap()).unwrap();
+    let test = &cfg.tests[0];
+    assert_eq!(test.cases.len(), 2);
+    assert_eq!(test.cases[1].input.get("name").unwrap(), "Bob");
+    // Non-string JSON values are stringified
+    assert_eq!(test.cases[1].input.get("age").unwrap(), "41");
+    assert!(matches!(
+        &test.cases[1].assertions[0].check,
+        AssertionType::Contains { value, .. } if value == "Bob"
+    ));
+}
+
+#[test]
+fn test_streamed_cases_are_not_loaded_up_front() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("rows.csv"), "name\nAlice\nBob\n").unwrap();
+
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "stream-test"
+    prompt: "Say hello to {{name}}"
+    cases_file: "rows.csv"
+    stream: true
+    assertions:
+      - type: "contains"
+        value: "{{name}}"
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+
+    let cfg = load_config(config_path.to_str().unwrap()).unwrap();
+    let test = &cfg.tests[0];
+    assert!(test.cases.is_empty());
+    assert_eq!(
+        test.cases_path.as_deref(),
+        Some(dir.path().join("rows.csv").as_path())
+    );
+    assert!(validate_config(&cfg).is_empty());
+}
+
+#[test]
+fn test_assertion_sets_apply_to_csv_rows() {
+    let mut csv_file = NamedTempFile::new().unwrap();
+    writeln!(csv_file, "name").unwrap();
+    writeln!(csv_file, "Alice").unwrap();
+    writeln!(csv_file, "Bob").unwrap();
+
+    let yaml = format!(
+        r#"
+version: "1.0"
+assertion_sets:
+  greets:
+    - type: "contains"
+      value: "Hello {{{{name}}}}"
+tests:
+  - id: "csv-sets"
+    prompt: "Say hello to {{{{name}}}}"
+    cases_file: "{}"
+    use: [greets]
+"#,
+        csv_file.path().to_str().unwrap()
+    );
+    let mut config_file = NamedTempFile::new().unwrap();
+    write!(config_file, "{}", yaml).unwrap();
+
+    let cfg = load_config(config_file.path().to_str().unwrap()).unwrap();
+    assert!(validate_config(&cfg).is_empty());
+
+    let cases = &cfg.tests[0].cases;
+    assert_eq!(cases.len(), 2);
+    assert_eq!(cases[1].assertions.len(), 1);
+    assert!(matches!(
+        &cases[1].assertions[0].check,
+        AssertionType::Contains { value, .. } if value == "Hello Bob"
+    ));
+}
+
+#[test]
+fn test_tsv_with_custom_quote() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("rows.tsv"),
+        "name\tgreeting\nAlice\t'Hi, \"Al\"\tthere'\nBob\tHey\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("bare.tsv"), "Carol\tYo\n").unwrap();
+
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "tsv"
+    prompt: "{{greeting}} {{name}}"
+    cases_file: "rows.tsv"
+    cases_file_options: { delimiter: "\t", quote: "'" }
+  - id: "no-headers"
+    prompt: "{{col2}} {{col1}}"
+    cases_file: "bare.tsv"
+    cases_file_options: { delimiter: "\t", has_headers: false }
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+
+    let cfg = load_config(config_path.to_str().unwrap()).unwrap();
+    assert!(cfg.unknown_fields.is_empty(), "{:?}", cfg.unknown_fields);
+    let cases = &cfg.tests[0].cases;
+    assert_eq!(cases.len(), 2);
+    assert_eq!(cases[0].input["name"], "Alice");
+    // The quoted field keeps its tab and double quotes
+    assert_eq!(cases[0].input["greeting"], "Hi, \"Al\"\tthere");
+    assert_eq!(cases[1].input["greeting"], "Hey");
+
+    // Without a header row the first row is data
+    let cases = &cfg.tests[1].cases;
+    assert_eq!(cases.len(), 1);
+    assert_eq!(cases[0].input["col1"], "Carol");
+    assert_eq!(cases[0].input["col2"], "Yo");
+}
+
+#[test]
+fn test_csv_delimiter_must_be_ascii() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("rows.csv"), "name\nAlice\n").unwrap();
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "csv"
+    prompt: "Hi {{name}}"
+    cases_file: "rows.csv"
+    cases_file_options: { delimiter: "→" }
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+
+    let err = load_config(config_path.to_str().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("cases_file_options.delimiter must be a single ASCII character"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_cases_file_row_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("rows.csv"), "name\nAlice\nBob\nCarol\n").unwrap();
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "big"
+    prompt: "Hi {{name}}"
+    cases_file: "rows.csv"
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+    let path = config_path.to_str().unwrap();
+
+    let err = load_config_with(path, &LoadOptions { max_cases: 2 })
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("cases_file 'rows.csv' exceeded 2 rows; use --max-cases to raise"),
+        "{}",
+        err
+    );
+
+    let cfg = load_config_with(path, &LoadOptions { max_cases: 3 }).unwrap();
+    assert_eq!(cfg.tests[0].cases.len(), 3);
+}
+
+#[test]
+fn test_csv_rows_with_wrong_field_count_are_listed() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("rows.csv"),
+        "name,expected\nAlice,Hi Alice\nBob,Hi,Bob\nCarol,Hi Carol\nDave\n",
+    )
+    .unwrap();
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "ragged"
+    prompt: "Hi {{name}}"
+    cases_file: "rows.csv"
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+
+    let err = load_config(config_path.to_str().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Test 'ragged': 2 malformed rows"), "{}", err);
+    assert!(err.contains("line 3 has 3 fields, expected 2"), "{}", err);
+    assert!(err.contains("line 5 has 1 fields, expected 2"), "{}", err);
+}
+
+#[test]
+fn test_csv_missing_prompt_column_is_named() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("rows.csv"), "name,expected\nAlice,Hi\n").unwrap();
+
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "csv-missing"
+    prompt: "Greet {{name}} in {{language}} as a {{tone}} {{language}} speaker"
+    cases_file: "rows.csv"
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+
+    let err = load_config(config_path.to_str().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("csv-missing"), "{}", err);
+    assert!(err.contains("{{language}}, {{tone}}"), "{}", err);
+    assert!(!err.contains("{{name}}"), "{}", err);
+    assert!(err.contains("rows.csv"), "{}", err);
+    assert!(err.contains("columns: name, expected"), "{}", err);
+}
+
+#[test]
+fn test_expected_column_assertion() {
+    use prompt_sentinel::assertions::{check_assertion, AssertionContext, SnapshotMode};
+    use prompt_sentinel::providers::TokenUsage;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("rows.csv"),
+        "city,expected\nParis,France\nLyon,France\n",
+    )
+    .unwrap();
+
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "capitals"
+    prompt: "Which country is {{city}} in?"
+    cases_file: "rows.csv"
+    assertions:
+      - type: "expected"
+        value: true
+      - type: "expected"
+        value: { match: contains }
+      - type: "expected"
+        value: { match: contains, case_sensitive: true }
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+    let cfg = load_config(config_path.to_str().unwrap()).unwrap();
+    assert!(validate_config(&cfg).is_empty());
+
+    let case = &cfg.tests[0].cases[0];
+    let check_with_default = |index: usize, output: &str, case_sensitive: bool| {
+        let mut kind = case.assertions[index].parse().unwrap();
+        kind.default_case_sensitive(case_sensitive);
+        check_assertion(
+            &kind,
+            output,
+            &AssertionContext {
+                latency_ms: 10,
+                snapshot_key: "capitals_case0",
+                snapshot_dir: dir.path(),
+                snapshot_base: None,
+                snapshot_mode: SnapshotMode::Check,
+                input: &case.input,
+                usage: &TokenUsage::default(),
+                timeout: None,
+            },
+        )
+    };
+    let check = |index: usize, output: &str| check_with_default(index, output, false);
+
+    // exact: whitespace-insensitive equality
+    assert!(check(0, "France\n").passed);
+    assert!(!check(0, "It is in France.").passed);
+    // contains: case-insensitive substring
+    assert!(check(1, "It is in france.").passed);
+    assert!(!check(1, "It is in Germany.").passed);
+    // ...unless the assertion or `defaults.case_sensitive_contains` says otherwise
+    assert!(!check(2, "It is in france.").passed);
+    assert!(check(2, "It is in France.").passed);
+    assert!(!check_with_default(1, "It is in france.", true).passed);
+
+    // A column the CSV doesn't have is caught at load time
+    let yaml = yaml.replace("{ match: contains }", "{ match: contains, column: answer }");
+    std::fs::write(&config_path, yaml).unwrap();
+    let err = load_config(config_path.to_str().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("column 'answer'"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_model_templated_from_csv_column() {
+    use prompt_sentinel::providers::OpenAiProvider;
+    use prompt_sentinel::runner::{run_all_tests, RunOptions};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "choices": [{"message": {"content": "ok"}}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+        })))
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("models.csv"),
+        "model,question\ngpt-4o-mini,Hi\ngpt-4o,Hello\n,Hey\n",
+    )
+    .unwrap();
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "matrix"
+    prompt: "{{question}}"
+    model: "{{model}}"
+    cases_file: "models.csv"
+    assertions:
+      - type: "contains"
+        value: "ok"
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+    let cfg = load_config(config_path.to_str().unwrap()).unwrap();
+
+    // The empty model in the last row is caught before the run...
+    let issues = validate_config(&cfg);
+    assert_eq!(issues.len(), 1, "{:?}", issues);
+    assert!(issues[0]
+        .message
+        .contains("case 3: model '{{model}}' resolves to ''"));
+
+    // ...and fails only its own case if run anyway
+    let provider = std::sync::Arc::new(OpenAiProvider::with_base_url(
+        "test-key".to_string(),
+        server.uri(),
+    ));
+    let options = RunOptions {
+        concurrency: 2,
+        timeout_ms: 5000,
+        ..Default::default()
+    };
+    let results = run_all_tests(&cfg, provider, &options).await;
+    let models: Vec<&str> = results.iter().map(|r| r.model.as_str()).collect();
+    assert_eq!(models, ["gpt-4o-mini", "gpt-4o", ""]);
+    assert!(results[0].passed && results[1].passed);
+    assert!(results[2]
+        .error
+        .as_deref()
+        .unwrap()
+        .contains("model resolved to ''"));
+
+    let requests = server.received_requests().await.unwrap();
+    let mut sent: Vec<String> = requests
+        .iter()
+        .map(|r| r.body_json::<serde_json::Value>().unwrap()["model"].to_string())
+        .collect();
+    sent.sort();
+    assert_eq!(sent, ["\"gpt-4o\"", "\"gpt-4o-mini\""]);
+
+    // A model column the CSV doesn't have is caught at load time
+    std::fs::write(&config_path, yaml.replace("{{model}}", "{{engine}}")).unwrap();
+    let err = load_config(config_path.to_str().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("no column for {{engine}}"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_input_filter_selects_csv_rows() {
+    use prompt_sentinel::providers::OpenAiProvider;
+    use prompt_sentinel::runner::{run_all_tests, RunOptions};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "choices": [{ "message": { "content": "ok" }, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 5, "completion_tokens": 1 }
+        })))
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("rows.csv"),
+        "lang,name\nen,Alice\nfr,Bob\nen,Carol\nfr,Dana\n",
+    )
+    .unwrap();
+    let yaml = r#"
+version: "1.0"
+tests:
+  - id: "greet"
+    prompt: "Greet {{name}} in {{lang}}"
+    cases:
+      - input: { lang: "fr", name: "Eve" }
+    cases_file: "rows.csv"
+    assertions:
+      - type: "contains"
+        value: "ok"
+"#;
+    let config_path = dir.path().join("tests.yaml");
+    std::fs::write(&config_path, yaml).unwrap();
+    let cfg = load_config(config_path.to_str().unwrap()).unwrap();
+
+    let provider = std::sync::Arc::new(OpenAiProvider::with_base_url(
+        "test-key".to_string(),
+        server.uri(),
+    ));
+    let run = |filter: &[(&str, &str)]| RunOptions {
+        timeout_ms: 5000,
+        input_filter: filter
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        ..Default::default()
+    };
+
+    // Inline cases and CSV rows are both filtered; every pair must match
+    let results = run_all_tests(&cfg, provider.clone(), &run(&[("lang", "fr")])).await;
+    let names: Vec<&str> = results.iter().map(|r| r.input["name"].as_str()).collect();
+    assert_eq!(names, ["Eve", "Bob", "Dana"]);
+
+    let results = run_all_tests(
+        &cfg,
+        provider.clone(),
+        &run(&[("lang", "en"), ("name", "Carol")]),
+    )
+    .await;
+    assert_eq!(results.len(), 1);
+    assert!(results[0].passed);
+    // Skipped rows don't shift the case's key (used for snapshots and request IDs)
+    assert!(results[0]
+        .request_id
+        .as_deref()
+        .unwrap()
+        .ends_with("-greet_case3-1"));
+
+    let results = run_all_tests(&cfg, provider.clone(), &run(&[("lang", "de")])).await;
+    assert!(results.is_empty());
+    let results = run_all_tests(&cfg, provider, &run(&[("missing", "")])).await;
+    assert!(results.is_empty());
+    assert_eq!(server.received_requests().await.unwrap().len(), 4);
+}